@@ -0,0 +1,436 @@
+//! Colored output, configured like Git does it.
+//!
+//! Colors are given in the same syntax as in Git's `color.*` config, for
+//! example `bold red` or `"#ff0000" black ul`, and whether to use them at all
+//! is decided by `--color`, `NO_COLOR` and `color.ui` / `color.<command>`.
+
+use std::io::IsTerminal;
+
+use gix::Repository;
+
+use crate::diffprinter::Segment;
+
+/// When to use colors, given by `--color=<when>`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Use colors if the output goes to a terminal
+    #[default]
+    Auto,
+    /// Always use colors
+    Always,
+    /// Never use colors
+    Never,
+}
+
+impl ColorChoice {
+    fn from_config(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "always" => Some(Self::Always),
+            "auto" | "true" | "yes" | "on" | "1" => Some(Self::Auto),
+            "never" | "false" | "no" | "off" | "0" => Some(Self::Never),
+            _ => None,
+        }
+    }
+
+    fn enabled(self) -> bool {
+        match self {
+            Self::Auto => std::io::stdout().is_terminal(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// Decide whether to use colors for the given command, e.g. `diff` or
+/// `blame`. The command line wins, then `NO_COLOR`, then `color.<command>`
+/// and `color.ui` in the repository's config.
+pub fn use_color(repo: &Repository, command: &str, choice: Option<ColorChoice>) -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let config = repo.config_snapshot();
+
+    resolve(command, choice, no_color, |key| {
+        config.string(key).map(|v| v.to_string())
+    })
+    .enabled()
+}
+
+/// The part of [`use_color()`] that doesn't depend on the environment.
+fn resolve(
+    command: &str,
+    choice: Option<ColorChoice>,
+    no_color: bool,
+    config: impl Fn(&str) -> Option<String>,
+) -> ColorChoice {
+    if let Some(choice) = choice {
+        return choice;
+    }
+
+    if no_color {
+        return ColorChoice::Never;
+    }
+
+    [format!("color.{command}"), "color.ui".to_owned()]
+        .iter()
+        .find_map(|key| config(key).and_then(|v| ColorChoice::from_config(&v)))
+        .unwrap_or_default()
+}
+
+/// An ANSI style. The default style is empty and paints nothing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Style(String);
+
+impl Style {
+    /// Parse a color in Git's syntax: up to two colors (foreground and
+    /// background) and any number of attributes, separated by whitespace.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut codes = vec![];
+        let mut colors = 0;
+
+        for word in value.split_whitespace() {
+            let word = word.to_ascii_lowercase();
+
+            if let Some(code) = attribute(&word) {
+                codes.push(code.to_owned());
+                continue;
+            }
+
+            let offset = match colors {
+                0 => 30,
+                1 => 40,
+                _ => return None,
+            };
+            colors += 1;
+
+            if let Some(code) = color(&word, offset) {
+                if !code.is_empty() {
+                    codes.push(code);
+                }
+            } else {
+                return None;
+            }
+        }
+
+        if codes.is_empty() {
+            Some(Self::default())
+        } else {
+            Some(Self(format!("\x1b[{}m", codes.join(";"))))
+        }
+    }
+
+    /// Read a style from the repository's config, falling back to `default`
+    /// if it isn't set or can't be parsed.
+    pub fn from_config(repo: &Repository, key: &str, default: &str) -> Self {
+        repo.config_snapshot()
+            .string(key)
+            .and_then(|v| Self::parse(&v.to_string()))
+            .unwrap_or_else(|| Self::parse(default).expect("valid default color"))
+    }
+
+    /// Wrap `text` in this style.
+    pub fn paint(&self, text: &str) -> String {
+        if self.0.is_empty() {
+            text.to_owned()
+        } else {
+            format!("{}{text}\x1b[m", self.0)
+        }
+    }
+}
+
+fn attribute(word: &str) -> Option<&'static str> {
+    Some(match word {
+        "bold" => "1",
+        "dim" => "2",
+        "italic" => "3",
+        "ul" | "underline" => "4",
+        "blink" => "5",
+        "reverse" => "7",
+        "strike" => "9",
+        "nobold" | "no-bold" | "nodim" | "no-dim" => "22",
+        "noitalic" | "no-italic" => "23",
+        "noul" | "no-ul" | "nounderline" | "no-underline" => "24",
+        "noblink" | "no-blink" => "25",
+        "noreverse" | "no-reverse" => "27",
+        "nostrike" | "no-strike" => "29",
+        _ => return None,
+    })
+}
+
+fn color(word: &str, offset: u8) -> Option<String> {
+    const NAMES: [&str; 8] = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+
+    if word == "normal" {
+        return Some(String::new());
+    }
+
+    if word == "default" {
+        return Some((offset + 9).to_string());
+    }
+
+    if let Some(i) = NAMES.iter().position(|n| *n == word) {
+        return Some((offset + i as u8).to_string());
+    }
+
+    if let Some(i) = word
+        .strip_prefix("bright")
+        .and_then(|w| NAMES.iter().position(|n| *n == w))
+    {
+        return Some((offset + 60 + i as u8).to_string());
+    }
+
+    if let Ok(n) = word.parse::<u8>() {
+        return Some(format!("{};5;{n}", offset + 8));
+    }
+
+    let hex = word.strip_prefix('#').filter(|h| h.len() == 6)?;
+    let rgb = (0..3)
+        .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+
+    Some(format!("{};2;{};{};{}", offset + 8, rgb[0], rgb[1], rgb[2]))
+}
+
+/// Styles for the different parts of a diff.
+#[derive(Debug, Default)]
+pub struct DiffPalette {
+    meta: Style,
+    frag: Style,
+    context: Style,
+    old: Style,
+    new: Style,
+}
+
+impl DiffPalette {
+    /// A palette without any colors
+    pub fn plain() -> Self {
+        Self::default()
+    }
+
+    /// Read `color.diff.*` from the repository's config, using Git's defaults
+    /// for anything that's missing.
+    pub fn from_config(repo: &Repository) -> Self {
+        Self {
+            meta: Style::from_config(repo, "color.diff.meta", "bold"),
+            frag: Style::from_config(repo, "color.diff.frag", "cyan"),
+            context: Style::from_config(repo, "color.diff.context", "normal"),
+            old: Style::from_config(repo, "color.diff.old", "red"),
+            new: Style::from_config(repo, "color.diff.new", "green"),
+        }
+    }
+
+    /// The style for the given part of a diff.
+    pub fn style(&self, segment: Segment) -> &Style {
+        match segment {
+            Segment::Meta => &self.meta,
+            Segment::Frag => &self.frag,
+            Segment::Context => &self.context,
+            Segment::Old => &self.old,
+            Segment::New => &self.new,
+        }
+    }
+}
+
+/// How to color the annotations of `blame`.
+#[derive(Debug, Default)]
+pub struct BlamePalette {
+    /// Colors by age, oldest first, each with the time before which it
+    /// applies. The last one has no limit.
+    by_age: Vec<(Style, Option<i64>)>,
+    /// Color for lines from the same commit as the previous line.
+    repeated: Option<Style>,
+}
+
+impl BlamePalette {
+    /// A palette without any colors
+    pub fn plain() -> Self {
+        Self::default()
+    }
+
+    /// Read `color.blame.highlightRecent` and `color.blame.repeatedLines` from
+    /// the repository's config, using Git's defaults if they're missing, and
+    /// enable the ones asked for.
+    pub fn from_config(repo: &Repository, by_age: bool, repeated_lines: bool) -> Self {
+        let now = std::time::SystemTime::now();
+
+        let by_age = if by_age {
+            let config = repo.config_snapshot();
+            config
+                .string("color.blame.highlightRecent")
+                .and_then(|v| parse_highlight_recent(&v.to_string(), now))
+                .or_else(|| {
+                    // Git's default is `blue,12 month ago,white,1 month ago,red`,
+                    // spelled in units `gix::date::parse()` understands
+                    parse_highlight_recent("blue,365 days ago,white,30 days ago,red", now)
+                })
+                .unwrap_or_default()
+        } else {
+            vec![]
+        };
+
+        let repeated =
+            repeated_lines.then(|| Style::from_config(repo, "color.blame.repeatedLines", "cyan"));
+
+        Self { by_age, repeated }
+    }
+
+    /// The style for the annotation of a line from a commit with the given
+    /// author time, like in Git. `repeated` says whether the previous line is
+    /// from the same commit.
+    pub fn style(&self, author_time: i64, repeated: bool) -> Style {
+        if repeated {
+            if let Some(style) = &self.repeated {
+                return style.clone();
+            }
+        }
+
+        self.by_age
+            .iter()
+            .find(|(_, limit)| match limit {
+                Some(limit) => author_time < *limit,
+                None => true,
+            })
+            .map(|(style, _)| style.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Parse `color.blame.highlightRecent`, a list of colors separated by dates
+/// like `blue,12 month ago,white,1 month ago,red`.
+fn parse_highlight_recent(
+    value: &str,
+    now: std::time::SystemTime,
+) -> Option<Vec<(Style, Option<i64>)>> {
+    let mut items = value.split(',').map(str::trim);
+    let mut colors = vec![];
+
+    loop {
+        let style = Style::parse(items.next()?)?;
+
+        match items.next() {
+            Some(date) => {
+                let time = gix::date::parse(date, Some(now)).ok()?;
+                colors.push((style, Some(time.seconds)));
+            }
+            None => {
+                colors.push((style, None));
+                return Some(colors);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn config<'a>(entries: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |key: &str| {
+            entries
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn command_line_wins() {
+        let entries = [("color.diff", "never"), ("color.ui", "never")];
+
+        assert_eq!(
+            resolve("diff", Some(ColorChoice::Always), true, config(&entries)),
+            ColorChoice::Always
+        );
+        assert_eq!(
+            resolve("diff", Some(ColorChoice::Never), false, config(&[])),
+            ColorChoice::Never
+        );
+    }
+
+    #[test]
+    fn no_color_beats_config() {
+        let entries = [("color.diff", "always")];
+
+        assert_eq!(
+            resolve("diff", None, true, config(&entries)),
+            ColorChoice::Never
+        );
+        assert_eq!(
+            resolve("diff", None, false, config(&entries)),
+            ColorChoice::Always
+        );
+    }
+
+    #[test]
+    fn command_config_beats_ui() {
+        let entries = [("color.blame", "false"), ("color.ui", "always")];
+
+        assert_eq!(
+            resolve("blame", None, false, config(&entries)),
+            ColorChoice::Never
+        );
+        assert_eq!(
+            resolve("diff", None, false, config(&entries)),
+            ColorChoice::Always
+        );
+        assert_eq!(resolve("diff", None, false, config(&[])), ColorChoice::Auto);
+    }
+
+    #[test]
+    fn invalid_config_is_ignored() {
+        let entries = [("color.diff", "sometimes"), ("color.ui", "off")];
+
+        assert_eq!(
+            resolve("diff", None, false, config(&entries)),
+            ColorChoice::Never
+        );
+    }
+
+    #[test]
+    fn parses_styles() {
+        assert_eq!(Style::parse("normal"), Some(Style::default()));
+        assert_eq!(Style::parse("bold red"), Some(Style("\x1b[1;31m".into())));
+        assert_eq!(
+            Style::parse("brightgreen blue ul"),
+            Some(Style("\x1b[92;44;4m".into()))
+        );
+        assert_eq!(
+            Style::parse("#ff0080 default"),
+            Some(Style("\x1b[38;2;255;0;128;49m".into()))
+        );
+        assert_eq!(Style::parse("208"), Some(Style("\x1b[38;5;208m".into())));
+        assert_eq!(Style::parse("red green blue"), None);
+        assert_eq!(Style::parse("reddish"), None);
+        assert_eq!(Style::parse("red").unwrap().paint("x"), "\x1b[31mx\x1b[m");
+        assert_eq!(Style::default().paint("x"), "x");
+    }
+
+    #[test]
+    fn colors_blame_by_age() {
+        let day = 24 * 60 * 60;
+        let now = 1000 * day;
+        let system_now = std::time::UNIX_EPOCH + std::time::Duration::from_secs(now as u64);
+
+        let palette = BlamePalette {
+            by_age: parse_highlight_recent("blue,1 week ago,white,1 day ago,red", system_now)
+                .unwrap(),
+            repeated: Some(Style::parse("cyan").unwrap()),
+        };
+
+        let blue = Style::parse("blue").unwrap();
+        let white = Style::parse("white").unwrap();
+        let red = Style::parse("red").unwrap();
+
+        assert_eq!(palette.style(now - 30 * day, false), blue);
+        assert_eq!(palette.style(now - 3 * day, false), white);
+        assert_eq!(palette.style(now, false), red);
+        assert_eq!(palette.style(now, true), Style::parse("cyan").unwrap());
+
+        assert_eq!(
+            parse_highlight_recent("blue,yesterday,red", system_now),
+            None
+        );
+    }
+}
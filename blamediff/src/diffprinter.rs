@@ -1,12 +1,49 @@
-use std::fmt::{Display, Write};
+use std::fmt::Display;
 use std::hash::Hash;
 use std::ops::Range;
 
 use gix::diff::blob::intern::{InternedInput, Interner, Token};
 use gix::diff::blob::Sink;
 
+use crate::color::DiffPalette;
+
+/// The different parts of a diff that can be styled differently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// File headers, like `diff --git` and `+++ b/foo`
+    Meta,
+    /// Hunk headers, like `@@ -1,3 +1,4 @@`
+    Frag,
+    /// Unchanged lines
+    Context,
+    /// Removed lines
+    Old,
+    /// Added lines
+    New,
+}
+
+/// A diff as a list of lines, each tagged with the part of the diff it belongs
+/// to. The lines don't contain the trailing newline.
+#[derive(Debug, Default)]
+pub struct StyledDiff(pub Vec<(Segment, String)>);
+
+impl StyledDiff {
+    /// Render the diff with the styles from the given palette.
+    pub fn paint(&self, palette: &DiffPalette) -> String {
+        let mut out = String::new();
+
+        for (segment, line) in &self.0 {
+            out.push_str(&palette.style(*segment).paint(line));
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
 /// A [`Sink`](crate::sink::Sink) that creates a textual diff
-/// in the format typically output by git or gnu-diff if the `-u` option is used
+/// in the format typically output by git or gnu-diff if the `-u` option is used.
+/// The lines are tagged with a [`Segment`] so they can be colored.
 pub struct UnifiedDiffBuilder<'a, T>
 where
     T: Hash + Eq + Display,
@@ -21,8 +58,8 @@ where
     before_hunk_len: u32,
     after_hunk_len: u32,
 
-    buffer: String,
-    dst: String,
+    buffer: Vec<(Segment, String)>,
+    dst: Vec<(Segment, String)>,
 
     before_blob: crate::BlobData<'a>,
     after_blob: crate::BlobData<'a>,
@@ -33,7 +70,7 @@ where
     T: Hash + Eq + Display,
 {
     /// Create a new `UnifiedDiffBuilder` for the given `input`,
-    /// that will return a [`String`](std::string::String).
+    /// that will return a [`StyledDiff`].
     pub fn new(
         input: &'a InternedInput<T>,
         old_blob: crate::BlobData<'a>,
@@ -44,8 +81,8 @@ where
             after_hunk_start: 0,
             before_hunk_len: 0,
             after_hunk_len: 0,
-            buffer: String::with_capacity(8),
-            dst: String::new(),
+            buffer: Vec::with_capacity(8),
+            dst: Vec::new(),
             interner: &input.interner,
             before: &input.before,
             after: &input.after,
@@ -56,8 +93,15 @@ where
     }
 
     fn print_tokens(&mut self, tokens: &[Token], prefix: char) {
+        let segment = match prefix {
+            '-' => Segment::Old,
+            '+' => Segment::New,
+            _ => Segment::Context,
+        };
+
         for &token in tokens {
-            writeln!(&mut self.buffer, "{prefix}{}", self.interner[token]).unwrap();
+            self.buffer
+                .push((segment, format!("{prefix}{}", self.interner[token])));
         }
     }
 
@@ -69,22 +113,37 @@ where
         let end = (self.pos + 3).min(self.before.len() as u32);
         self.update_pos(end, end);
 
-        writeln!(
-            &mut self.dst,
-            "diff --git a/{0} b/{1}\nindex {2}..{3} 100644\n--- a/{0}\n+++ b/{1}\n@@ -{4},{5} +{6},{7} @@",
-            self.before_blob.path,
-            self.after_blob.path,
-            self.before_blob.id.to_hex_with_len(7),
-            self.after_blob.id.to_hex_with_len(7),
-            self.before_hunk_start + 1,
-            self.before_hunk_len,
-            self.after_hunk_start + 1,
-            self.after_hunk_len,
-        )
-        .unwrap();
-
-        write!(&mut self.dst, "{}", &self.buffer).unwrap();
-        self.buffer.clear();
+        let before = &self.before_blob;
+        let after = &self.after_blob;
+
+        self.dst.extend([
+            (
+                Segment::Meta,
+                format!("diff --git a/{} b/{}", before.path, after.path),
+            ),
+            (
+                Segment::Meta,
+                format!(
+                    "index {}..{} 100644",
+                    before.id.to_hex_with_len(7),
+                    after.id.to_hex_with_len(7)
+                ),
+            ),
+            (Segment::Meta, format!("--- a/{}", before.path)),
+            (Segment::Meta, format!("+++ b/{}", after.path)),
+            (
+                Segment::Frag,
+                format!(
+                    "@@ -{},{} +{},{} @@",
+                    self.before_hunk_start + 1,
+                    self.before_hunk_len,
+                    self.after_hunk_start + 1,
+                    self.after_hunk_len,
+                ),
+            ),
+        ]);
+
+        self.dst.append(&mut self.buffer);
         self.before_hunk_len = 0;
         self.after_hunk_len = 0
     }
@@ -102,7 +161,7 @@ impl<T> Sink for UnifiedDiffBuilder<'_, T>
 where
     T: Hash + Eq + Display,
 {
-    type Out = String;
+    type Out = StyledDiff;
 
     fn process_change(&mut self, before: Range<u32>, after: Range<u32>) {
         if before.start - self.pos > 6 {
@@ -123,6 +182,6 @@ where
 
     fn finish(mut self) -> Self::Out {
         self.flush();
-        self.dst
+        StyledDiff(self.dst)
     }
 }
@@ -0,0 +1,253 @@
+//! Rendering of an ASCII commit graph, like `git log --graph`.
+//!
+//! The [`Graph`] is fed commits in topological order, for example from
+//! [`topo::Walk`], and keeps track of which lines of history ("lanes") are
+//! waiting for which commit. For each commit it returns the lines to draw.
+
+use gix::ObjectId;
+
+/// The lines to draw for a single commit.
+#[derive(Debug, Default, PartialEq)]
+pub struct Rendering {
+    /// The prefix for the first line of the commit's text. Contains the `*`.
+    pub commit_line: String,
+    /// The prefix for the remaining lines of the commit's text.
+    pub padding: String,
+    /// Lines that connect this commit to whatever comes next, drawn after the
+    /// commit's text.
+    pub edges: Vec<String>,
+}
+
+/// State of an ASCII commit graph.
+#[derive(Debug, Default)]
+pub struct Graph {
+    /// Each lane waits for the commit with the given id.
+    lanes: Vec<ObjectId>,
+}
+
+impl Graph {
+    /// Create a new, empty graph.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next commit to the graph. Commits must be given in topological
+    /// order, ie. no parent before all of its children.
+    pub fn next_commit(&mut self, id: ObjectId, parent_ids: &[ObjectId]) -> Rendering {
+        let idx = match self.lanes.iter().position(|l| *l == id) {
+            Some(idx) => idx,
+            None => {
+                self.lanes.push(id);
+                self.lanes.len() - 1
+            }
+        };
+
+        let commit_line = self.draw(|i| if i == idx { '*' } else { '|' });
+
+        let padding = if parent_ids.is_empty() {
+            self.draw(|i| if i == idx { ' ' } else { '|' })
+        } else {
+            self.draw(|_| '|')
+        };
+
+        let edges = self.replace_lane(idx, parent_ids);
+
+        Rendering {
+            commit_line,
+            padding,
+            edges,
+        }
+    }
+
+    /// Let lane `idx` wait for the given parents instead, and merge lanes
+    /// that end up waiting for the same commit.
+    fn replace_lane(&mut self, idx: usize, parent_ids: &[ObjectId]) -> Vec<String> {
+        let mut edges = vec![];
+
+        match parent_ids.split_first() {
+            Some((first, rest)) => {
+                self.lanes[idx] = *first;
+
+                for (n, parent_id) in rest.iter().enumerate() {
+                    edges.push(self.draw_expansion(idx + n));
+                    self.lanes.insert(idx + n + 1, *parent_id);
+                }
+            }
+            None => {
+                if idx + 1 < self.lanes.len() {
+                    edges.push(self.draw_removal(idx));
+                }
+                self.lanes.remove(idx);
+            }
+        }
+
+        while let Some((target, lane)) = self.find_duplicate() {
+            edges.push(self.draw_collapse(target, lane));
+            self.lanes.remove(lane);
+        }
+
+        edges
+    }
+
+    /// Find the first lane that waits for the same commit as a lane to its
+    /// left. Returns the index of the leftmost lane and of the duplicate.
+    fn find_duplicate(&self) -> Option<(usize, usize)> {
+        self.lanes.iter().enumerate().find_map(|(lane, id)| {
+            self.lanes[..lane]
+                .iter()
+                .position(|other| other == id)
+                .map(|target| (target, lane))
+        })
+    }
+
+    /// Draw one character per lane, separated by spaces.
+    fn draw(&self, mut f: impl FnMut(usize) -> char) -> String {
+        let mut line = String::with_capacity(self.lanes.len() * 2);
+
+        for i in 0..self.lanes.len() {
+            if i > 0 {
+                line.push(' ');
+            }
+            line.push(f(i));
+        }
+
+        line
+    }
+
+    /// Draw a new lane branching off to the right of lane `idx`, pushing all
+    /// lanes to its right one step to the right.
+    fn draw_expansion(&self, idx: usize) -> String {
+        let mut line = vec![' '; self.lanes.len() * 2 + 1];
+
+        for i in 0..self.lanes.len() {
+            if i <= idx {
+                line[i * 2] = '|';
+            } else {
+                line[i * 2 + 1] = '\\';
+            }
+        }
+        line[idx * 2 + 1] = '\\';
+
+        finish(line)
+    }
+
+    /// Draw the end of lane `idx`, pulling all lanes to its right one step to
+    /// the left.
+    fn draw_removal(&self, idx: usize) -> String {
+        let mut line = vec![' '; self.lanes.len() * 2];
+
+        for i in 0..self.lanes.len() {
+            if i < idx {
+                line[i * 2] = '|';
+            } else if i > idx {
+                line[i * 2 - 1] = '/';
+            }
+        }
+
+        finish(line)
+    }
+
+    /// Draw lane `lane` merging into lane `target` to its left, pulling all
+    /// lanes to the right of `lane` one step to the left.
+    fn draw_collapse(&self, target: usize, lane: usize) -> String {
+        let mut line = vec![' '; self.lanes.len() * 2];
+
+        for i in 0..self.lanes.len() {
+            if i < lane {
+                line[i * 2] = '|';
+            } else {
+                line[i * 2 - 1] = '/';
+            }
+        }
+
+        for c in &mut line[target * 2 + 1..lane * 2 - 1] {
+            if *c == ' ' {
+                *c = '_';
+            }
+        }
+
+        finish(line)
+    }
+}
+
+fn finish(line: Vec<char>) -> String {
+    line.into_iter().collect::<String>().trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn id(n: u8) -> ObjectId {
+        ObjectId::from_str(&format!("{n:040x}")).unwrap()
+    }
+
+    fn render(commits: &[(u8, &[u8])]) -> Vec<String> {
+        let mut graph = Graph::new();
+        let mut lines = vec![];
+
+        for (c, parents) in commits {
+            let parents = parents.iter().map(|p| id(*p)).collect::<Vec<_>>();
+            let r = graph.next_commit(id(*c), &parents);
+            lines.push(r.commit_line);
+            lines.extend(r.edges);
+        }
+
+        lines
+    }
+
+    #[test]
+    fn linear() {
+        assert_eq!(render(&[(3, &[2]), (2, &[1]), (1, &[])]), ["*", "*", "*"]);
+    }
+
+    #[test]
+    fn branch_and_merge() {
+        assert_eq!(
+            render(&[(4, &[3, 2]), (3, &[1]), (2, &[1]), (1, &[])]),
+            ["*", "|\\", "* |", "| *", "|/", "*"]
+        );
+    }
+
+    #[test]
+    fn octopus() {
+        assert_eq!(
+            render(&[(5, &[4, 3, 2]), (4, &[1]), (3, &[1]), (2, &[1]), (1, &[])]),
+            ["*", "|\\", "| |\\", "* | |", "| * |", "|/ /", "| *", "|/", "*"]
+        );
+    }
+
+    #[test]
+    fn two_roots() {
+        assert_eq!(
+            render(&[(3, &[2, 1]), (2, &[]), (1, &[])]),
+            ["*", "|\\", "* |", " /", "*"]
+        );
+    }
+
+    #[test]
+    fn parallel_branches() {
+        assert_eq!(
+            render(&[(5, &[4]), (3, &[1]), (4, &[2]), (2, &[1]), (1, &[])]),
+            ["*", "| *", "* |", "* |", "|/", "*"]
+        );
+    }
+
+    #[test]
+    fn collapse_over_lane() {
+        assert_eq!(
+            render(&[
+                (9, &[8]),
+                (6, &[1]),
+                (7, &[2]),
+                (8, &[2]),
+                (2, &[1]),
+                (1, &[])
+            ]),
+            ["*", "| *", "| | *", "* | |", "|_|/", "* |", "|/", "*"]
+        );
+    }
+}
@@ -1,65 +1,394 @@
-#![allow(unused_must_use)]
-#![allow(dead_code)]
-#![allow(unused_imports)]
-
-use gix::{bstr, discover, hash, index, object, objs, Object, Repository};
-
-pub fn log(paths: &[std::path::PathBuf]) {
-    let path = &paths[0];
-    let repo = discover(".").unwrap();
-
-    let head = repo.rev_parse("HEAD").unwrap().single().unwrap();
-
-    let mut iter = repo
-        .rev_walk(std::iter::once(head))
-        .all()
-        .unwrap()
-        .peekable();
-
-    let mut buf = Vec::<u8>::new();
-    let mut buff = Vec::<u8>::new();
-
-    while let Some(c_id) = iter.next() {
-        let c_id = c_id.unwrap().id;
-
-        let c = repo
-            .find_object(c_id)
-            .unwrap()
-            .peel_to_kind(object::Kind::Commit)
-            .unwrap()
-            .into_commit();
-
-        let e = c
-            .tree()
-            .unwrap()
-            .lookup_entry_by_path(path, &mut buf)
-            .unwrap();
-
-        if let Some(e) = e {
-            if let Some(aa) = iter.peek() {
-                let aa = aa.as_ref().unwrap();
-
-                let cc = repo
-                    .find_object(aa.id)
-                    .unwrap()
-                    .peel_to_kind(object::Kind::Commit)
-                    .unwrap()
-                    .into_commit();
-
-                let ee = cc
-                    .tree()
-                    .unwrap()
-                    .lookup_entry_by_path(path, &mut buff)
-                    .unwrap();
-
-                if let Some(ee) = ee {
-                    if e.object_id() != ee.object_id() {
-                        println!("{} {}", c.id, c.message().unwrap().summary())
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use gix::{bstr::ByteSlice, revision::plumbing::Spec, ObjectId, Repository};
+use time::macros::format_description;
+
+use crate::graph::Graph;
+
+/// Options for [`log()`]
+#[derive(Default)]
+pub struct Options {
+    /// Sorting to use for the walk
+    pub sorting: topo::Sorting,
+    /// Whether or not to follow only the first parent
+    pub parents: topo::Parents,
+    /// Paths, relative to the root of the repository, to limit the output to
+    pub paths: Vec<PathBuf>,
+    /// How to simplify the history when limiting the output to paths
+    pub simplification: topo::Simplification,
+    /// Draw an ASCII graph of the history next to the output. Implies
+    /// [`topo::Sorting::TopoOrder`].
+    pub graph: bool,
+    /// Only show commits on the ancestry path of the excluded commits if
+    /// empty, or of the given commits. See [`topo::Builder::ancestry_path`].
+    pub ancestry_path: Option<Vec<ObjectId>>,
+    /// Show the oldest commits first. Can't be combined with `graph`.
+    pub reverse: bool,
+    /// Show at most this many commits
+    pub max_count: Option<usize>,
+    /// Skip this many commits before starting to show them
+    pub skip: usize,
+    /// Only show commits more recent than this, in seconds since the epoch
+    pub since: Option<i64>,
+    /// Only show commits older than this, in seconds since the epoch
+    pub until: Option<i64>,
+    /// Only show commits with at least this many parents
+    pub min_parents: usize,
+    /// Only show commits with at most this many parents
+    pub max_parents: Option<usize>,
+    /// Only show commits by authors matching any of these
+    pub authors: Vec<String>,
+    /// Only show commits by committers matching any of these
+    pub committers: Vec<String>,
+    /// Only show commits with messages matching any of these
+    pub grep: Vec<String>,
+    /// Only show commits with messages matching all of `grep`
+    pub all_match: bool,
+    /// Only show the commits on this side of a symmetric difference
+    pub only: Option<topo::Side>,
+    /// Mark which side of a symmetric difference commits are on
+    pub left_right: bool,
+    /// What to do with commits that have an equivalent on the other side of a
+    /// symmetric difference
+    pub cherry: Option<Cherry>,
+}
+
+/// How to treat commits on one side of a symmetric difference that make the
+/// same change as a commit on the other side, see [`topo::cherry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cherry {
+    /// Show them marked with `=`, and the others with `+`, like `git log
+    /// --cherry-mark`
+    Mark,
+    /// Leave them out, like `git log --cherry-pick`
+    Pick,
+}
+
+/// Write the history of the given revisions and ranges to `out`, like `git
+/// log`.
+pub fn log(
+    out: &mut impl Write,
+    repo: &Repository,
+    specs: Vec<Spec>,
+    options: Options,
+) -> anyhow::Result<()> {
+    let sorting = if options.graph {
+        topo::Sorting::TopoOrder
+    } else {
+        options.sorting
+    };
+
+    // Like Git, don't use the commit-graph with replace refs or in a shallow
+    // clone
+    let replace = topo::replace::Replace::at(&repo.objects, repo.common_dir())?;
+    let use_replace_refs = !replace.is_empty();
+    let shallow = topo::shallow::Shallow::at(replace, repo.git_dir())?;
+    let use_commit_graph = !use_replace_refs && !shallow.is_shallow();
+
+    let mut builder = topo::Builder::from_specs(shallow, specs)
+        .with_commit_graph(repo.commit_graph().ok().filter(|_| use_commit_graph))
+        .with_changed_paths(if options.paths.is_empty() || !use_commit_graph {
+            None
+        } else {
+            topo::bloom::ChangedPaths::at(repo.objects.store_ref().path().join("info")).ok()
+        })
+        .sorting(sorting)
+        .parents(options.parents)
+        .reverse(options.reverse)
+        .paths(options.paths.iter().map(|p| {
+            gix::path::to_unix_separators_on_windows(gix::path::into_bstr(p)).into_owned()
+        }))
+        .simplification(options.simplification)
+        .min_parents(options.min_parents)
+        .all_match(options.all_match);
+
+    // Commits are only left out by side or patch-id after the walk, so the
+    // walk can't count them
+    let filtered = options.only.is_some() || options.cherry.is_some();
+    if !filtered {
+        builder = builder.skip(options.skip);
+        if let Some(n) = options.max_count {
+            builder = builder.max_count(n);
+        }
+    }
+    if let Some(time) = options.since {
+        builder = builder.since(time);
+    }
+    if let Some(time) = options.until {
+        builder = builder.until(time);
+    }
+    if let Some(n) = options.max_parents {
+        builder = builder.max_parents(n);
+    }
+    for pattern in options.authors {
+        builder = builder.author(pattern);
+    }
+    for pattern in options.committers {
+        builder = builder.committer(pattern);
+    }
+    for pattern in options.grep {
+        builder = builder.grep(pattern);
+    }
+
+    match options.ancestry_path.as_deref() {
+        None => (),
+        Some([]) => builder = builder.ancestry_path(),
+        Some(commits) => {
+            for id in commits {
+                builder = builder.ancestry_path_through(*id);
+            }
+        }
+    }
+
+    let walk = builder.build()?;
+
+    let (infos, equivalent) = if filtered {
+        let infos = walk.collect::<Result<Vec<_>, _>>()?;
+        let equivalent = match options.cherry {
+            Some(_) => {
+                topo::cherry::equivalent(&infos, |info| crate::patch_id::patch_id(repo, info))?
+            }
+            None => Default::default(),
+        };
+
+        let infos = infos
+            .into_iter()
+            .filter(|info| options.only.map_or(true, |side| info.side == Some(side)))
+            .filter(|info| {
+                options.cherry != Some(Cherry::Pick) || !equivalent.contains_key(&info.id)
+            })
+            .skip(options.skip)
+            .take(options.max_count.unwrap_or(usize::MAX))
+            .collect::<Vec<_>>();
+
+        (
+            Box::new(infos.into_iter().map(Ok::<_, topo::Error>)) as Box<dyn Iterator<Item = _>>,
+            equivalent,
+        )
+    } else {
+        (
+            Box::new(walk) as Box<dyn Iterator<Item = _>>,
+            Default::default(),
+        )
+    };
+
+    let mut graph = options.graph.then(Graph::new);
+    let mut first = true;
+
+    for info in infos {
+        let info = info?;
+
+        match &mut graph {
+            Some(graph) => {
+                let rendering = graph.next_commit(info.id, &info.parent_ids);
+                let mut lines = header_lines(repo, &info)?.into_iter();
+
+                if let Some(line) = lines.next() {
+                    writeln!(out, "{} {line}", rendering.commit_line)?;
+                }
+                for line in lines.chain(std::iter::once(String::new())) {
+                    let line = format!("{} {line}", rendering.padding);
+                    writeln!(out, "{}", line.trim_end())?;
+                }
+                for edge in rendering.edges {
+                    writeln!(out, "{edge}")?;
+                }
+            }
+            None => {
+                if !first {
+                    writeln!(out)?;
+                }
+                first = false;
+
+                // Like Git, commits with an equivalent are marked with `=`
+                // even when the sides are marked
+                let mark = if equivalent.contains_key(&info.id) {
+                    Some('=')
+                } else if options.left_right {
+                    info.side.map(|side| match side {
+                        topo::Side::Left => '<',
+                        topo::Side::Right => '>',
+                    })
+                } else {
+                    (options.cherry == Some(Cherry::Mark)).then_some('+')
+                };
+
+                match mark {
+                    None => print_header(out, repo, &info)?,
+                    Some(mark) => {
+                        let mut lines = header_lines(repo, &info)?;
+                        lines[0] = format!("commit {mark} {}", info.id);
+                        for line in lines {
+                            writeln!(out, "{line}")?;
+                        }
                     }
                 }
-            } else {
-                println!("{} {}", c.id, c.message().unwrap().summary());
             }
         }
     }
+
+    Ok(())
+}
+
+/// Write the commits the given refs pointed to to `out`, newest first, like
+/// `git log -g`. A ref like `main@{2}` starts at that entry of its reflog. Only
+/// the number of commits to show and to skip apply.
+pub fn reflog(
+    out: &mut impl Write,
+    repo: &Repository,
+    refs: &[String],
+    options: &Options,
+) -> anyhow::Result<()> {
+    let head = [String::from("HEAD")];
+    let refs = if refs.is_empty() { &head[..] } else { refs };
+
+    let mut first = true;
+
+    for name in refs {
+        let (name, start) = reflog_start(name)?;
+
+        // HEAD has its reflog in the git dir of the worktree, and other refs
+        // in the common dir
+        let (dir, full_name) = if name == "HEAD" {
+            (repo.git_dir(), name.to_owned())
+        } else {
+            let reference = repo
+                .find_reference(name)
+                .with_context(|| format!("{name} isn't a ref with a reflog"))?;
+            (repo.common_dir(), reference.name().as_bstr().to_string())
+        };
+
+        let entries = topo::reflog::Reflog::at(&repo.objects, dir, &full_name)
+            .with_context(|| format!("reading the reflog of {name}"))?
+            .skip(start + options.skip)
+            .take(options.max_count.unwrap_or(usize::MAX));
+
+        for entry in entries {
+            let entry = entry?;
+
+            if !first {
+                writeln!(out)?;
+            }
+            first = false;
+
+            let mut lines = header_lines(repo, &entry.info)?;
+            let signature = &entry.signature;
+            lines.splice(
+                1..1,
+                [
+                    format!(
+                        "Reflog: {} ({} <{}>)",
+                        entry.selector, signature.name, signature.email
+                    ),
+                    format!("Reflog message: {}", entry.message),
+                ],
+            );
+
+            for line in lines {
+                writeln!(out, "{line}")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Split a ref like `main@{2}` into the ref and the entry of its reflog to
+/// start at. `@{2}` on its own is `HEAD@{2}`.
+fn reflog_start(name: &str) -> anyhow::Result<(&str, usize)> {
+    let Some((name, entry)) = name.strip_suffix('}').and_then(|n| n.rsplit_once("@{")) else {
+        return Ok((name, 0));
+    };
+
+    let start = entry.parse().with_context(|| {
+        format!("only numbered reflog entries like {name}@{{1}} can be walked, not @{{{entry}}}")
+    })?;
+
+    Ok((if name.is_empty() { "HEAD" } else { name }, start))
+}
+
+/// Write a commit the way `git log` does with its default format.
+pub(crate) fn print_header(
+    out: &mut impl Write,
+    repo: &Repository,
+    info: &topo::Info,
+) -> anyhow::Result<()> {
+    for line in header_lines(repo, info)? {
+        writeln!(out, "{line}")?;
+    }
+
+    Ok(())
+}
+
+/// Same as [`print_header()`] but returns the lines instead of writing them.
+pub(crate) fn header_lines(repo: &Repository, info: &topo::Info) -> anyhow::Result<Vec<String>> {
+    let format = format_description!(
+        "[weekday repr:short] [month repr:short] [day padding:none] [hour]:[minute]:[second] [year] [offset_hour sign:mandatory][offset_minute]"
+    );
+
+    // Reading the author leaves the commit in the buffer for the message
+    let mut buf = vec![];
+    let author = info.signatures(&repo.objects, &mut buf)?.author;
+    let message = gix::objs::CommitRef::from_bytes(&buf)
+        .context("decoding commit")?
+        .message;
+
+    let mut lines = vec![format!("commit {}", info.id)];
+
+    if info.parent_ids.len() > 1 {
+        let parents = info
+            .parent_ids
+            .iter()
+            .map(|id| id.to_hex_with_len(7).to_string())
+            .collect::<Vec<_>>();
+        lines.push(format!("Merge: {}", parents.join(" ")));
+    }
+
+    lines.push(format!("Author: {} <{}>", author.name, author.email));
+    lines.push(format!("Date:   {}", author.time.format(format)));
+    lines.push(String::new());
+
+    for line in message.trim_end().lines() {
+        lines.push(format!("    {}", line.as_bstr()));
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn medium_format_like_git() {
+        let repo = gix::discover(".").unwrap();
+        let range = "d3baed3..c57fe89";
+
+        let mut out = vec![];
+        let specs = vec![repo.rev_parse(range).unwrap().detach()];
+        log(&mut out, &repo, specs, Options::default()).unwrap();
+
+        let expected = std::process::Command::new("git")
+            .args(["-C", "..", "-c", "core.abbrev=7", "log", "--date-order"])
+            .args(["--no-decorate", "--format=medium", range])
+            .output()
+            .expect("able to run git log")
+            .stdout;
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn splits_reflog_starts() {
+        assert_eq!(reflog_start("main").unwrap(), ("main", 0));
+        assert_eq!(reflog_start("HEAD@{3}").unwrap(), ("HEAD", 3));
+        assert_eq!(reflog_start("@{1}").unwrap(), ("HEAD", 1));
+        assert!(reflog_start("main@{yesterday}").is_err());
+    }
 }
@@ -3,7 +3,9 @@
 #![allow(unused_imports)]
 #![allow(unused_variables)]
 
+mod color;
 mod diffprinter;
+mod graph;
 
 use std::borrow::BorrowMut;
 use std::cmp::Reverse;
@@ -13,12 +15,13 @@ use std::default;
 use std::path::PathBuf;
 
 use anyhow::Context;
+use color::{BlamePalette, ColorChoice, DiffPalette};
 use diffprinter::UnifiedDiffBuilder;
 use gix::bstr::ByteSlice;
 use gix::prelude::FindExt;
 use gix::{bstr, config::tree::Diff};
 
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 
 use gix::{diff, discover, hash, index, object, objs, Object, ObjectId, Repository};
 
@@ -28,10 +31,14 @@ mod error;
 use error::BlameDiffError;
 
 mod log;
+mod patch_id;
+mod revs;
+mod tui;
+mod xdiff;
 
 #[derive(Args)]
 struct DiffArgs {
-    /// Old commit to diff
+    /// Old commit to diff, or a range like `A..B` or `B^!`
     #[arg(short, long)]
     old: Option<bstr::BString>,
 
@@ -39,13 +46,57 @@ struct DiffArgs {
     #[arg(short, long)]
     new: Option<bstr::BString>,
 
+    /// When to use colors
+    #[arg(
+        long,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    color: Option<ColorChoice>,
+
     /// Paths to filter on
     paths: Vec<PathBuf>,
 }
 
 #[derive(Args)]
 struct BlameArgs {
+    /// When to use colors
+    #[arg(
+        long,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    color: Option<ColorChoice>,
+
+    /// Color the annotations by age, using color.blame.highlightRecent
+    #[arg(long)]
+    color_by_age: bool,
+
+    /// Color the annotations of lines from the same commit as the line before
+    /// them, using color.blame.repeatedLines
+    #[arg(long)]
+    color_lines: bool,
+
+    #[command(flatten)]
+    ref_sets: revs::RefSets,
+
+    /// Revision to start blaming at, and revisions to stop at, prefixed with ^
+    /// or after --not
+    revisions: Vec<String>,
+
+    path: PathBuf,
+}
+
+#[derive(Args)]
+struct TuiArgs {
+    /// Revision to start blaming at
+    #[arg(short, long, default_value = "HEAD")]
     revision: String,
+
     path: PathBuf,
 }
 
@@ -56,10 +107,204 @@ struct TestArgs {
 
 #[derive(Args)]
 struct LogArgs {
+    /// Follow only the first parent of merge commits
     #[arg(short, long)]
     first_parent: bool,
 
-    revision: String,
+    /// Avoid showing commits on multiple lines of history intermixed
+    #[arg(long, conflicts_with_all = ["date_order", "author_date_order"])]
+    topo_order: bool,
+
+    /// Show commits in commit timestamp order, but no parents before all of
+    /// their children
+    #[arg(long, conflicts_with = "author_date_order")]
+    date_order: bool,
+
+    /// Show commits in author timestamp order, but no parents before all of
+    /// their children
+    #[arg(long)]
+    author_date_order: bool,
+
+    /// Show the commits the given refs, or HEAD, pointed to according to
+    /// their reflogs, newest first. Start at an older entry with a ref like
+    /// main@{2}. Only --max-count and --skip apply to the entries.
+    #[arg(
+        short = 'g',
+        long,
+        conflicts_with_all = [
+            "graph", "reverse", "ancestry_path", "first_parent", "full_history",
+            "simplify_merges", "sparse", "since", "until", "merges", "no_merges",
+            "min_parents", "max_parents", "author", "committer", "grep",
+            "all_match", "left_right", "left_only", "right_only", "cherry_mark",
+            "cherry_pick", "cherry", "all", "branches", "tags", "remotes", "glob",
+            "exclude", "not", "paths",
+        ]
+    )]
+    walk_reflogs: bool,
+
+    /// Draw a text-based graph of the history. Implies --topo-order
+    #[arg(long, conflicts_with_all = ["date_order", "author_date_order"])]
+    graph: bool,
+
+    /// Only show commits that are descendants of the excluded commits, or
+    /// ancestors or descendants of the given commit
+    #[arg(long, value_name = "COMMIT", num_args = 0..=1, require_equals = true)]
+    ancestry_path: Option<Vec<String>>,
+
+    /// Show the oldest commits first
+    #[arg(long, conflicts_with = "graph")]
+    reverse: bool,
+
+    /// Follow all parents of merges when limiting the output to paths
+    #[arg(long)]
+    full_history: bool,
+
+    /// Like --full-history, but hide merges that don't bring anything new
+    #[arg(long, conflicts_with_all = ["full_history", "sparse"])]
+    simplify_merges: bool,
+
+    /// Show all commits that aren't merges when limiting the output to paths
+    #[arg(long, conflicts_with = "full_history")]
+    sparse: bool,
+
+    /// Show at most this many commits
+    #[arg(short = 'n', long, value_name = "NUMBER")]
+    max_count: Option<usize>,
+
+    /// Skip this many commits before starting to show them
+    #[arg(long, value_name = "NUMBER", default_value_t = 0)]
+    skip: usize,
+
+    /// Show commits more recent than a specific date
+    #[arg(long, visible_alias = "after", value_name = "DATE")]
+    since: Option<String>,
+
+    /// Show commits older than a specific date
+    #[arg(long, visible_alias = "before", value_name = "DATE")]
+    until: Option<String>,
+
+    /// Only show merge commits
+    #[arg(long, conflicts_with = "no_merges")]
+    merges: bool,
+
+    /// Don't show merge commits
+    #[arg(long)]
+    no_merges: bool,
+
+    /// Only show commits with at least this many parents
+    #[arg(long, value_name = "NUMBER")]
+    min_parents: Option<usize>,
+
+    /// Only show commits with at most this many parents
+    #[arg(long, value_name = "NUMBER")]
+    max_parents: Option<usize>,
+
+    /// Only show commits whose author contains the given string
+    #[arg(long, value_name = "PATTERN")]
+    author: Vec<String>,
+
+    /// Only show commits whose committer contains the given string
+    #[arg(long, value_name = "PATTERN")]
+    committer: Vec<String>,
+
+    /// Only show commits whose message contains the given string
+    #[arg(long, value_name = "PATTERN")]
+    grep: Vec<String>,
+
+    /// Only show commits whose message contains all of the --grep strings
+    #[arg(long)]
+    all_match: bool,
+
+    /// Mark which side of a symmetric difference commits are reachable from,
+    /// with < for the left and > for the right
+    #[arg(long, conflicts_with = "graph")]
+    left_right: bool,
+
+    /// Only show commits on the left side of a symmetric difference
+    #[arg(long, conflicts_with = "right_only")]
+    left_only: bool,
+
+    /// Only show commits on the right side of a symmetric difference
+    #[arg(long)]
+    right_only: bool,
+
+    /// Mark commits on either side of a symmetric difference that make the
+    /// same change as a commit on the other side with =, and the others with +
+    #[arg(long, conflicts_with_all = ["graph", "cherry_pick"])]
+    cherry_mark: bool,
+
+    /// Leave out commits on either side of a symmetric difference that make
+    /// the same change as a commit on the other side
+    #[arg(long, conflicts_with = "graph")]
+    cherry_pick: bool,
+
+    /// Show the commits on the right side of a symmetric difference that
+    /// aren't merges, marked like --cherry-mark. Like --right-only
+    /// --cherry-mark --no-merges
+    #[arg(long, conflicts_with_all = ["graph", "left_only", "cherry_pick"])]
+    cherry: bool,
+
+    #[command(flatten)]
+    ref_sets: revs::RefSets,
+
+    /// Revisions or ranges to show the history of, HEAD if none are given
+    revisions: Vec<String>,
+
+    /// Only show commits that modify these paths
+    #[arg(last = true)]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+struct CommitGraphArgs {
+    #[command(subcommand)]
+    command: CommitGraphCommand,
+}
+
+#[derive(Subcommand)]
+enum CommitGraphCommand {
+    /// Write a commit-graph file with all commits reachable from any ref
+    Write {
+        /// Compute changed-path Bloom filters, to speed up limiting to paths
+        #[arg(long)]
+        changed_paths: bool,
+    },
+    /// Check that the commit-graph file is valid and agrees with the commits
+    Verify,
+}
+
+#[derive(Args)]
+struct MergeBaseArgs {
+    /// Show all merge bases, instead of only the newest
+    #[arg(long, conflicts_with = "is_ancestor")]
+    all: bool,
+
+    /// Check whether the first commit is an ancestor of the second, exiting
+    /// with 0 if so and 1 if not
+    #[arg(long)]
+    is_ancestor: bool,
+
+    /// The commits to find the merge base of, the first against all the others
+    #[arg(required = true, num_args = 2..)]
+    commits: Vec<String>,
+}
+
+#[derive(Args)]
+struct RevListArgs {
+    /// Print the number of commits instead of their ids
+    #[arg(long)]
+    count: bool,
+
+    /// Mark which side of a symmetric difference each commit is reachable
+    /// from, or count them separately with --count
+    #[arg(long)]
+    left_right: bool,
+
+    #[command(flatten)]
+    ref_sets: revs::RefSets,
+
+    /// Revisions or ranges to list, HEAD if none are given
+    revisions: Vec<String>,
 }
 
 #[derive(Parser)]
@@ -69,12 +314,39 @@ struct Cli {
     command: Command,
 }
 
+impl Cli {
+    /// Turn the matched arguments into the commands' arguments, noting where
+    /// the revisions and sets of refs were for `--not`.
+    fn from_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        let mut cli = Self::from_arg_matches(matches)?;
+
+        if let Some((_, matches)) = matches.subcommand() {
+            match &mut cli.command {
+                Command::Blame(BlameArgs { ref_sets, .. })
+                | Command::Log(LogArgs { ref_sets, .. })
+                | Command::RevList(RevListArgs { ref_sets, .. }) => ref_sets.locate(matches),
+                _ => (),
+            }
+        }
+
+        Ok(cli)
+    }
+}
+
 #[derive(Subcommand)]
 enum Command {
     Diff(DiffArgs),
     Blame(BlameArgs),
     Test(TestArgs),
     Log(LogArgs),
+    /// Browse the blame of a file interactively
+    Tui(TuiArgs),
+    /// Write or verify the commit-graph, which speeds up walking the history
+    CommitGraph(CommitGraphArgs),
+    /// Find the best common ancestors of commits
+    MergeBase(MergeBaseArgs),
+    /// List or count the commits in a revision range
+    RevList(RevListArgs),
 }
 
 fn get_object(
@@ -93,13 +365,17 @@ fn resolve_tree<'a>(repo: &'a Repository, object: &bstr::BStr) -> anyhow::Result
 }
 
 fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
+    let args = Cli::from_matches(&Cli::command().get_matches()).unwrap_or_else(|e| e.exit());
 
     match args.command {
         Command::Diff(da) => cmd_diff(da),
         Command::Blame(ba) => cmd_blame(ba),
         Command::Test(ta) => cmd_test(ta),
         Command::Log(la) => cmd_log(la),
+        Command::Tui(ta) => cmd_tui(ta),
+        Command::CommitGraph(ca) => cmd_commit_graph(ca),
+        Command::MergeBase(ma) => cmd_merge_base(ma),
+        Command::RevList(ra) => cmd_rev_list(ra),
     }
 }
 
@@ -132,20 +408,57 @@ fn cmd_diff(da: DiffArgs) -> anyhow::Result<()> {
 
     let paths = owned_paths.iter().map(|s| s.as_ref()).collect::<Vec<_>>();
 
+    let palette = if color::use_color(&repo, "diff", da.color) {
+        DiffPalette::from_config(&repo)
+    } else {
+        DiffPalette::plain()
+    };
+
     let old = da.old.unwrap_or(bstr::BString::from("HEAD"));
-    let old = resolve_tree(&repo, old.as_ref())?;
+    let (old, new) = diff_endpoints(&repo, old.as_ref(), da.new)?;
 
-    if let Some(arg) = da.new {
-        let new = resolve_tree(&repo, arg.as_ref())?;
+    let old = get_object(&repo, old, object::Kind::Tree)?.into_tree();
 
-        diff_two_trees(old, new, &paths);
+    if let Some(new) = new {
+        let new = get_object(&repo, new, object::Kind::Tree)?.into_tree();
+
+        diff_two_trees(old, new, &paths, &palette);
     } else {
-        diff_with_disk(&repo, &paths);
+        diff_with_disk(&repo, &paths, &palette);
     }
 
     Ok(())
 }
 
+/// Resolve `--old` and `--new` of the diff command to the commits to diff.
+///
+/// Like `git diff`, `--old` can also be a range or a commit with its parent
+/// excluded, e.g. `A..B` or `B^!`, which diffs A and B or B^ and B.
+fn diff_endpoints(
+    repo: &Repository,
+    old: &bstr::BStr,
+    new: Option<bstr::BString>,
+) -> anyhow::Result<(ObjectId, Option<ObjectId>)> {
+    use gix::revision::plumbing::Spec;
+    Ok(match (repo.rev_parse(old)?.detach(), new) {
+        (Spec::Include(id), None) => (id, None),
+        (Spec::Include(id), Some(new)) => (id, Some(repo.rev_parse_single(new.as_ref())?.detach())),
+        (Spec::Range { from, to }, None) => (from, Some(to)),
+        (Spec::ExcludeParents(id), None) => {
+            let commit = repo.find_object(id)?.into_commit();
+            let mut parent_ids = commit.parent_ids();
+            match (parent_ids.next(), parent_ids.next()) {
+                (Some(parent_id), None) => (parent_id.detach(), Some(id)),
+                (None, _) => anyhow::bail!("{old} has no parent to diff against"),
+                (Some(_), Some(_)) => {
+                    anyhow::bail!("{old} is a merge, diff its parents explicitly with A..B instead")
+                }
+            }
+        }
+        _ => return Err(BlameDiffError::InvalidRange.into()),
+    })
+}
+
 fn disk_newer_than_index(
     stat: &index::entry::Stat,
     path: &std::path::Path,
@@ -163,6 +476,7 @@ fn diff_two_trees(
     tree_old: gix::Tree,
     tree_new: gix::Tree,
     paths: &[&bstr::BStr],
+    palette: &DiffPalette,
 ) -> anyhow::Result<()> {
     let mut platform = tree_old.changes()?;
 
@@ -176,12 +490,12 @@ fn diff_two_trees(
                 Addition { entry_mode, id }
                     if entry_mode.kind() == object::tree::EntryKind::Blob =>
                 {
-                    diff_blob_with_null(id, c.location, false)
+                    diff_blob_with_null(id, c.location, false, palette)
                 }
                 Deletion { entry_mode, id }
                     if entry_mode.kind() == object::tree::EntryKind::Blob =>
                 {
-                    diff_blob_with_null(id, c.location, true)
+                    diff_blob_with_null(id, c.location, true, palette)
                 }
                 Modification {
                     previous_entry_mode,
@@ -191,12 +505,9 @@ fn diff_two_trees(
                 } if entry_mode.kind() == object::tree::EntryKind::Blob
                     && previous_entry_mode.kind() == object::tree::EntryKind::Blob =>
                 {
-                    diff_two_blobs(previous_id, id, c.location)
-                }
-                x => {
-                    dbg!(x);
-                    Ok(())
+                    diff_two_blobs(previous_id, id, c.location, palette)
                 }
+                _ => Ok(()),
             }
             .map(|()| object::tree::diff::Action::Continue)
         } else {
@@ -207,7 +518,11 @@ fn diff_two_trees(
     Ok(())
 }
 
-fn diff_with_disk(repo: &Repository, paths: &[&bstr::BStr]) -> anyhow::Result<()> {
+fn diff_with_disk(
+    repo: &Repository,
+    paths: &[&bstr::BStr],
+    palette: &DiffPalette,
+) -> anyhow::Result<()> {
     let index = repo.open_index()?;
     for e in index.entries() {
         let p = e.path(&index);
@@ -232,7 +547,7 @@ fn diff_with_disk(repo: &Repository, paths: &[&bstr::BStr]) -> anyhow::Result<()
                     UnifiedDiffBuilder::new(&input, old, new),
                 );
 
-                print!("{}", diff);
+                print!("{}", diff.paint(palette));
             }
         }
     }
@@ -244,6 +559,7 @@ fn diff_blob_with_null(
     id: gix::Id,
     path: &bstr::BStr,
     to_null: bool,
+    palette: &DiffPalette,
 ) -> Result<(), BlameDiffError> {
     let data = &id.object()?.data;
     let file = std::str::from_utf8(data)?;
@@ -269,7 +585,7 @@ fn diff_blob_with_null(
         },
     );
 
-    print!("{}", diff);
+    print!("{}", diff.paint(palette));
 
     Ok(())
 }
@@ -278,6 +594,7 @@ fn diff_two_blobs(
     old_id: gix::Id,
     new_id: gix::Id,
     path: &bstr::BStr,
+    palette: &DiffPalette,
 ) -> Result<(), BlameDiffError> {
     let old_data = &old_id.object()?.data;
     let new_data = &new_id.object()?.data;
@@ -296,26 +613,54 @@ fn diff_two_blobs(
         path,
     };
 
-    let t = culpa::line_tracking::LineTracker::from_range(0..new_file.lines().count() as u32);
-
     let diff = diff::blob::diff(
         diff::blob::Algorithm::Histogram,
         &input,
-        culpa::sinks::RangeAndLineCollector::new(&input, t),
+        UnifiedDiffBuilder::new(&input, old, new),
     );
 
-    print!("{:#?}", diff);
+    print!("{}", diff.paint(palette));
 
     Ok(())
 }
 
 fn cmd_blame(ba: BlameArgs) -> anyhow::Result<()> {
     let repo = gix::discover(".")?;
-    let b = culpa::blame_file(&repo, &ba.revision, culpa::Parents::All, &ba.path)?;
+    let specs = revs::specs(&repo, &ba.revisions, &ba.ref_sets)?;
+
+    let tips = specs
+        .iter()
+        .filter(|s| !matches!(s, gix::revision::plumbing::Spec::Exclude(_)))
+        .count();
+    if tips > 1 {
+        anyhow::bail!(
+            "blame starts at a single commit, but the revisions give {tips}; \
+            exclude the others with ^ or --not"
+        );
+    }
+
+    let b = culpa::blame_file_with_specs(&repo, specs, culpa::Parents::All, &ba.path)?;
     let format = format_description!(
         "[year]-[month]-[day] [hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
     );
 
+    let palette = if color::use_color(&repo, "blame", ba.color) {
+        let coloring = repo
+            .config_snapshot()
+            .string("blame.coloring")
+            .map(|v| v.to_string());
+
+        BlamePalette::from_config(
+            &repo,
+            ba.color_by_age || coloring.as_deref() == Some("highlightRecent"),
+            ba.color_lines || coloring.as_deref() == Some("repeatedLines"),
+        )
+    } else {
+        BlamePalette::plain()
+    };
+
+    let mut previous = None;
+
     for bl in b.blamed_lines() {
         let c = repo.find_object(bl.id)?.into_commit();
 
@@ -328,10 +673,22 @@ fn cmd_blame(ba: BlameArgs) -> anyhow::Result<()> {
             ("", c.id.to_hex_with_len(8))
         };
 
-        println!(
-            "{boundary}{short_hash} ({name} {timestamp} {:2}) {}",
-            bl.line_no + 1,
-            bl.line,
+        let style = palette.style(author.time.seconds, previous == Some(bl.id));
+        previous = Some(bl.id);
+
+        let annotation = format!(
+            "{boundary}{short_hash} ({name} {timestamp} {:2})",
+            bl.line_no + 1
+        );
+
+        println!("{} {}", style.paint(&annotation), bl.line);
+    }
+
+    let unblamable = b.unblamable_lines();
+    if !unblamable.is_empty() {
+        eprintln!(
+            "warning: {} lines couldn't be blamed further because objects are missing",
+            unblamable.len()
         );
     }
 
@@ -357,202 +714,253 @@ struct MyState {
 //     }
 // }
 
-// Info about a future merge commit
-#[derive(Debug)]
-struct DescendedMerge {
-    chain: usize,
-    info: Info,
-    total: usize,
-}
-
 fn cmd_test(ta: TestArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
-// fn cmd_test(ta: TestArgs) -> anyhow::Result<()> {
-//     let repo = &gix::discover(".")?;
-//     let head = repo.rev_parse_single(ta.args[0].as_str())?;
-
-//     let state = ancestors::State::default();
-
-//     let mut merges = HashMap::<gix::ObjectId, DescendedMerge>::new();
-//     let mut seen = HashSet::<gix::ObjectId>::new();
-
-//     let ancestors = Ancestors::new(
-//         std::iter::once(head),
-//         state,
-//         |oid: &gix::oid, buf: &mut Vec<u8>| repo.objects.find_commit_iter(oid, buf),
-//     )
-//     .sorting(Sorting::ByCommitTimeNewestFirst)?;
-
-//     for (c, chain) in ancestors.map(|c| {
-//         let commit = c.unwrap();
-//         seen.insert(commit.id);
-
-//         let total = commit.parent_ids.len();
-
-//         if total > 1 {
-//             // This is a merge commit. Start tracking it.
-//             for (i, parent) in commit.parent_ids.iter().enumerate() {
-//                 merges.insert(
-//                     *parent,
-//                     DescendedMerge {
-//                         chain: i,
-//                         info: commit.clone(),
-//                         total,
-//                     },
-//                 );
-//             }
-//         }
-
-//         let chain = match merges.entry(commit.id) {
-//             Entry::Occupied(e) => {
-//                 // This commit is an ancestor of a merge we know about
-//                 let (_, descended_merge) = e.remove_entry();
-
-//                 let mut chain = Some(descended_merge.chain);
-
-//                 if commit.parent_ids.len() > 0 {
-//                     let pid = *commit.parent_ids.first().unwrap();
-
-//                     if pid == descended_merge.info.id {
-//                         chain = None;
-//                     }
-
-//                     if merges.contains_key(&pid) {
-//                         chain = None;
-//                     } else {
-//                         // Update to indicate that this commit's parent is now
-//                         // know to be an ancestor of a merge we know about
-//                         merges.insert(
-//                             pid,
-//                             DescendedMerge {
-//                                 chain: descended_merge.chain,
-//                                 info: descended_merge.info,
-//                                 total: descended_merge.total,
-//                             },
-//                         );
-//                     }
-//                 }
-
-//                 chain
-//             }
-//             Entry::Vacant(_) => {
-//                 // This commit is not an ancestor of a merge commit we know about so there is no chain
-//                 None
-//             }
-//         };
-
-//         // dbg!(&commit, &merges);
-
-//         (commit, chain)
-//     }) {
-//         if let Some(chain) = chain {
-//             if chain == 0 {
-//                 println!("* | {}", c.id);
-//             } else {
-//                 println!("| * {}", c.id);
-//             }
-//         } else {
-//             println!("* {}", c.id);
-//         }
-//         if c.parent_ids.len() > 1 {
-//             println!("|\\");
-//         }
-//     }
-
-//     Ok(())
-// }
-
 fn cmd_log(la: LogArgs) -> anyhow::Result<()> {
     let repo = discover(".")?;
-    let revision = la.revision.as_str();
 
-    let range = repo.rev_parse(revision)?.detach();
+    let prefix = repo
+        .prefix()
+        .expect("have worktree")
+        .expect("have worktree");
 
-    use gix::revision::plumbing::Spec;
-    let (start, end) = match range {
-        Spec::Include(oid) => (repo.find_object(oid)?.id, None),
-        Spec::Exclude(oid) => (repo.rev_parse_single("HEAD")?.object()?.id, Some(oid)),
-        Spec::Range { from, to } => (to, Some(from)),
-        _ => return Err(anyhow::anyhow!("Invalid range")),
+    let options = log::Options {
+        sorting: if la.topo_order {
+            topo::Sorting::TopoOrder
+        } else if la.author_date_order {
+            topo::Sorting::AuthorDateOrder
+        } else {
+            topo::Sorting::DateOrder
+        },
+        parents: if la.first_parent {
+            topo::Parents::First
+        } else {
+            topo::Parents::All
+        },
+        paths: la.paths.into_iter().map(|p| prefix.join(p)).collect(),
+        simplification: if la.simplify_merges {
+            topo::Simplification::SimplifyMerges
+        } else if la.full_history {
+            topo::Simplification::FullHistory
+        } else if la.sparse {
+            topo::Simplification::Sparse
+        } else {
+            topo::Simplification::Dense
+        },
+        graph: la.graph,
+        ancestry_path: la
+            .ancestry_path
+            .map(|commits| {
+                commits
+                    .iter()
+                    .map(|c| Ok(repo.rev_parse_single(c.as_str())?.detach()))
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?,
+        reverse: la.reverse,
+        max_count: la.max_count,
+        skip: la.skip,
+        since: la.since.as_deref().map(parse_date).transpose()?,
+        until: la.until.as_deref().map(parse_date).transpose()?,
+        min_parents: la.min_parents.unwrap_or(if la.merges { 2 } else { 0 }),
+        max_parents: la.max_parents.or((la.no_merges || la.cherry).then_some(1)),
+        authors: la.author,
+        committers: la.committer,
+        grep: la.grep,
+        all_match: la.all_match,
+        only: if la.left_only {
+            Some(topo::Side::Left)
+        } else if la.right_only || la.cherry {
+            Some(topo::Side::Right)
+        } else {
+            None
+        },
+        left_right: la.left_right,
+        cherry: if la.cherry_mark || la.cherry {
+            Some(log::Cherry::Mark)
+        } else if la.cherry_pick {
+            Some(log::Cherry::Pick)
+        } else {
+            None
+        },
     };
 
-    let rev_walker = {
-        let r = repo
-            .rev_walk(std::iter::once(start))
-            .sorting(gix::traverse::commit::Sorting::BreadthFirst);
+    if la.walk_reflogs {
+        return log::reflog(
+            &mut std::io::stdout().lock(),
+            &repo,
+            &la.revisions,
+            &options,
+        );
+    }
 
-        if la.first_parent {
-            r.first_parent_only()
-        } else {
-            r
+    let specs = revs::specs(&repo, &la.revisions, &la.ref_sets)?;
+
+    log::log(&mut std::io::stdout().lock(), &repo, specs, options)
+}
+
+/// Parse a date the way Git does, like `2 weeks ago` or `2023-10-01`, into
+/// seconds since the epoch.
+fn parse_date(date: &str) -> anyhow::Result<i64> {
+    let time = gix::date::parse(date, Some(std::time::SystemTime::now()))
+        .with_context(|| format!("invalid date '{date}'"))?;
+
+    Ok(time.seconds)
+}
+
+fn cmd_tui(ta: TuiArgs) -> anyhow::Result<()> {
+    let repo = discover(".")?;
+
+    tui::run(&repo, &ta.revision, &ta.path)
+}
+
+fn cmd_commit_graph(ca: CommitGraphArgs) -> anyhow::Result<()> {
+    let repo = discover(".")?;
+    let info_dir = repo.objects.store_ref().path().join("info");
+
+    match ca.command {
+        CommitGraphCommand::Write { changed_paths } => {
+            let mut tips = vec![];
+
+            if let Ok(head) = repo.head_id() {
+                tips.push(head.detach());
+            }
+
+            for reference in repo.references()?.all()? {
+                let mut reference = reference.map_err(|e| anyhow::anyhow!(e))?;
+                let id = reference.peel_to_id_in_place()?;
+
+                // Refs to trees and blobs have no history
+                if let Ok(commit) = get_object(&repo, id, object::Kind::Commit) {
+                    tips.push(commit.id);
+                }
+            }
+
+            let outcome = topo::commit_graph::write(
+                &repo.objects,
+                tips,
+                &info_dir,
+                topo::commit_graph::Options { changed_paths },
+            )?;
+
+            println!(
+                "Wrote {} commits to {}",
+                outcome.num_commits,
+                outcome.path.display()
+            );
+        }
+        CommitGraphCommand::Verify => {
+            let outcome = topo::commit_graph::verify(&repo.objects, &info_dir)?;
+
+            println!("Verified {} commits", outcome.num_commits);
         }
     }
-    .all()?;
 
-    let commit_graph = repo.commit_graph()?;
+    Ok(())
+}
 
-    let history: Result<Vec<_>, _> = rev_walker.into_iter().collect();
-    let history = history?;
+/// Resolve a revision to the commit it points to.
+fn resolve_commit(repo: &Repository, revision: &str) -> anyhow::Result<ObjectId> {
+    let id = repo.rev_parse_single(revision)?;
+    Ok(get_object(repo, id, object::Kind::Commit)?.id)
+}
 
-    // TODO: rev_walker.map_while() directly instead of collecting everything into a Vec
+fn cmd_merge_base(ma: MergeBaseArgs) -> anyhow::Result<()> {
+    let repo = discover(".")?;
+    let commit_graph = repo.commit_graph().ok();
 
-    // Collect commits that don't have any generation numbers. As soon as a gen
-    // number is found we know all subsequent (ie. earlier in history) have.
-    let missing_gens: Vec<_> = history
+    let commits = ma
+        .commits
         .iter()
-        .map_while(|h| {
-            if commit_graph.lookup(h.id).is_none() {
-                Some(h)
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    let mut my_gen_numbers = HashMap::<gix::ObjectId, u32>::new();
-
-    // Walk in reverse
-    for h in missing_gens.into_iter().rev() {
-        // A commit's generation number is m + 1 where m is the maximum generation number of its parents
-        let gen = h
-            .parent_ids()
-            .map(|pid| {
-                // Get gen number either from the commit graph or a value previously calculated in my_gen_numbers
-                commit_graph.commit_by_id(&pid).map_or_else(
-                    || {
-                        *my_gen_numbers
-                            .get(&pid.detach())
-                            .expect("have gen number inserted")
-                    },
-                    |o| o.generation(),
-                )
-            })
-            .max()
-            .unwrap_or(0)
-            + 1;
+        .map(|c| resolve_commit(&repo, c))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let (one, others) = commits.split_first().expect("at least two commits");
 
-        if my_gen_numbers.insert(h.id, gen).is_some() {
-            panic!("was already there");
-        }
+    if ma.is_ancestor {
+        let [descendant] = others else {
+            anyhow::bail!("--is-ancestor takes exactly two commits");
+        };
+
+        let is_ancestor =
+            topo::reach::is_ancestor(&repo.objects, commit_graph.as_ref(), *one, *descendant)?;
+        std::process::exit(if is_ancestor { 0 } else { 1 });
     }
 
-    // Copy rest of history numbers to avoid having to lookup two places:
-    for c in commit_graph.iter_commits() {
-        my_gen_numbers.insert(ObjectId::from(c.id()), c.generation());
+    let bases =
+        topo::reach::merge_bases(&repo.objects, commit_graph.as_ref(), *one, others.to_vec())?;
+
+    if bases.is_empty() {
+        // Like Git, no merge base isn't an error but exits with 1
+        std::process::exit(1);
     }
 
-    // let topo_walker = topo::Walk::new(
-    //     repo.commit_graph()?,
-    //     move |id, buf| repo.objects.find_commit_iter(id, buf),
-    //     std::iter::once(start),
-    //     None,
-    // )?;
+    for id in bases.iter().take(if ma.all { bases.len() } else { 1 }) {
+        println!("{id}");
+    }
 
-    // for c in topo_walker {
-    //     println!("{}", c?.to_hex());
-    // }
+    Ok(())
+}
+
+fn cmd_rev_list(ra: RevListArgs) -> anyhow::Result<()> {
+    use gix::revision::plumbing::Spec;
+
+    let repo = discover(".")?;
+    let specs = revs::specs(&repo, &ra.revisions, &ra.ref_sets)?;
+
+    if let (true, true, [Spec::Merge { theirs, ours }]) = (ra.count, ra.left_right, &specs[..]) {
+        let (ahead, behind) = topo::reach::ahead_behind(
+            &repo.objects,
+            repo.commit_graph().ok().as_ref(),
+            *theirs,
+            *ours,
+        )?;
+        println!("{ahead}\t{behind}");
+        return Ok(());
+    }
+
+    let walk = topo::Builder::from_specs(&repo.objects, specs)
+        .with_commit_graph(repo.commit_graph().ok())
+        .build()?;
+
+    let (mut left, mut right) = (0, 0);
+
+    for info in walk {
+        let info = info?;
+
+        match (ra.count, info.side) {
+            (true, Some(topo::Side::Left)) if ra.left_right => left += 1,
+            (true, _) => right += 1,
+            (false, Some(topo::Side::Left)) if ra.left_right => println!("<{}", info.id),
+            (false, Some(topo::Side::Right)) if ra.left_right => println!(">{}", info.id),
+            (false, _) => println!("{}", info.id),
+        }
+    }
+
+    match (ra.count, ra.left_right) {
+        (true, true) => println!("{left}\t{right}"),
+        (true, false) => println!("{right}"),
+        (false, _) => (),
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_exclude_parents() {
+        let repo = gix::discover(".").unwrap();
+        let id = |rev: &str| repo.rev_parse_single(rev).unwrap().detach();
+
+        let (old, new) = diff_endpoints(&repo, "d7d6328^!".into(), None).unwrap();
+        assert_eq!((old, new), (id("d7d6328^"), Some(id("d7d6328"))));
+
+        // A merge has more than one parent to diff against
+        let err = diff_endpoints(&repo, "c57fe89^!".into(), None).unwrap_err();
+        assert!(err.to_string().contains("is a merge"), "{err}");
+    }
+}
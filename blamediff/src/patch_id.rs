@@ -0,0 +1,342 @@
+//! Patch-ids, hashes of the changes a commit makes that stay the same when
+//! it's cherry-picked elsewhere. They're the ones Git computes for
+//! `git log --cherry-mark`, which are the same as those of
+//! `git patch-id --stable` for changes to the contents of text files: each
+//! file's diff is hashed without whitespace, hunk headers and line numbers,
+//! but with three lines of context, and the hashes are added together so the
+//! order of the files doesn't matter.
+
+use std::ops::Range;
+
+use gix::bstr::{BString, ByteSlice};
+use gix::diff::blob::intern::InternedInput;
+use gix::diff::blob::sources::byte_lines_with_terminator;
+use gix::features::hash::Sha1;
+use gix::{object, ObjectId, Repository};
+
+use crate::xdiff;
+
+/// Lines of context around the changes
+const CONTEXT: usize = 3;
+
+/// A file changed by a commit, with its id and mode on either side
+struct FileChange {
+    path: BString,
+    previous: Option<(ObjectId, u32)>,
+    current: Option<(ObjectId, u32)>,
+}
+
+/// The patch-id of a commit, or `None` for merges like in Git.
+pub fn patch_id(repo: &Repository, info: &topo::Info) -> anyhow::Result<Option<ObjectId>> {
+    let commit = repo.find_object(info.id)?.into_commit();
+    let mut parent_ids = commit.parent_ids();
+
+    let previous_tree = match (parent_ids.next(), parent_ids.next()) {
+        (None, _) => repo.empty_tree(),
+        (Some(parent_id), None) => parent_id.object()?.peel_to_tree()?,
+        (Some(_), Some(_)) => return Ok(None),
+    };
+    let tree = commit.tree()?;
+
+    let mut changes = vec![];
+    let mut platform = previous_tree.changes()?;
+    platform.track_path();
+    platform.for_each_to_obtain_tree(&tree, |c| {
+        use object::tree::diff::change::Event::*;
+
+        let (previous, current) = match c.event {
+            Addition { entry_mode, id } => (None, file(entry_mode, id.detach())),
+            Deletion { entry_mode, id } => (file(entry_mode, id.detach()), None),
+            Modification {
+                previous_entry_mode,
+                previous_id,
+                entry_mode,
+                id,
+            } => (
+                file(previous_entry_mode, previous_id.detach()),
+                file(entry_mode, id.detach()),
+            ),
+        };
+
+        if previous.is_some() || current.is_some() {
+            changes.push(FileChange {
+                path: c.location.to_owned(),
+                previous,
+                current,
+            });
+        }
+
+        Ok::<_, std::convert::Infallible>(object::tree::diff::Action::Continue)
+    })?;
+
+    // Each file's hash is added to the others as a little-endian number
+    let mut patch_id = [0u8; 20];
+    for change in &changes {
+        let mut carry = 0u16;
+        for (sum, byte) in patch_id.iter_mut().zip(file_patch_id(repo, change)?) {
+            carry += u16::from(*sum) + u16::from(byte);
+            *sum = carry as u8;
+            carry >>= 8;
+        }
+    }
+
+    Ok(Some(ObjectId::from(patch_id)))
+}
+
+/// Mode of submodules, whose commit ids are diffed
+const GITLINK: u32 = 0o160000;
+
+/// The id and mode of a file in Git's terms, leaving out trees, which are
+/// walked instead.
+fn file(mode: object::tree::EntryMode, id: ObjectId) -> Option<(ObjectId, u32)> {
+    use object::tree::EntryKind::*;
+
+    let mode = match mode.kind() {
+        Blob => 0o100644,
+        BlobExecutable => 0o100755,
+        Link => 0o120000,
+        Commit => GITLINK,
+        Tree => return None,
+    };
+
+    Some((id, mode))
+}
+
+/// Hash the diff of a file like `git diff` would show it, without whitespace
+/// and hunk headers.
+fn file_patch_id(repo: &Repository, change: &FileChange) -> anyhow::Result<[u8; 20]> {
+    let mut hasher = gix::features::hash::hasher(gix::hash::Kind::Sha1);
+    let path = without_whitespace(&change.path);
+
+    hasher.update(b"diff--gita/");
+    hasher.update(&path);
+    hasher.update(b"b/");
+    hasher.update(&path);
+
+    match (change.previous, change.current) {
+        (None, Some((_, mode))) => hasher.update(format!("newfilemode{mode:06o}").as_bytes()),
+        (Some((_, mode)), None) => hasher.update(format!("deletedfilemode{mode:06o}").as_bytes()),
+        (Some((_, previous_mode)), Some((_, mode))) if previous_mode != mode => {
+            hasher.update(format!("oldmode{previous_mode:06o}newmode{mode:06o}").as_bytes())
+        }
+        _ => (),
+    }
+
+    let previous = blob_data(repo, change.previous)?;
+    let current = blob_data(repo, change.current)?;
+
+    if is_binary(&previous) || is_binary(&current) {
+        // Like Git, binary files are compared by their ids
+        for file in [change.previous, change.current] {
+            let id = file.map_or(ObjectId::null(gix::hash::Kind::Sha1), |(id, _)| id);
+            hasher.update(id.to_hex().to_string().as_bytes());
+        }
+    } else {
+        match change.previous {
+            Some(_) => {
+                hasher.update(b"---a/");
+                hasher.update(&path);
+            }
+            None => hasher.update(b"---/dev/null"),
+        }
+        match change.current {
+            Some(_) => {
+                hasher.update(b"+++b/");
+                hasher.update(&path);
+            }
+            None => hasher.update(b"+++/dev/null"),
+        }
+
+        hash_diff(&mut hasher, &previous, &current);
+    }
+
+    Ok(hasher.digest())
+}
+
+/// A run of lines replaced by another
+struct Change {
+    before: Range<usize>,
+    after: Range<usize>,
+}
+
+/// Hash the lines of the hunks of Git's diff, with a `-` or `+` in front of
+/// removed and added lines.
+fn hash_diff(hasher: &mut Sha1, previous: &[u8], current: &[u8]) {
+    let input = InternedInput::new(
+        byte_lines_with_terminator(previous),
+        byte_lines_with_terminator(current),
+    );
+    let (before, after) = xdiff::diff(&input.before, &input.after, input.interner.num_tokens());
+
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if before.get(i) == Some(&true) || after.get(j) == Some(&true) {
+            let change = Change {
+                before: i..i + before[i..].iter().take_while(|c| **c).count(),
+                after: j..j + after[j..].iter().take_while(|c| **c).count(),
+            };
+            (i, j) = (change.before.end, change.after.end);
+            changes.push(change);
+        } else {
+            i += 1;
+            j += 1;
+        }
+    }
+
+    let mut hash_line = |prefix: &[u8], line| {
+        hasher.update(prefix);
+        hasher.update(&without_whitespace(input.interner[line]));
+    };
+
+    let mut changes = &changes[..];
+    while !changes.is_empty() {
+        // Changes with no more than twice the context between them share a
+        // hunk
+        let len = 1 + changes
+            .windows(2)
+            .take_while(|w| w[1].before.start - w[0].before.end <= 2 * CONTEXT)
+            .count();
+        let (hunk, rest) = changes.split_at(len);
+        changes = rest;
+
+        // The context is hashed without the space in front of it
+        let mut line = hunk[0].after.start.saturating_sub(CONTEXT);
+        for change in hunk {
+            for context in line..change.after.start {
+                hash_line(b"", input.after[context]);
+            }
+            for removed in change.before.clone() {
+                hash_line(b"-", input.before[removed]);
+            }
+            for added in change.after.clone() {
+                hash_line(b"+", input.after[added]);
+            }
+            line = change.after.end;
+        }
+
+        let end = (line + CONTEXT).min(input.after.len());
+        for context in line..end {
+            hash_line(b"", input.after[context]);
+        }
+    }
+}
+
+/// Whether Git would consider the contents binary, which is when there's a
+/// NUL byte near the start.
+fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(8000)].contains(&0)
+}
+
+fn without_whitespace(data: &[u8]) -> Vec<u8> {
+    data.iter()
+        .copied()
+        .filter(|b| !matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+        .collect()
+}
+
+/// The contents of a file, which Git makes up for submodules.
+fn blob_data(repo: &Repository, file: Option<(ObjectId, u32)>) -> anyhow::Result<Vec<u8>> {
+    match file {
+        Some((id, GITLINK)) => Ok(format!("Subproject commit {id}\n").into_bytes()),
+        Some((id, _)) => Ok(repo.find_object(id)?.detach().data),
+        None => Ok(vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use super::*;
+    use crate::log;
+    use pretty_assertions::assert_eq;
+    use topo::scratch::Scratch;
+
+    fn git_patch_id(rev: &str) -> Option<ObjectId> {
+        // Git's own patch-ids come from diffs without the indent heuristic
+        let diff = Command::new("git")
+            .args(["-C", "..", "diff-tree", "-p", "--root", "--full-index"])
+            .args(["--no-indent-heuristic", rev])
+            .output()
+            .expect("able to run git diff-tree")
+            .stdout;
+
+        let mut git = Command::new("git")
+            .args(["patch-id", "--stable"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("able to run git patch-id");
+        git.stdin.take().unwrap().write_all(&diff).unwrap();
+        let output = git.wait_with_output().unwrap().stdout;
+
+        std::str::from_utf8(&output)
+            .unwrap()
+            .split_whitespace()
+            .next()
+            .map(|id| ObjectId::from_hex(id.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn same_as_git_patch_id() {
+        let repo = gix::discover(".").unwrap();
+
+        for rev in ["d3baed3", "3be8265", "b282e76", "d7d6328"] {
+            let commit = repo.rev_parse_single(rev).unwrap().object().unwrap();
+            let commit = commit.into_commit();
+            let info = topo::Info {
+                id: commit.id,
+                parent_ids: commit.parent_ids().map(|id| id.detach()).collect(),
+                side: None,
+                boundary: false,
+                details: None,
+            };
+
+            assert_eq!(patch_id(&repo, &info).unwrap(), git_patch_id(rev), "{rev}");
+        }
+    }
+
+    #[test]
+    fn cherry_mark_like_git() {
+        let scratch = Scratch::borrowing_objects("cherry");
+        let commit_tree = |tree: &str, parent: &str, message: &str| {
+            let output = scratch.git(&["commit-tree", tree, "-p", parent, "-m", message]);
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).unwrap().trim().to_owned()
+        };
+
+        // The same change as 3be8265 on top of its parent, and then undone
+        let picked = commit_tree("3be8265^{tree}", "3be8265^", "Pick 3be8265");
+        let reverted = commit_tree("3be8265^^{tree}", &picked, "Revert 3be8265");
+        let range = format!("3be8265...{reverted}");
+
+        let repo = gix::open(&scratch.dir).unwrap();
+        let mut out = vec![];
+        let specs = vec![repo.rev_parse(range.as_str()).unwrap().detach()];
+        let options = log::Options {
+            cherry: Some(log::Cherry::Mark),
+            ..Default::default()
+        };
+        log::log(&mut out, &repo, specs, options).unwrap();
+
+        let expected = scratch
+            .git(&[
+                "-c",
+                "core.abbrev=7",
+                "log",
+                "--date-order",
+                "--no-decorate",
+                "--format=medium",
+                "--cherry-mark",
+                &range,
+            ])
+            .stdout;
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(expected).unwrap()
+        );
+    }
+}
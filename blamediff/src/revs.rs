@@ -0,0 +1,343 @@
+//! Revisions given as sets of refs, like `git log --branches`, in addition to
+//! the usual revisions and ranges, and `--not` to turn what follows it
+//! around.
+
+use std::collections::HashMap;
+
+use clap::{ArgAction, ArgMatches, Args};
+use gix::bstr::{BStr, BString, ByteSlice};
+use gix::revision::plumbing::Spec;
+use gix::{object, Repository};
+
+/// Sets of refs to start at, all of them resolved through the repository's
+/// refs.
+#[derive(Args, Default)]
+pub struct RefSets {
+    /// Start at all refs, and HEAD
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = ArgAction::Append,
+        hide_possible_values = true
+    )]
+    all: Vec<bool>,
+
+    /// Start at all branches, or those matching the pattern
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "*"
+    )]
+    branches: Vec<String>,
+
+    /// Start at all tags, or those matching the pattern
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "*"
+    )]
+    tags: Vec<String>,
+
+    /// Start at all remote-tracking branches, or those matching the pattern
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "*"
+    )]
+    remotes: Vec<String>,
+
+    /// Start at all refs matching the pattern. It's relative to refs/ unless
+    /// it starts with it.
+    #[arg(long, value_name = "PATTERN")]
+    glob: Vec<String>,
+
+    /// Leave out the refs matching the pattern from --all, --branches, --tags,
+    /// --remotes and --glob. Unlike in Git it applies to all of them, no
+    /// matter the order.
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Exclude the revisions and sets of refs that follow, and include those
+    /// prefixed with ^, up to the next --not
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        action = ArgAction::Append,
+        hide_possible_values = true
+    )]
+    not: Vec<bool>,
+
+    /// Where each revision, set of refs and `--not` was on the command line.
+    /// Clap keeps the values of each argument apart, so this is the only way
+    /// to tell what `--not` applies to.
+    #[arg(skip)]
+    indices: HashMap<&'static str, Vec<usize>>,
+}
+
+impl RefSets {
+    /// Whether any set of refs is given.
+    pub fn is_empty(&self) -> bool {
+        self.all.is_empty()
+            && self.branches.is_empty()
+            && self.tags.is_empty()
+            && self.remotes.is_empty()
+            && self.glob.is_empty()
+    }
+
+    /// Note where the revisions, which are in the `revisions` argument next to
+    /// these, and the sets of refs were among the arguments.
+    pub fn locate(&mut self, matches: &ArgMatches) {
+        for id in [
+            "all",
+            "branches",
+            "tags",
+            "remotes",
+            "glob",
+            "not",
+            "revisions",
+        ] {
+            if let Some(indices) = matches.indices_of(id) {
+                self.indices.insert(id, indices.collect());
+            }
+        }
+    }
+
+    /// Whether the `nth` value of the argument comes after an odd number of
+    /// `--not`.
+    fn negated(&self, id: &str, nth: usize) -> bool {
+        let Some(index) = self.indices.get(id).and_then(|i| i.get(nth)) else {
+            return false;
+        };
+
+        let nots = self.indices.get("not").map_or(&[][..], |n| n.as_slice());
+        nots.iter().filter(|not| *not < index).count() % 2 == 1
+    }
+
+    /// The patterns to match the full names of refs against, the prefix to
+    /// strip before matching the excluded patterns, like in Git, and whether
+    /// the refs are negated.
+    fn patterns(&self) -> Vec<(BString, &'static str, bool)> {
+        let mut patterns = vec![];
+
+        for nth in 0..self.all.len() {
+            patterns.push(("refs/*".into(), "", self.negated("all", nth)));
+        }
+
+        for (id, globs, prefix) in [
+            ("branches", &self.branches, "refs/heads/"),
+            ("tags", &self.tags, "refs/tags/"),
+            ("remotes", &self.remotes, "refs/remotes/"),
+            ("glob", &self.glob, "refs/"),
+        ] {
+            for (nth, glob) in globs.iter().enumerate() {
+                let strip = if id == "glob" { "" } else { prefix };
+                patterns.push((normalize_glob(prefix, glob), strip, self.negated(id, nth)));
+            }
+        }
+
+        patterns
+    }
+}
+
+/// Prefix the pattern, unless it already starts with `refs/`, and make it
+/// match everything below it if it has no wildcards, like `--glob` does in
+/// Git.
+fn normalize_glob(prefix: &str, glob: &str) -> BString {
+    let mut pattern = if glob.starts_with("refs/") && prefix == "refs/" {
+        glob.to_owned()
+    } else {
+        format!("{prefix}{glob}")
+    };
+
+    if !pattern.contains(['*', '?', '[']) {
+        if !pattern.ends_with('/') {
+            pattern.push('/');
+        }
+        pattern.push('*');
+    }
+
+    pattern.into()
+}
+
+fn matches(pattern: &BStr, name: &BStr) -> bool {
+    gix::glob::wildmatch(pattern, name, gix::glob::wildmatch::Mode::empty())
+}
+
+/// Exclude what the spec includes and the other way around, like `--not`.
+fn negate(spec: Spec, revision: &str) -> anyhow::Result<Spec> {
+    Ok(match spec {
+        Spec::Include(id) => Spec::Exclude(id),
+        Spec::Exclude(id) => Spec::Include(id),
+        // A..B turns into ^B A, which is B..A
+        Spec::Range { from, to } => Spec::Range { from: to, to: from },
+        _ => anyhow::bail!("can't use {revision} after --not"),
+    })
+}
+
+/// Resolve the revisions and sets of refs to what to include in and exclude
+/// from a walk. Without either, the walk starts at `HEAD`.
+pub fn specs(
+    repo: &Repository,
+    revisions: &[String],
+    ref_sets: &RefSets,
+) -> anyhow::Result<Vec<Spec>> {
+    let mut specs = vec![];
+
+    for (nth, revision) in revisions.iter().enumerate() {
+        let spec = repo.rev_parse(revision.as_str())?.detach();
+        specs.push(if ref_sets.negated("revisions", nth) {
+            negate(spec, revision)?
+        } else {
+            spec
+        });
+    }
+
+    for nth in 0..ref_sets.all.len() {
+        if let Ok(head) = repo.head_id() {
+            specs.push(if ref_sets.negated("all", nth) {
+                Spec::Exclude(head.detach())
+            } else {
+                Spec::Include(head.detach())
+            });
+        }
+    }
+
+    let patterns = ref_sets.patterns();
+
+    if !patterns.is_empty() {
+        for reference in repo.references()?.all()? {
+            let mut reference = reference.map_err(|e| anyhow::anyhow!(e))?;
+            let name = reference.name().as_bstr().to_owned();
+
+            let matching = patterns
+                .iter()
+                .filter(|(pattern, prefix, _)| {
+                    let short_name = name.strip_prefix(prefix.as_bytes()).unwrap_or(&name[..]);
+                    matches(pattern.as_bstr(), name.as_bstr())
+                        && !ref_sets
+                            .exclude
+                            .iter()
+                            .any(|e| matches(e.as_bytes().as_bstr(), short_name.as_bstr()))
+                })
+                .map(|(_, _, negated)| *negated)
+                .collect::<Vec<_>>();
+
+            if matching.is_empty() {
+                continue;
+            }
+
+            // Like Git, skip refs to trees and blobs, they have no history
+            let id = reference.peel_to_id_in_place()?;
+            if let Ok(commit) = crate::get_object(repo, id, object::Kind::Commit) {
+                // A ref in both an included and an excluded set is both, and
+                // excluding wins
+                if matching.contains(&false) {
+                    specs.push(Spec::Include(commit.id));
+                }
+                if matching.contains(&true) {
+                    specs.push(Spec::Exclude(commit.id));
+                }
+            }
+        }
+    }
+
+    if revisions.is_empty() && ref_sets.is_empty() {
+        specs.push(Spec::Include(repo.head_id()?.detach()));
+    }
+
+    Ok(specs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{log, Cli, Command};
+    use clap::CommandFactory;
+    use pretty_assertions::assert_eq;
+    use topo::scratch::Scratch;
+
+    #[test]
+    fn normalizes_globs() {
+        assert_eq!(
+            normalize_glob("refs/heads/", "feature"),
+            "refs/heads/feature/*"
+        );
+        assert_eq!(
+            normalize_glob("refs/heads/", "feature/*"),
+            "refs/heads/feature/*"
+        );
+        assert_eq!(normalize_glob("refs/", "refs/notes/"), "refs/notes/*");
+        assert_eq!(
+            normalize_glob("refs/", "remotes/origin/m?in"),
+            "refs/remotes/origin/m?in"
+        );
+    }
+
+    #[test]
+    fn matches_across_slashes() {
+        assert!(matches(
+            b"refs/heads/*".as_bstr(),
+            b"refs/heads/a/b".as_bstr()
+        ));
+        assert!(!matches(
+            b"refs/tags/*".as_bstr(),
+            b"refs/heads/a".as_bstr()
+        ));
+    }
+
+    #[test]
+    fn logs_like_git() {
+        let scratch = Scratch::borrowing_objects("revs");
+        for (branch, commit) in [
+            ("feature/a", "c57fe89"),
+            ("feature/b", "3be8265"),
+            ("main", "b282e76"),
+        ] {
+            assert!(scratch.git(&["branch", branch, commit]).status.success());
+        }
+        let repo = gix::open(&scratch.dir).unwrap();
+
+        for args in [
+            &["--branches=feature", "^d3baed3"][..],
+            &["c57fe89", "--not", "--branches=main"],
+            &["--not", "--branches=main", "--not", "--all", "^d7d6328"],
+            &["--branches", "--not", "d3baed3..c57fe89"],
+        ] {
+            let matches = Cli::command().get_matches_from(["blamediff", "log"].iter().chain(args));
+            let Command::Log(la) = Cli::from_matches(&matches).unwrap().command else {
+                unreachable!("parsed as log");
+            };
+
+            let mut out = vec![];
+            let specs = specs(&repo, &la.revisions, &la.ref_sets).unwrap();
+            log::log(&mut out, &repo, specs, log::Options::default()).unwrap();
+
+            let expected = scratch
+                .git(
+                    &[
+                        &["-c", "core.abbrev=7", "log", "--date-order"][..],
+                        &["--no-decorate", "--format=medium"],
+                        args,
+                    ]
+                    .concat(),
+                )
+                .stdout;
+
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                String::from_utf8(expected).unwrap(),
+                "{args:?}"
+            );
+        }
+    }
+}
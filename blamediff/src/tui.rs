@@ -0,0 +1,601 @@
+//! An interactive blame browser for the terminal, like `tig blame`.
+//!
+//! The upper pane shows the blame of a file. The lower pane shows the commit
+//! that the selected line is blamed on, and the hunk of that commit's diff
+//! that the line is part of. Pressing enter re-blames the file at the commit
+//! before that one, and going back pops the previous blame off a stack.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, queue,
+    style::{self, Attribute, Color},
+    terminal,
+};
+use gix::{ObjectId, Repository};
+
+use crate::diffprinter::{Segment, StyledDiff, UnifiedDiffBuilder};
+use crate::BlobData;
+
+/// A blame of the file at a particular revision.
+struct View {
+    revision: String,
+    path: PathBuf,
+    lines: Vec<culpa::BlamedLine>,
+    cursor: usize,
+    top: usize,
+}
+
+impl View {
+    fn new(repo: &Repository, revision: &str, path: &Path) -> anyhow::Result<Self> {
+        let blame = culpa::blame_file(repo, revision, culpa::Parents::All, path)?;
+
+        Ok(Self {
+            revision: revision.to_owned(),
+            path: path.to_owned(),
+            lines: blame.blamed_lines(),
+            cursor: 0,
+            top: 0,
+        })
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        let last = self.lines.len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(delta).min(last);
+    }
+
+    fn scroll_into_view(&mut self, height: usize) {
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if height > 0 && self.cursor >= self.top + height {
+            self.top = self.cursor + 1 - height;
+        }
+    }
+}
+
+/// What we show about a commit. Computed once per commit.
+struct CommitDetails {
+    author: String,
+    date: String,
+    summary: String,
+    header: Vec<String>,
+    diff: StyledDiff,
+}
+
+impl CommitDetails {
+    fn new(repo: &Repository, id: ObjectId, path: &Path) -> anyhow::Result<Self> {
+        let commit = repo.find_object(id)?.into_commit();
+        let parent_ids = commit
+            .parent_ids()
+            .map(|id| id.detach())
+            .collect::<Vec<_>>();
+
+        let author = commit.author()?;
+        let date = author
+            .time
+            .format(time::macros::format_description!("[year]-[month]-[day]"));
+
+        Ok(Self {
+            author: author.name.to_string(),
+            date,
+            summary: commit.message()?.summary().to_string(),
+            header: crate::log::header_lines(
+                repo,
+                &topo::Info {
+                    id,
+                    parent_ids: parent_ids.iter().copied().collect(),
+                    side: None,
+                    boundary: false,
+                    details: None,
+                },
+            )?,
+            diff: diff_of_path(repo, id, parent_ids.first().copied(), path)?,
+        })
+    }
+
+    /// The hunk of the diff that contains the given line of the new version,
+    /// counting from 1.
+    fn hunk(&self, line_no: u32) -> &[(Segment, String)] {
+        let lines = &self.diff.0;
+
+        let start = lines.iter().position(|(segment, line)| {
+            *segment == Segment::Frag
+                && hunk_range(line)
+                    .is_some_and(|(start, len)| start <= line_no && line_no < start + len.max(1))
+        });
+
+        match start {
+            Some(start) => {
+                let len = lines[start + 1..]
+                    .iter()
+                    .take_while(|(segment, _)| !matches!(segment, Segment::Frag | Segment::Meta))
+                    .count();
+                &lines[start..start + 1 + len]
+            }
+            None => &[],
+        }
+    }
+}
+
+/// Parse the new side of a hunk header like `@@ -1,3 +1,4 @@`.
+fn hunk_range(header: &str) -> Option<(u32, u32)> {
+    let new = header.split_whitespace().nth(2)?.strip_prefix('+')?;
+    let (start, len) = new.split_once(',').unwrap_or((new, "1"));
+
+    Some((start.parse().ok()?, len.parse().ok()?))
+}
+
+/// Diff the file at `path` between `parent_id`, if any, and `id`.
+fn diff_of_path(
+    repo: &Repository,
+    id: ObjectId,
+    parent_id: Option<ObjectId>,
+    path: &Path,
+) -> anyhow::Result<StyledDiff> {
+    let mut buf = Vec::new();
+
+    let mut blob = |id: Option<ObjectId>| -> anyhow::Result<Option<(ObjectId, Vec<u8>)>> {
+        let Some(id) = id else {
+            return Ok(None);
+        };
+
+        let entry = repo
+            .find_object(id)?
+            .peel_to_tree()?
+            .lookup_entry_by_path(path, &mut buf)?;
+
+        match entry {
+            Some(entry) => Ok(Some((entry.object_id(), entry.object()?.detach().data))),
+            None => Ok(None),
+        }
+    };
+
+    let new = blob(Some(id))?;
+    let old = blob(parent_id)?;
+
+    let path = gix::path::into_bstr(path);
+    let blob_data = |blob: &Option<(ObjectId, Vec<u8>)>| match blob {
+        Some((id, _)) => BlobData {
+            path: path.as_ref(),
+            id: *id,
+        },
+        None => crate::DEV_NULL,
+    };
+
+    let old_file = old
+        .as_ref()
+        .map_or(Ok(""), |(_, d)| std::str::from_utf8(d))?;
+    let new_file = new
+        .as_ref()
+        .map_or(Ok(""), |(_, d)| std::str::from_utf8(d))?;
+
+    let input = gix::diff::blob::intern::InternedInput::new(old_file, new_file);
+
+    Ok(gix::diff::blob::diff(
+        gix::diff::blob::Algorithm::Histogram,
+        &input,
+        UnifiedDiffBuilder::new(&input, blob_data(&old), blob_data(&new)),
+    ))
+}
+
+/// The contents of the file at `path` in the commit `id`.
+fn file_at(repo: &Repository, id: ObjectId, path: &Path) -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+
+    let entry = repo
+        .find_object(id)?
+        .peel_to_tree()?
+        .lookup_entry_by_path(path, &mut buf)?
+        .with_context(|| format!("{} doesn't exist in {id}", path.display()))?;
+
+    Ok(String::from_utf8(entry.object()?.detach().data)?)
+}
+
+/// Where the line `line_no` of `new`, counting from 1, is in `old`, counting
+/// from 0. Like in tig, that's the start of the change that the line is part
+/// of, or the same line moved by the changes above it.
+fn line_in_old(old: &str, new: &str, line_no: u32) -> usize {
+    let line = line_no.saturating_sub(1);
+    let input = gix::diff::blob::intern::InternedInput::new(old, new);
+
+    let mut changes = vec![];
+    gix::diff::blob::diff(
+        gix::diff::blob::Algorithm::Histogram,
+        &input,
+        |before: Range<u32>, after: Range<u32>| changes.push((before, after)),
+    );
+
+    let mut offset = 0;
+    for (before, after) in changes {
+        if after.contains(&line) {
+            return before.start as usize;
+        }
+        if after.start > line {
+            break;
+        }
+        offset += before.len() as i64 - after.len() as i64;
+    }
+
+    (line as i64 + offset).max(0) as usize
+}
+
+struct App<'repo> {
+    repo: &'repo Repository,
+    view: View,
+    stack: Vec<View>,
+    details: HashMap<(ObjectId, PathBuf), CommitDetails>,
+    status: String,
+}
+
+impl App<'_> {
+    fn details(&mut self, id: ObjectId) -> anyhow::Result<&CommitDetails> {
+        let key = (id, self.view.path.clone());
+
+        if !self.details.contains_key(&key) {
+            let details = CommitDetails::new(self.repo, id, &self.view.path)?;
+            self.details.insert(key.clone(), details);
+        }
+
+        Ok(&self.details[&key])
+    }
+
+    /// Blame the file as it was before the commit the selected line is blamed
+    /// on, keeping the current view on the stack. Like in tig, that's in the
+    /// commit and at the path culpa calls `previous`, and the cursor goes to
+    /// where the change that added the line starts.
+    fn blame_parent(&mut self) {
+        let Some(line) = self.view.lines.get(self.view.cursor) else {
+            return;
+        };
+
+        if line.boundary {
+            self.status = format!("{} is a boundary commit", line.id.to_hex_with_len(8));
+            return;
+        }
+
+        let Some((previous_id, previous_path)) = line.previous.clone() else {
+            self.status = format!(
+                "{} has no previous version of the file",
+                line.id.to_hex_with_len(8)
+            );
+            return;
+        };
+
+        let (id, orig_line_no) = (line.id, line.orig_line_no);
+        let revision = previous_id.to_string();
+
+        let view = View::new(self.repo, &revision, &previous_path).and_then(|mut view| {
+            let old = file_at(self.repo, previous_id, &previous_path)?;
+            let new = file_at(self.repo, id, &self.view.path)?;
+            let last = view.lines.len().saturating_sub(1);
+
+            view.cursor = line_in_old(&old, &new, orig_line_no).min(last);
+            Ok(view)
+        });
+
+        match view {
+            Ok(view) => {
+                self.stack.push(std::mem::replace(&mut self.view, view));
+                self.status.clear();
+            }
+            Err(e) => {
+                self.status = format!("Cannot blame {revision}: {e}");
+            }
+        }
+    }
+
+    fn go_back(&mut self) {
+        match self.stack.pop() {
+            Some(view) => {
+                self.view = view;
+                self.status.clear();
+            }
+            None => self.status = "Already at the first blame".to_owned(),
+        }
+    }
+
+    fn run(&mut self, out: &mut impl Write) -> anyhow::Result<()> {
+        loop {
+            self.draw(out)?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+
+            let (_, height) = terminal::size()?;
+            let page = (height as isize / 2).max(1);
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('j') | KeyCode::Down => self.view.move_cursor(1),
+                KeyCode::Char('k') | KeyCode::Up => self.view.move_cursor(-1),
+                KeyCode::PageDown | KeyCode::Char(' ') => self.view.move_cursor(page),
+                KeyCode::PageUp | KeyCode::Char('b') => self.view.move_cursor(-page),
+                KeyCode::Home | KeyCode::Char('g') => self.view.cursor = 0,
+                KeyCode::End | KeyCode::Char('G') => self.view.move_cursor(isize::MAX),
+                KeyCode::Enter | KeyCode::Char(',') => self.blame_parent(),
+                KeyCode::Backspace | KeyCode::Char('<') => self.go_back(),
+                _ => (),
+            }
+        }
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> anyhow::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+
+        // Blame on top, then a title bar, the details and a status line
+        let blame_height = height.saturating_sub(2) * 3 / 5;
+        let details_height = height.saturating_sub(blame_height + 2);
+
+        self.view.scroll_into_view(blame_height);
+
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+
+        let visible = self.view.top..(self.view.top + blame_height).min(self.view.lines.len());
+
+        for (row, idx) in visible.enumerate() {
+            let line = &self.view.lines[idx];
+            let (id, boundary, line_no, text) =
+                (line.id, line.boundary, line.line_no, line.line.clone());
+            let details = self.details(id)?;
+
+            let text = format!(
+                "{}{} {:<15.15} {} {line_no:>4} {}",
+                if boundary { "^" } else { " " },
+                id.to_hex_with_len(8),
+                details.author,
+                details.date,
+                text.replace('\t', "    "),
+            );
+
+            queue!(out, cursor::MoveTo(0, row as u16))?;
+            if idx == self.view.cursor {
+                queue!(out, style::SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                out,
+                style::Print(truncate(&text, width)),
+                style::SetAttribute(Attribute::Reset)
+            )?;
+        }
+
+        let mut title = String::new();
+        let mut details_lines = vec![];
+
+        if let Some(line) = self.view.lines.get(self.view.cursor) {
+            let (id, orig_line_no) = (line.id, line.orig_line_no);
+            let details = self.details(id)?;
+
+            title = format!("{} {}", id.to_hex_with_len(8), details.summary);
+
+            details_lines.extend(
+                details
+                    .header
+                    .iter()
+                    .map(|l| (Segment::Context, l.clone()))
+                    .chain(std::iter::once((Segment::Context, String::new())))
+                    .chain(details.hunk(orig_line_no).iter().cloned()),
+            );
+        }
+
+        queue!(
+            out,
+            cursor::MoveTo(0, blame_height as u16),
+            style::SetAttribute(Attribute::Reverse),
+            style::Print(format!("{:width$}", truncate(&title, width))),
+            style::SetAttribute(Attribute::Reset),
+        )?;
+
+        for (row, (segment, line)) in details_lines.iter().take(details_height).enumerate() {
+            let color = match segment {
+                Segment::Meta => Color::Reset,
+                Segment::Frag => Color::Cyan,
+                Segment::Context => Color::Reset,
+                Segment::Old => Color::Red,
+                Segment::New => Color::Green,
+            };
+
+            queue!(
+                out,
+                cursor::MoveTo(0, (blame_height + 1 + row) as u16),
+                style::SetForegroundColor(color),
+                style::Print(truncate(&line.replace('\t', "    "), width)),
+                style::ResetColor,
+            )?;
+        }
+
+        let status = format!(
+            "{}:{} [{}/{}] {}",
+            self.view.revision,
+            self.view.path.display(),
+            self.view.cursor + 1,
+            self.view.lines.len(),
+            self.status,
+        );
+
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1) as u16),
+            style::Print(truncate(&status, width)),
+        )?;
+
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+fn truncate(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}
+
+/// Browse the blame of `path` at `revision` interactively.
+pub fn run(repo: &Repository, revision: &str, path: &Path) -> anyhow::Result<()> {
+    let view = View::new(repo, revision, path)?;
+
+    let mut app = App {
+        repo,
+        view,
+        stack: vec![],
+        details: HashMap::new(),
+        status: String::new(),
+    };
+
+    let mut stdout = std::io::stdout();
+
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+    let result = app.run(&mut stdout);
+
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn view(lines: usize) -> View {
+        View {
+            revision: "HEAD".to_owned(),
+            path: PathBuf::from("lorem-ipsum.txt"),
+            lines: (1..=lines)
+                .map(|n| culpa::BlamedLine {
+                    id: ObjectId::null(gix::hash::Kind::Sha1),
+                    boundary: false,
+                    line_no: n,
+                    orig_line_no: n as u32,
+                    previous: None,
+                    line: String::new(),
+                })
+                .collect(),
+            cursor: 0,
+            top: 0,
+        }
+    }
+
+    #[test]
+    fn moves_cursor_within_lines() {
+        let mut v = view(10);
+
+        v.move_cursor(-1);
+        assert_eq!(v.cursor, 0);
+        v.move_cursor(3);
+        assert_eq!(v.cursor, 3);
+        v.move_cursor(isize::MAX);
+        assert_eq!(v.cursor, 9);
+        v.move_cursor(-100);
+        assert_eq!(v.cursor, 0);
+
+        let mut v = view(0);
+        v.move_cursor(5);
+        assert_eq!(v.cursor, 0);
+    }
+
+    #[test]
+    fn scrolls_cursor_into_view() {
+        let mut v = view(100);
+
+        v.cursor = 30;
+        v.scroll_into_view(10);
+        assert_eq!(v.top, 21);
+
+        v.cursor = 5;
+        v.scroll_into_view(10);
+        assert_eq!(v.top, 5);
+
+        v.cursor = 14;
+        v.scroll_into_view(10);
+        assert_eq!(v.top, 5);
+    }
+
+    #[test]
+    fn finds_hunks() {
+        assert_eq!(hunk_range("@@ -1,3 +4,5 @@"), Some((4, 5)));
+        assert_eq!(hunk_range("@@ -1 +2 @@ fn main()"), Some((2, 1)));
+        assert_eq!(hunk_range("diff --git a/x b/x"), None);
+
+        let line = |segment, text: &str| (segment, text.to_owned());
+        let details = CommitDetails {
+            author: String::new(),
+            date: String::new(),
+            summary: String::new(),
+            header: vec![],
+            diff: StyledDiff(vec![
+                line(Segment::Meta, "diff --git a/x b/x"),
+                line(Segment::Frag, "@@ -1,2 +1,2 @@"),
+                line(Segment::Old, "-a"),
+                line(Segment::New, "+b"),
+                line(Segment::Context, " c"),
+                line(Segment::Frag, "@@ -10,0 +10,1 @@"),
+                line(Segment::New, "+d"),
+            ]),
+        };
+
+        assert_eq!(details.hunk(2), &details.diff.0[1..5]);
+        assert_eq!(details.hunk(10), &details.diff.0[5..]);
+        assert!(details.hunk(5).is_empty());
+    }
+
+    #[test]
+    fn finds_lines_in_old_version() {
+        let (old, new) = ("a\nb\nc\nd\n", "a\nx\ny\nb\nd\n");
+
+        assert_eq!(line_in_old(old, new, 1), 0);
+        assert_eq!(line_in_old(old, new, 3), 1);
+        assert_eq!(line_in_old(old, new, 4), 1);
+        assert_eq!(line_in_old(old, new, 5), 3);
+        assert_eq!(line_in_old("a\nb\nc\n", "a\nB\nc\n", 2), 1);
+    }
+
+    #[test]
+    fn blames_previous_and_goes_back() {
+        let repo = gix::discover(".").unwrap();
+        let view = View::new(&repo, "3be8265", Path::new("lorem-ipsum.txt")).unwrap();
+
+        let mut app = App {
+            repo: &repo,
+            view,
+            stack: vec![],
+            details: HashMap::new(),
+            status: String::new(),
+        };
+
+        app.go_back();
+        assert_eq!(app.status, "Already at the first blame");
+
+        let cursor = app.view.lines.iter().position(|l| !l.boundary).unwrap();
+        let (previous, _) = app.view.lines[cursor].previous.clone().unwrap();
+        app.view.cursor = cursor;
+
+        app.blame_parent();
+        assert_eq!(app.status, "");
+        assert_eq!(app.view.revision, previous.to_string());
+        assert_eq!(app.stack.len(), 1);
+
+        app.go_back();
+        assert_eq!(app.view.revision, "3be8265");
+        assert_eq!(app.view.cursor, cursor);
+        assert!(app.stack.is_empty());
+
+        app.view.lines[cursor].boundary = true;
+        app.blame_parent();
+        assert!(app.status.ends_with("is a boundary commit"));
+        assert!(app.stack.is_empty());
+    }
+}
@@ -0,0 +1,640 @@
+//! Git's line diff, the Myers algorithm with the heuristics of xdiff and the
+//! sliding of changes that follows it, for when a diff has to be the same as
+//! Git's line for line, like for patch-ids.
+
+use gix::diff::blob::intern::Token;
+
+/// Stop looking for a smaller diff when the edit cost is this high, unless
+/// the files are so large that the square root of their lengths is higher
+const MAX_COST_MIN: isize = 256;
+/// Look for a good enough place to split when the edit cost is this high
+const HEURISTIC_MIN_COST: isize = 256;
+/// How long a run of matching lines must be to be a good place to split
+const SNAKE_COUNT: isize = 20;
+/// How much further along than the edit cost a good place to split must be
+const K_HEURISTIC: isize = 4;
+/// Lines that match at least this many lines in the other file, or the
+/// square root of its length if lower, can be left out of the search
+const MAX_EQUAL_LIMIT: usize = 1024;
+/// How far to look around a line that matches many others for lines without
+/// any matches
+const SIMILAR_SCAN_WINDOW: usize = 100;
+/// How many times more lines without matches than lines with many matches
+/// there must be around a line with many matches to leave it out
+const KEEP_DISCARDED_RUN: usize = 4;
+
+/// Which lines of `before` and `after` changed, the way Git's Myers diff
+/// without the indent heuristic sees it.
+pub fn diff(before: &[Token], after: &[Token], num_tokens: u32) -> (Vec<bool>, Vec<bool>) {
+    let mut before = File::new(before);
+    let mut after = File::new(after);
+
+    let mut counts = vec![[0; 2]; num_tokens as usize];
+    for line in before.lines {
+        counts[line.0 as usize][0] += 1;
+    }
+    for line in after.lines {
+        counts[line.0 as usize][1] += 1;
+    }
+
+    // Lines both files start and end with are left alone
+    let start = before
+        .lines
+        .iter()
+        .zip(after.lines)
+        .take_while(|(b, a)| b == a)
+        .count();
+    let end = before.lines[start..]
+        .iter()
+        .rev()
+        .zip(after.lines[start..].iter().rev())
+        .take_while(|(b, a)| b == a)
+        .count();
+
+    before.discard(start..before.lines.len() - end, |line| {
+        counts[line.0 as usize][1]
+    });
+    after.discard(start..after.lines.len() - end, |line| {
+        counts[line.0 as usize][0]
+    });
+
+    let kept1 = 0..before.kept.len() as isize;
+    let kept2 = 0..after.kept.len() as isize;
+    Myers::new(&before, &after).compare(&mut before, kept1, &mut after, kept2, false);
+
+    before.compact(&after);
+    after.compact(&before);
+
+    (before.changed, after.changed)
+}
+
+/// The lines of one side of a diff.
+struct File<'a> {
+    lines: &'a [Token],
+    changed: Vec<bool>,
+    /// The indices of the lines left to compare after discarding those that
+    /// can't match or match too many others
+    kept: Vec<usize>,
+    /// The lines at those indices
+    kept_lines: Vec<Token>,
+}
+
+impl<'a> File<'a> {
+    fn new(lines: &'a [Token]) -> Self {
+        Self {
+            lines,
+            changed: vec![false; lines.len()],
+            kept: vec![],
+            kept_lines: vec![],
+        }
+    }
+
+    /// Mark the lines in `range` that don't match any line in the other file
+    /// as changed, along with those that match many lines and are among lines
+    /// without matches, and keep the rest to compare.
+    fn discard(&mut self, range: std::ops::Range<usize>, matches: impl Fn(Token) -> usize) {
+        let limit = bogo_sqrt(self.lines.len()).min(MAX_EQUAL_LIMIT);
+
+        let mut discards = vec![Discard::NoMatch; self.lines.len()];
+        for i in range.clone() {
+            discards[i] = match matches(self.lines[i]) {
+                0 => Discard::NoMatch,
+                n if n >= limit => Discard::ManyMatches,
+                _ => Discard::Keep,
+            };
+        }
+
+        let Some(last) = range.end.checked_sub(1) else {
+            return;
+        };
+
+        for i in range.clone() {
+            let keep = match discards[i] {
+                Discard::Keep => true,
+                Discard::ManyMatches => !among_unmatched(&discards, i, range.start, last),
+                Discard::NoMatch => false,
+            };
+
+            if keep {
+                self.kept.push(i);
+                self.kept_lines.push(self.lines[i]);
+            } else {
+                self.changed[i] = true;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Discard {
+    NoMatch,
+    Keep,
+    ManyMatches,
+}
+
+/// Whether the line at `i`, which matches many lines, is among enough lines
+/// without any matches to leave it out, looking no further than `first` and
+/// `last`.
+fn among_unmatched(discards: &[Discard], i: usize, first: usize, last: usize) -> bool {
+    let first = first.max(i.saturating_sub(SIMILAR_SCAN_WINDOW));
+    let last = last.min(i + SIMILAR_SCAN_WINDOW);
+
+    let count = |lines: &mut dyn Iterator<Item = usize>| {
+        let (mut unmatched, mut many) = (0, 1);
+        for j in lines {
+            match discards[j] {
+                Discard::NoMatch => unmatched += 1,
+                Discard::ManyMatches => many += 1,
+                Discard::Keep => break,
+            }
+        }
+        (unmatched, many)
+    };
+
+    let (unmatched_before, many_before) = count(&mut (first..i).rev());
+    if unmatched_before == 0 {
+        return false;
+    }
+    let (unmatched_after, many_after) = count(&mut (i + 1..=last));
+    if unmatched_after == 0 {
+        return false;
+    }
+
+    let many = many_before + many_after;
+    many * KEEP_DISCARDED_RUN < many + unmatched_before + unmatched_after
+}
+
+/// Git's approximation of the square root, a power of two.
+fn bogo_sqrt(mut n: usize) -> usize {
+    let mut root = 1;
+    while n > 0 {
+        root <<= 1;
+        n >>= 2;
+    }
+    root
+}
+
+/// Where to split the files in two to compare the halves separately, and
+/// whether to look for the smallest diff in each half.
+struct Split {
+    before: isize,
+    after: isize,
+    minimal_low: bool,
+    minimal_high: bool,
+}
+
+/// The furthest reaching paths along each diagonal, forwards and backwards.
+struct Myers {
+    forward: Vec<isize>,
+    backward: Vec<isize>,
+    /// The index of diagonal 0
+    offset: isize,
+    max_cost: isize,
+}
+
+impl Myers {
+    fn new(before: &File<'_>, after: &File<'_>) -> Self {
+        let diagonals = before.kept.len() + after.kept.len() + 3;
+        Self {
+            forward: vec![0; diagonals],
+            backward: vec![0; diagonals],
+            offset: after.kept.len() as isize + 1,
+            max_cost: (bogo_sqrt(diagonals) as isize).max(MAX_COST_MIN),
+        }
+    }
+
+    fn f(&mut self, d: isize) -> &mut isize {
+        &mut self.forward[(d + self.offset) as usize]
+    }
+
+    fn b(&mut self, d: isize) -> &mut isize {
+        &mut self.backward[(d + self.offset) as usize]
+    }
+
+    /// Mark the lines that changed between the kept lines of `before` in
+    /// `range1` and of `after` in `range2`.
+    fn compare(
+        &mut self,
+        before: &mut File<'_>,
+        range1: std::ops::Range<isize>,
+        after: &mut File<'_>,
+        range2: std::ops::Range<isize>,
+        minimal: bool,
+    ) {
+        let (mut off1, mut lim1) = (range1.start, range1.end);
+        let (mut off2, mut lim2) = (range2.start, range2.end);
+        let line1 = |i: isize| before.kept_lines[i as usize];
+        let line2 = |i: isize| after.kept_lines[i as usize];
+
+        while off1 < lim1 && off2 < lim2 && line1(off1) == line2(off2) {
+            off1 += 1;
+            off2 += 1;
+        }
+        while off1 < lim1 && off2 < lim2 && line1(lim1 - 1) == line2(lim2 - 1) {
+            lim1 -= 1;
+            lim2 -= 1;
+        }
+
+        if off1 == lim1 {
+            for i in off2..lim2 {
+                after.changed[after.kept[i as usize]] = true;
+            }
+        } else if off2 == lim2 {
+            for i in off1..lim1 {
+                before.changed[before.kept[i as usize]] = true;
+            }
+        } else {
+            let split = self.split(
+                &before.kept_lines,
+                off1..lim1,
+                &after.kept_lines,
+                off2..lim2,
+                minimal,
+            );
+            self.compare(
+                before,
+                off1..split.before,
+                after,
+                off2..split.after,
+                split.minimal_low,
+            );
+            self.compare(
+                before,
+                split.before..lim1,
+                after,
+                split.after..lim2,
+                split.minimal_high,
+            );
+        }
+    }
+
+    /// Find the middle snake of the shortest edit script, or a good enough
+    /// place to split when that's too expensive to find.
+    fn split(
+        &mut self,
+        ha1: &[Token],
+        range1: std::ops::Range<isize>,
+        ha2: &[Token],
+        range2: std::ops::Range<isize>,
+        minimal: bool,
+    ) -> Split {
+        let (off1, lim1) = (range1.start, range1.end);
+        let (off2, lim2) = (range2.start, range2.end);
+        let line1 = |i: isize| ha1[i as usize];
+        let line2 = |i: isize| ha2[i as usize];
+
+        let (dmin, dmax) = (off1 - lim2, lim1 - off2);
+        let (fmid, bmid) = (off1 - off2, lim1 - lim2);
+        let odd = (fmid - bmid) & 1 != 0;
+        let (mut fmin, mut fmax) = (fmid, fmid);
+        let (mut bmin, mut bmax) = (bmid, bmid);
+
+        *self.f(fmid) = off1;
+        *self.b(bmid) = lim1;
+
+        for cost in 1.. {
+            let mut got_snake = false;
+
+            // Extend the diagonals searched by one in each direction, or
+            // shrink them when they'd leave the box
+            if fmin > dmin {
+                fmin -= 1;
+                *self.f(fmin - 1) = -1;
+            } else {
+                fmin += 1;
+            }
+            if fmax < dmax {
+                fmax += 1;
+                *self.f(fmax + 1) = -1;
+            } else {
+                fmax -= 1;
+            }
+
+            for d in (fmin..=fmax).rev().step_by(2) {
+                let mut i1 = if *self.f(d - 1) >= *self.f(d + 1) {
+                    *self.f(d - 1) + 1
+                } else {
+                    *self.f(d + 1)
+                };
+                let start = i1;
+                let mut i2 = i1 - d;
+                while i1 < lim1 && i2 < lim2 && line1(i1) == line2(i2) {
+                    i1 += 1;
+                    i2 += 1;
+                }
+                if i1 - start > SNAKE_COUNT {
+                    got_snake = true;
+                }
+                *self.f(d) = i1;
+                if odd && bmin <= d && d <= bmax && *self.b(d) <= i1 {
+                    return Split {
+                        before: i1,
+                        after: i2,
+                        minimal_low: true,
+                        minimal_high: true,
+                    };
+                }
+            }
+
+            if bmin > dmin {
+                bmin -= 1;
+                *self.b(bmin - 1) = isize::MAX;
+            } else {
+                bmin += 1;
+            }
+            if bmax < dmax {
+                bmax += 1;
+                *self.b(bmax + 1) = isize::MAX;
+            } else {
+                bmax -= 1;
+            }
+
+            for d in (bmin..=bmax).rev().step_by(2) {
+                let mut i1 = if *self.b(d - 1) < *self.b(d + 1) {
+                    *self.b(d - 1)
+                } else {
+                    *self.b(d + 1) - 1
+                };
+                let start = i1;
+                let mut i2 = i1 - d;
+                while i1 > off1 && i2 > off2 && line1(i1 - 1) == line2(i2 - 1) {
+                    i1 -= 1;
+                    i2 -= 1;
+                }
+                if start - i1 > SNAKE_COUNT {
+                    got_snake = true;
+                }
+                *self.b(d) = i1;
+                if !odd && fmin <= d && d <= fmax && i1 <= *self.f(d) {
+                    return Split {
+                        before: i1,
+                        after: i2,
+                        minimal_low: true,
+                        minimal_high: true,
+                    };
+                }
+            }
+
+            if minimal {
+                continue;
+            }
+
+            // Past the heuristic's threshold, split at a long run of
+            // matching lines that got far enough
+            if got_snake && cost > HEURISTIC_MIN_COST {
+                let mut best = None;
+                let mut best_value = 0;
+                for d in (fmin..=fmax).rev().step_by(2) {
+                    let dd = (d - fmid).abs();
+                    let i1 = *self.f(d);
+                    let i2 = i1 - d;
+                    let value = (i1 - off1) + (i2 - off2) - dd;
+
+                    if value > K_HEURISTIC * cost
+                        && value > best_value
+                        && off1 + SNAKE_COUNT <= i1
+                        && i1 < lim1
+                        && off2 + SNAKE_COUNT <= i2
+                        && i2 < lim2
+                        && (1..=SNAKE_COUNT).all(|k| line1(i1 - k) == line2(i2 - k))
+                    {
+                        best_value = value;
+                        best = Some((i1, i2));
+                    }
+                }
+                if let Some((i1, i2)) = best {
+                    return Split {
+                        before: i1,
+                        after: i2,
+                        minimal_low: true,
+                        minimal_high: false,
+                    };
+                }
+
+                for d in (bmin..=bmax).rev().step_by(2) {
+                    let dd = (d - bmid).abs();
+                    let i1 = *self.b(d);
+                    let i2 = i1 - d;
+                    let value = (lim1 - i1) + (lim2 - i2) - dd;
+
+                    if value > K_HEURISTIC * cost
+                        && value > best_value
+                        && off1 < i1
+                        && i1 <= lim1 - SNAKE_COUNT
+                        && off2 < i2
+                        && i2 <= lim2 - SNAKE_COUNT
+                        && (0..SNAKE_COUNT).all(|k| line1(i1 + k) == line2(i2 + k))
+                    {
+                        best_value = value;
+                        best = Some((i1, i2));
+                    }
+                }
+                if let Some((i1, i2)) = best {
+                    return Split {
+                        before: i1,
+                        after: i2,
+                        minimal_low: false,
+                        minimal_high: true,
+                    };
+                }
+            }
+
+            // Give up and take the path that got the furthest
+            if cost >= self.max_cost {
+                let (mut fbest, mut fbest1) = (-1, -1);
+                for d in (fmin..=fmax).rev().step_by(2) {
+                    let mut i1 = (*self.f(d)).min(lim1);
+                    let mut i2 = i1 - d;
+                    if lim2 < i2 {
+                        i1 = lim2 + d;
+                        i2 = lim2;
+                    }
+                    if fbest < i1 + i2 {
+                        fbest = i1 + i2;
+                        fbest1 = i1;
+                    }
+                }
+
+                let (mut bbest, mut bbest1) = (isize::MAX, isize::MAX);
+                for d in (bmin..=bmax).rev().step_by(2) {
+                    let mut i1 = off1.max(*self.b(d));
+                    let mut i2 = i1 - d;
+                    if i2 < off2 {
+                        i1 = off2 + d;
+                        i2 = off2;
+                    }
+                    if i1 + i2 < bbest {
+                        bbest = i1 + i2;
+                        bbest1 = i1;
+                    }
+                }
+
+                return if (lim1 + lim2) - bbest < fbest - (off1 + off2) {
+                    Split {
+                        before: fbest1,
+                        after: fbest - fbest1,
+                        minimal_low: true,
+                        minimal_high: false,
+                    }
+                } else {
+                    Split {
+                        before: bbest1,
+                        after: bbest - bbest1,
+                        minimal_low: false,
+                        minimal_high: true,
+                    }
+                };
+            }
+        }
+
+        unreachable!("the search ends by the time it covers both files")
+    }
+}
+
+/// A run of changed lines, or the place between two lines where one would be.
+#[derive(Clone, Copy)]
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl File<'_> {
+    fn is_changed(&self, line: usize) -> bool {
+        self.changed.get(line).copied().unwrap_or(false)
+    }
+
+    fn first_group(&self) -> Group {
+        let mut group = Group { start: 0, end: 0 };
+        while self.is_changed(group.end) {
+            group.end += 1;
+        }
+        group
+    }
+
+    fn next_group(&self, group: &mut Group) -> bool {
+        if group.end == self.lines.len() {
+            return false;
+        }
+        group.start = group.end + 1;
+        group.end = group.start;
+        while self.is_changed(group.end) {
+            group.end += 1;
+        }
+        true
+    }
+
+    fn previous_group(&self, group: &mut Group) -> bool {
+        if group.start == 0 {
+            return false;
+        }
+        group.end = group.start - 1;
+        group.start = group.end;
+        while group.start > 0 && self.is_changed(group.start - 1) {
+            group.start -= 1;
+        }
+        true
+    }
+
+    fn slide_down(&mut self, group: &mut Group) -> bool {
+        if group.end < self.lines.len() && self.lines[group.start] == self.lines[group.end] {
+            self.changed[group.start] = false;
+            self.changed[group.end] = true;
+            group.start += 1;
+            group.end += 1;
+            while self.is_changed(group.end) {
+                group.end += 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn slide_up(&mut self, group: &mut Group) -> bool {
+        if group.start > 0 && self.lines[group.start - 1] == self.lines[group.end - 1] {
+            group.start -= 1;
+            group.end -= 1;
+            self.changed[group.start] = true;
+            self.changed[group.end] = false;
+            while group.start > 0 && self.is_changed(group.start - 1) {
+                group.start -= 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Slide each group of changed lines as far down as it goes, merging it
+    /// with the groups it runs into, unless it can line up with a group of
+    /// changes in the other file.
+    fn compact(&mut self, other: &File<'_>) {
+        let mut group = self.first_group();
+        let mut other_group = other.first_group();
+
+        loop {
+            if group.start != group.end {
+                let mut earliest_end;
+                let mut matches_other;
+
+                loop {
+                    let size = group.end - group.start;
+
+                    while self.slide_up(&mut group) {
+                        assert!(other.previous_group(&mut other_group), "groups out of step");
+                    }
+                    earliest_end = group.end;
+                    matches_other = other_group.start != other_group.end;
+
+                    while self.slide_down(&mut group) {
+                        assert!(other.next_group(&mut other_group), "groups out of step");
+                        matches_other |= other_group.start != other_group.end;
+                    }
+
+                    if size == group.end - group.start {
+                        break;
+                    }
+                }
+
+                if group.end != earliest_end && matches_other {
+                    while other_group.start == other_group.end {
+                        assert!(self.slide_up(&mut group), "lost the matching group");
+                        assert!(other.previous_group(&mut other_group), "groups out of step");
+                    }
+                }
+            }
+
+            if !self.next_group(&mut group) {
+                break;
+            }
+            assert!(other.next_group(&mut other_group), "groups out of step");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gix::diff::blob::intern::InternedInput;
+    use gix::diff::blob::sources::byte_lines_with_terminator;
+
+    fn changed(before: &[u8], after: &[u8]) -> (Vec<bool>, Vec<bool>) {
+        let input = InternedInput::new(
+            byte_lines_with_terminator(before),
+            byte_lines_with_terminator(after),
+        );
+        diff(&input.before, &input.after, input.interner.num_tokens())
+    }
+
+    #[test]
+    fn slides_changes_down() {
+        assert_eq!(
+            changed(b"a\nb\n", b"a\nb\na\nb\n"),
+            (vec![false; 2], vec![false, false, true, true])
+        );
+        assert_eq!(
+            changed(b"x\n\n\ny\n", b"x\n\ny\n"),
+            (vec![false, false, true, false], vec![false; 3])
+        );
+    }
+}
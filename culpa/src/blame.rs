@@ -1,4 +1,8 @@
-use std::{collections::HashMap, ops::Range, path::Path};
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 use gix::{
     diff::blob::{diff, intern::InternedInput, Algorithm},
@@ -40,6 +44,11 @@ pub struct BlamedLine {
     /// The line number of the line in the revision that introduced it
     pub orig_line_no: u32,
 
+    /// The commit before the one to blame that has the file, and the path of
+    /// the file there, like `previous` in `git blame --porcelain`. Lines
+    /// blamed on boundary commits have none.
+    pub previous: Option<(ObjectId, PathBuf)>,
+
     /// The line contents themselves
     pub line: String,
 }
@@ -50,6 +59,9 @@ pub struct BlamedLine {
 #[derive(Debug)]
 pub struct Blame {
     ids: Vec<(bool, u32, ObjectId)>,
+    unblamable: Vec<usize>,
+    previous: HashMap<ObjectId, ObjectId>,
+    path: PathBuf,
     contents: String,
 }
 
@@ -60,6 +72,14 @@ impl Blame {
         &self.ids
     }
 
+    /// Returns the line numbers, like [`BlamedLine::line_no`], of the lines
+    /// that couldn't be blamed any further because objects are missing, like
+    /// in a partial clone. These are blamed on the last commit they could be
+    /// followed to, as if it were a boundary commit.
+    pub fn unblamable_lines(&self) -> &[usize] {
+        &self.unblamable
+    }
+
     /// Returns a list of [`BlamedLine`]s.
     pub fn blamed_lines(&self) -> Vec<BlamedLine> {
         self.ids
@@ -71,6 +91,7 @@ impl Blame {
                     boundary: *boundary,
                     line_no: line_no + 1,
                     orig_line_no: *orig_line_no + 1,
+                    previous: self.previous.get(id).map(|p| (*p, self.path.clone())),
                     line: line.to_owned(),
                 },
             )
@@ -91,6 +112,7 @@ struct IncompleteBlame {
     blamed_lines2: Vec<Option<Line>>,
     total_range: Range<u32>,
     line_trackers: HashMap<ObjectId, LineTracker>,
+    unblamable: Vec<u32>,
     contents: String,
 }
 
@@ -107,6 +129,7 @@ impl IncompleteBlame {
             blamed_lines2: vec![None; lines],
             total_range: total_range,
             line_trackers: line_mappings,
+            unblamable: vec![],
             contents,
         }
     }
@@ -168,6 +191,24 @@ impl IncompleteBlame {
         }
     }
 
+    /// Assign the lines that are still tracked to this commit, because the
+    /// objects needed to follow them further are missing.
+    fn assign_as_unblamable(&mut self, id: ObjectId) {
+        let line_tracker = self.line_trackers.get(&id).expect("have line mapping");
+        let lines = self
+            .total_range
+            .clone()
+            .filter(|l| {
+                line_tracker.get_old_line(*l).is_some() && self.blamed_lines2[*l as usize].is_none()
+            })
+            .collect::<Vec<_>>();
+
+        for l in lines {
+            self.raw_assign(l..l + 1, true, id);
+            self.unblamable.push(l);
+        }
+    }
+
     fn process(&mut self, ranges: &[BeforeAfter], id: ObjectId) {
         for BeforeAfter { before: _, after } in ranges.iter().cloned() {
             let line_tracker = self.line_trackers.get(&id).expect("have line mapping");
@@ -183,7 +224,7 @@ impl IncompleteBlame {
             && !self.blamed_lines2.iter().any(|o| o.is_none())
     }
 
-    fn finish(self) -> Blame {
+    fn finish(self, previous: HashMap<ObjectId, ObjectId>, path: PathBuf) -> Blame {
         let ids = self
             .blamed_lines2
             .iter()
@@ -193,25 +234,76 @@ impl IncompleteBlame {
             })
             .collect::<Vec<_>>();
 
+        let mut unblamable = self
+            .unblamable
+            .iter()
+            .map(|l| *l as usize + 1)
+            .collect::<Vec<_>>();
+        unblamable.sort();
+
         Blame {
             ids,
+            unblamable,
+            previous,
+            path,
             contents: self.contents,
         }
     }
 }
 
-fn tree_entry(
+/// Look up the entry at the path in a tree, or in the tree of a commit,
+/// following replace refs.
+fn tree_entry<Find>(
     repo: &Repository,
+    replace: &topo::replace::Replace<Find>,
     id: impl Into<ObjectId>,
     path: impl AsRef<Path>,
-) -> Result<Option<object::tree::Entry>> {
+) -> Result<Option<object::tree::Entry>>
+where
+    Find: gix::objs::Find,
+{
     let mut v = Vec::<u8>::new();
-    repo.find_object(id)?
+    repo.find_object(replace.replacement(&id.into()))?
         .peel_to_tree()?
         .lookup_entry_by_path(path, &mut v)
         .map_err(|e| e.into())
 }
 
+/// The first parent of the commit that has the file, like `previous` in `git
+/// blame --porcelain`.
+fn previous_commit<Find>(
+    repo: &Repository,
+    replace: &topo::replace::Replace<Find>,
+    id: ObjectId,
+    parents: Parents,
+    path: &Path,
+) -> Result<Option<ObjectId>>
+where
+    Find: gix::objs::Find,
+{
+    let commit = repo.find_object(replace.replacement(&id))?.into_commit();
+    let limit = match parents {
+        Parents::All => usize::MAX,
+        Parents::First => 1,
+    };
+
+    for parent_id in commit.parent_ids().take(limit) {
+        if tree_entry(repo, replace, parent_id, path)?.is_some() {
+            return Ok(Some(parent_id.detach()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Whether an object couldn't be found, like the blobs in a partial clone.
+fn is_missing_object(err: &error::Error) -> bool {
+    matches!(
+        err,
+        error::Error::FindObject(gix::object::find::existing::Error::NotFound { .. })
+    )
+}
+
 fn diff_tree_entries(
     old: object::tree::Entry,
     new: object::tree::Entry,
@@ -253,26 +345,99 @@ pub fn blame_file(
 ) -> Result<Blame> {
     let range = repo.rev_parse(revision)?.detach();
 
+    blame_file_with_specs(repo, std::iter::once(range), parents, path)
+}
+
+/// Like [`blame_file`], but with revisions that are already parsed, for
+/// example to exclude everything reachable from a set of refs. They must
+/// include exactly one commit to start at, or none to start at `HEAD`, and can
+/// exclude any number of commits. `<commit>^!` is rejected for merges, blame
+/// can't end at all of their parents.
+pub fn blame_file_with_specs(
+    repo: &Repository,
+    specs: impl IntoIterator<Item = gix::revision::plumbing::Spec>,
+    parents: Parents,
+    path: &Path,
+) -> Result<Blame> {
     use gix::revision::plumbing::Spec;
-    let (start, end) = match range {
-        Spec::Include(oid) => (repo.find_object(oid)?, None),
-        Spec::Exclude(oid) => (repo.rev_parse_single("HEAD")?.object()?, Some(oid)),
-        Spec::Range { from, to } => (repo.find_object(to)?, Some(from)),
+
+    let mut specs = specs.into_iter().collect::<Vec<_>>();
+    let mut starts = vec![];
+    let mut end = None;
+
+    for spec in &specs {
+        match *spec {
+            Spec::Include(oid) => starts.push(oid),
+            Spec::Exclude(oid) => end = end.or(Some(oid)),
+            Spec::Range { from, to } => {
+                starts.push(to);
+                end = end.or(Some(from));
+            }
+            Spec::ExcludeParents(oid) => {
+                // Blame can only end at a single commit, so not at all the
+                // parents of a merge
+                let commit = repo.find_object(oid)?.into_commit();
+                let mut parent_ids = commit.parent_ids();
+                match (parent_ids.next(), parent_ids.next()) {
+                    (parent_id, None) => {
+                        starts.push(oid);
+                        end = end.or(parent_id.map(|id| id.detach()));
+                    }
+                    _ => return Err(error::Error::InvalidRange),
+                }
+            }
+            Spec::IncludeOnlyParents(oid) => {
+                // Blame can only start from a single commit
+                let commit = repo.find_object(oid)?.into_commit();
+                let mut parent_ids = commit.parent_ids();
+                match (parent_ids.next(), parent_ids.next()) {
+                    (Some(parent_id), None) => starts.push(parent_id.detach()),
+                    _ => return Err(error::Error::InvalidRange),
+                }
+            }
+            _ => return Err(error::Error::InvalidRange),
+        }
+    }
+
+    let start_id = match starts.as_slice() {
+        [] => {
+            let head = repo.rev_parse_single("HEAD")?.detach();
+            specs.push(Spec::Include(head));
+            head
+        }
+        [start] => *start,
         _ => return Err(error::Error::InvalidRange),
     };
 
-    let rev_walker = topo::Builder::from_specs(&repo.objects, std::iter::once(range))
-        .with_commit_graph(repo.commit_graph().ok())
+    // Replace refs can change the parents of commits, and in a shallow clone
+    // the history ends at the shallow commits. Like Git, don't use the
+    // commit-graph in either case, it would still have the original parents.
+    let replace = topo::replace::Replace::at(&repo.objects, repo.common_dir())?;
+    let shallow = topo::shallow::Shallow::at(replace.clone(), repo.git_dir())?;
+    let use_commit_graph = replace.is_empty() && !shallow.is_shallow();
+
+    let rev_walker = topo::Builder::from_specs(shallow, specs)
+        .with_commit_graph(repo.commit_graph().ok().filter(|_| use_commit_graph))
+        .with_changed_paths(
+            topo::bloom::ChangedPaths::at(repo.objects.store_ref().path().join("info"))
+                .ok()
+                .filter(|_| use_commit_graph),
+        )
         .sorting(topo::Sorting::TopoOrder)
+        .boundary(true)
+        .with_details(true)
         .parents(match parents {
             Parents::First => topo::Parents::First,
             Parents::All => topo::Parents::All,
         })
+        .paths(Some(
+            gix::path::to_unix_separators_on_windows(gix::path::into_bstr(path)).into_owned(),
+        ))
         .build()?;
 
     let mut buf = Vec::<u8>::new();
-    let start_id = start.id;
-    let blob = start
+    let blob = repo
+        .find_object(replace.replacement(&start_id))?
         .peel_to_tree()?
         .lookup_entry_by_path(path, &mut buf)?
         .ok_or(std::io::Error::from(std::io::ErrorKind::NotFound))?
@@ -283,15 +448,31 @@ pub fn blame_file(
 
     let mut blame_state = IncompleteBlame::new(contents, start_id);
 
-    let commits = rev_walker
-        .collect::<std::result::Result<Vec<_>, _>>()
-        .expect("Able to collect all history");
+    let (boundaries, commits): (Vec<_>, Vec<_>) = rev_walker
+        .collect::<std::result::Result<Vec<_>, _>>()?
+        .into_iter()
+        .partition(|c| c.boundary);
+
+    // The walk skips commits that don't change the file, possibly including
+    // the one we start at. The file is the same in the first one it returns.
+    let first_id = commits.first().map_or(end.unwrap_or(start_id), |c| c.id);
+    if first_id != start_id {
+        if let Some(line_tracker) = blame_state.line_trackers.remove(&start_id) {
+            blame_state.line_trackers.insert(first_id, line_tracker);
+        }
+    }
 
     for commit_info in &commits {
         let commit = commit_info.id;
-        let entry = tree_entry(repo, commit, path)?;
 
-        let line_tracker = blame_state.line_trackers.get(&commit).unwrap().clone();
+        // No lines were followed to this commit, because the objects needed
+        // to follow them from its children are missing
+        let Some(line_tracker) = blame_state.line_trackers.get(&commit).cloned() else {
+            continue;
+        };
+
+        let tree_id = commit_info.details.as_ref().map_or(commit, |d| d.tree_id);
+        let entry = tree_entry(repo, &replace, tree_id, path)?;
 
         match commit_info.parent_ids.len() {
             0 => {
@@ -300,11 +481,18 @@ pub fn blame_file(
             }
             n if n == 1 || matches!(parents, Parents::First) => {
                 let prev_commit = commit_info.parent_ids[0];
-                let prev_entry = tree_entry(repo, prev_commit, path)?;
+                let prev_entry = tree_entry(repo, &replace, prev_commit, path)?;
 
                 match (&entry, prev_entry) {
                     (Some(e), Some(p_e)) if e.object_id() != p_e.object_id() => {
-                        let changes = diff_tree_entries(p_e, e.to_owned(), line_tracker.clone())?;
+                        let changes =
+                            match diff_tree_entries(p_e, e.to_owned(), line_tracker.clone()) {
+                                Err(err) if is_missing_object(&err) => {
+                                    blame_state.assign_as_unblamable(commit);
+                                    continue;
+                                }
+                                changes => changes?,
+                            };
                         blame_state.process(&changes.ranges, commit);
 
                         match blame_state.line_trackers.entry(prev_commit) {
@@ -338,12 +526,18 @@ pub fn blame_file(
                 let mut merge_changes = Vec::with_capacity(n);
 
                 for prev_commit in &commit_info.parent_ids {
-                    let prev_entry = tree_entry(repo, *prev_commit, path)?;
+                    let prev_entry = tree_entry(repo, &replace, *prev_commit, path)?;
 
                     match (&entry, prev_entry) {
                         (Some(e), Some(p_e)) if e.object_id() != p_e.object_id() => {
                             let changes =
-                                diff_tree_entries(p_e, e.to_owned(), line_tracker.clone())?;
+                                match diff_tree_entries(p_e, e.to_owned(), line_tracker.clone()) {
+                                    Err(err) if is_missing_object(&err) => {
+                                        blame_state.assign_as_unblamable(commit);
+                                        break;
+                                    }
+                                    changes => changes?,
+                                };
 
                             match blame_state.line_trackers.entry(*prev_commit) {
                                 std::collections::hash_map::Entry::Occupied(mut o) => {
@@ -378,18 +572,34 @@ pub fn blame_file(
         }
     }
 
-    // Whatever's left assign it to the last (or only) commit. In the case of an
-    // explicit endpoint, assign to that. If we hit the "break" above there is
-    // no rest to assign so this does nothing.
-    if let Some(end) = end {
-        blame_state.assign_as_boundary(end);
-    } else {
-        blame_state.assign_as_boundary(commits.last().expect("At least one commit").id);
+    // Whatever's left assign it to the boundary commit the remaining lines
+    // were tracked to, or failing that the explicit endpoint or the last (or
+    // only) commit, if lines were tracked to it. If we hit the "break" above
+    // there is no rest to assign so this does nothing.
+    let boundary = boundaries
+        .iter()
+        .map(|c| c.id)
+        .chain(end.or(commits.last().map(|c| c.id)))
+        .find(|id| blame_state.line_trackers.contains_key(id));
+    if let Some(id) = boundary {
+        blame_state.assign_as_boundary(id);
+    }
+
+    if !blame_state.is_complete() {
+        return Err(error::Error::Generation);
     }
 
-    if blame_state.is_complete() {
-        Ok(blame_state.finish())
-    } else {
-        Err(error::Error::Generation)
+    let mut previous = HashMap::new();
+    for line in blame_state.blamed_lines2.iter().flatten() {
+        if !line.boundary && !previous.contains_key(&line.id) {
+            let id = previous_commit(repo, &replace, line.id, parents, path)?;
+            previous.insert(line.id, id);
+        }
     }
+    let previous = previous
+        .into_iter()
+        .filter_map(|(id, previous)| Some((id, previous?)))
+        .collect();
+
+    Ok(blame_state.finish(previous, path.to_owned()))
 }
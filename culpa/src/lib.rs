@@ -7,7 +7,8 @@
 //! Currently it is very simple and is lacking in features. It does not handle
 //! renamed files and presumably does poorly with parallel histories. It assumes
 //! all revisions of the blamed file can be interpreted as UTF-8. The only
-//! interface to this crate's functionality is [`blame_file`].
+//! interface to this crate's functionality is [`blame_file`], or
+//! [`blame_file_with_specs`] with revisions that are already parsed.
 //!
 //! [gitoxide]: https://github.com/Byron/gitoxide
 
@@ -63,6 +63,10 @@ pub fn run_git_blame(revision: &str, parents: Parents) -> Vec<BlamedLine> {
                 orig_line_no: s[1].parse().expect("valid"),
                 line_no: s[2].parse().expect("valid"),
                 boundary: c[10].starts_with("boundary"),
+                previous: c[10].strip_prefix("previous ").map(|p| {
+                    let (id, path) = p.split_once(' ').expect("id and path");
+                    (gix::ObjectId::from_str(id).expect("Valid id"), path.into())
+                }),
                 line: c[12][1..].to_owned(),
             }
         })
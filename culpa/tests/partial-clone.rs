@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use pretty_assertions::assert_eq;
+use topo::scratch::Scratch;
+
+const FILE: &str = "lorem-ipsum.txt";
+
+#[test]
+fn blames_partial_clone() {
+    let clone = Scratch::clone(
+        "partial",
+        &[
+            "--filter=blob:none",
+            "--upload-pack=git -c uploadpack.allowFilter=true upload-pack",
+        ],
+    );
+
+    // Only fetch the blob we start at, none of the older ones
+    let blob = format!("d7d6328:{}", FILE);
+    assert!(clone.git(&["cat-file", "-e", &blob]).status.success());
+
+    let r = gix::open(&clone.dir).unwrap();
+
+    for parents in [culpa::Parents::All, culpa::Parents::First] {
+        let blame = culpa::blame_file(&r, "d7d6328", parents, Path::new(FILE)).unwrap();
+        let lines = blame.blamed_lines();
+
+        assert!(lines.iter().all(|l| l.boundary), "flags = {parents:?}");
+        assert_eq!(
+            blame.unblamable_lines(),
+            (1..=lines.len()).collect::<Vec<_>>(),
+            "flags = {parents:?}"
+        );
+    }
+}
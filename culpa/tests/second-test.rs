@@ -185,3 +185,17 @@ blame_test!(t15_11_f7a3a57_d7d6328, "f7a3a57..d7d6328");
 blame_test!(t15_12_392db1b_d7d6328, "392db1b..d7d6328");
 blame_test!(t15_13_bb48275_d7d6328, "bb48275..d7d6328");
 blame_test!(t15_14_c57fe89_d7d6328, "c57fe89..d7d6328");
+
+// Parent shorthands
+blame_test!(t16_d7d6328_only, "d7d6328^!");
+blame_test!(t16_f28f649_only, "f28f649^!");
+
+// A merge has more than one parent to end at
+#[test]
+fn t16_c57fe89_only() {
+    let r = gix::discover(".").unwrap();
+    for parents in [culpa::Parents::All, culpa::Parents::First] {
+        let blame = culpa::blame_file(&r, "c57fe89^!", parents, &Path::new(common::FILE));
+        assert!(matches!(blame, Err(culpa::Error::InvalidRange)));
+    }
+}
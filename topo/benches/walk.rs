@@ -0,0 +1,85 @@
+//! Compare the time it takes to walk the history of this repository with
+//! `git rev-list`, with and without the commit-graph.
+//!
+//! Run with `cargo bench -p topo`. Set `TOPO_BENCH_REPO` to the `.git`
+//! directory of a bigger repository for more meaningful numbers.
+
+use std::{
+    path::PathBuf,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use gix_hash::ObjectId;
+use topo::{Builder, Sorting};
+
+const ROUNDS: u32 = 10;
+
+fn time(mut f: impl FnMut() -> usize) -> (Duration, usize) {
+    let mut count = 0;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        count = f();
+    }
+    (start.elapsed() / ROUNDS, count)
+}
+
+fn main() {
+    let git_dir = std::env::var_os("TOPO_BENCH_REPO")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("../.git"));
+
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("--git-dir")
+            .arg(&git_dir)
+            .args(args)
+            .output()
+            .expect("able to run git")
+            .stdout
+    };
+
+    let head = git(&["rev-parse", "HEAD"]);
+    let head = ObjectId::from_hex(head.trim_ascii_end()).expect("HEAD is a commit");
+
+    let store = gix_odb::at(git_dir.join("objects")).expect("find objects");
+
+    for (sorting, flag) in [
+        (Sorting::DateOrder, "--date-order"),
+        (Sorting::TopoOrder, "--topo-order"),
+    ] {
+        for use_graph in [true, false] {
+            let (topo_time, topo_count) = time(|| {
+                let commit_graph = use_graph
+                    .then(|| gix_commitgraph::at(git_dir.join("objects/info")).ok())
+                    .flatten();
+
+                Builder::from_iters(&store, [head], None::<Vec<ObjectId>>)
+                    .with_commit_graph(commit_graph)
+                    .sorting(sorting)
+                    .build()
+                    .expect("able to start walk")
+                    .map(|info| info.expect("able to walk"))
+                    .count()
+            });
+
+            let config = format!("core.commitGraph={use_graph}");
+            let (git_time, git_count) = time(|| {
+                let output = Command::new("git")
+                    .arg("--git-dir")
+                    .arg(&git_dir)
+                    .args(["-c", &config, "rev-list", flag, "HEAD"])
+                    .output()
+                    .expect("able to run git rev-list")
+                    .stdout;
+                output.iter().filter(|b| **b == b'\n').count()
+            });
+
+            assert_eq!(topo_count, git_count);
+
+            println!(
+                "{flag:<13} graph={use_graph:<5} commits={topo_count:<7} topo={topo_time:>10.2?} git={git_time:>10.2?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Changed-path Bloom filters from commit-graph files, see "Bloom Filter Index"
+//! and "Bloom Filter Data" in `git help gitformat-commit-graph`.
+//!
+//! Each commit in a commit-graph can carry a filter of the paths that changed
+//! compared to its first parent, including their leading directories. If a
+//! path isn't in the filter the commit definitely didn't change it, and the
+//! tree diff can be skipped.
+
+use std::path::{Path, PathBuf};
+
+use gix_hash::oid;
+use gix_object::bstr::BStr;
+
+const SIGNATURE: &[u8] = b"CGPH";
+const BLOOM_INDEX: u32 = u32::from_be_bytes(*b"BIDX");
+const BLOOM_DATA: u32 = u32::from_be_bytes(*b"BDAT");
+
+/// Size of the header at the start of the BDAT chunk
+const DATA_HEADER_LEN: usize = 12;
+
+const SEED_0: u32 = 0x293a_e76f;
+const SEED_1: u32 = 0x7e64_6e2c;
+
+/// The settings Git uses by default when writing filters
+pub(crate) const VERSION: u32 = 2;
+pub(crate) const NUM_HASHES: u32 = 7;
+pub(crate) const BITS_PER_ENTRY: u32 = 10;
+/// Commits that change more paths than this get a filter with all bits set
+pub(crate) const MAX_CHANGED_PATHS: usize = 512;
+
+#[derive(thiserror::Error, Debug)]
+#[allow(missing_docs)]
+pub enum Error {
+    #[error("Could not read {}: {source}", path.display())]
+    Io {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("Error opening commit-graph: {0}")]
+    File(#[from] gix_commitgraph::file::Error),
+    #[error("{} is not a valid commit-graph file: {message}", path.display())]
+    Corrupt {
+        message: &'static str,
+        path: PathBuf,
+    },
+}
+
+/// The changed-path Bloom filters of all commit-graph files in a repository.
+pub struct ChangedPaths {
+    files: Vec<FilterFile>,
+}
+
+/// A single commit-graph file and its filters, if it has any.
+struct FilterFile {
+    graph: gix_commitgraph::File,
+    filters: Option<Filters>,
+}
+
+struct Filters {
+    version: u32,
+    num_hashes: u32,
+    /// Where the filter of each commit ends, relative to the end of the header
+    index: Vec<u32>,
+    data: Vec<u8>,
+}
+
+/// The hashes of a path, for each version of the filters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Key {
+    /// `(hash0, hash1)` for version 1 and 2
+    hashes: [(u32, u32); 2],
+}
+
+impl ChangedPaths {
+    /// Read the filters of the commit-graph files in `info_dir`, which is the
+    /// `objects/info` directory of a repository, like
+    /// [`gix_commitgraph::at()`]. It's not an error if none of the files
+    /// have filters.
+    pub fn at(info_dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let info_dir = info_dir.as_ref();
+        let single = info_dir.join("commit-graph");
+
+        let paths = if single.is_file() {
+            vec![single]
+        } else {
+            let graphs_dir = info_dir.join("commit-graphs");
+            let chain = graphs_dir.join("commit-graph-chain");
+            let chain = std::fs::read_to_string(&chain).map_err(|source| Error::Io {
+                source,
+                path: chain,
+            })?;
+
+            chain
+                .lines()
+                .map(|hash| graphs_dir.join(format!("graph-{}.graph", hash.trim())))
+                .collect()
+        };
+
+        let mut files = vec![];
+        for path in paths {
+            let data = std::fs::read(&path).map_err(|source| Error::Io {
+                source,
+                path: path.clone(),
+            })?;
+            let graph = gix_commitgraph::File::at(&path)?;
+            let filters = Filters::from_graph_file(&data, graph.num_commits())
+                .map_err(|message| Error::Corrupt { message, path })?;
+
+            files.push(FilterFile { graph, filters });
+        }
+
+        Ok(Self { files })
+    }
+
+    /// Whether any of the filters can be used.
+    pub fn is_empty(&self) -> bool {
+        self.files.iter().all(|f| f.filters.is_none())
+    }
+
+    /// Check if the commit might have changed any of the paths compared to
+    /// its first parent. Each path is given as the keys of itself and its
+    /// leading directories, see [`keys()`]. Returns `None` if there is no
+    /// filter for the commit, and `Some(false)` if it definitely didn't
+    /// change them.
+    pub(crate) fn maybe_changed(&self, id: &oid, paths: &[Vec<Key>]) -> Option<bool> {
+        let (filters, filter) = self.filter(id)?;
+
+        // A path has changed only if its leading directories have as well
+        Some(paths.iter().any(|keys| {
+            keys.iter()
+                .all(|key| filters.contains(filter, key.hashes[filters.version as usize - 1]))
+        }))
+    }
+
+    fn filter(&self, id: &oid) -> Option<(&Filters, &[u8])> {
+        for file in &self.files {
+            if let Some(pos) = file.graph.lookup(id) {
+                let filters = file.filters.as_ref()?;
+                let filter = filters.get(pos.0 as usize)?;
+
+                // An empty filter was never computed
+                return (!filter.is_empty()).then_some((filters, filter));
+            }
+        }
+
+        None
+    }
+}
+
+impl Filters {
+    /// Find the BIDX and BDAT chunks in a commit-graph file. Returns `None`
+    /// if the file doesn't have both of them.
+    fn from_graph_file(data: &[u8], num_commits: u32) -> Result<Option<Self>, &'static str> {
+        if data.len() < 8 || &data[..4] != SIGNATURE {
+            return Err("missing signature");
+        }
+
+        let num_chunks = data[6] as usize;
+        let table = data
+            .get(8..8 + (num_chunks + 1) * 12)
+            .ok_or("truncated chunk table")?;
+
+        let chunks = table
+            .chunks_exact(12)
+            .map(|entry| {
+                let id = u32::from_be_bytes(entry[..4].try_into().expect("4 bytes"));
+                let offset = u64::from_be_bytes(entry[4..].try_into().expect("8 bytes"));
+                (id, offset as usize)
+            })
+            .collect::<Vec<_>>();
+
+        let chunk = |wanted| {
+            chunks
+                .windows(2)
+                .find(|w| w[0].0 == wanted)
+                .map(|w| data.get(w[0].1..w[1].1).ok_or("chunk out of bounds"))
+                .transpose()
+        };
+
+        let (Some(index), Some(bloom_data)) = (chunk(BLOOM_INDEX)?, chunk(BLOOM_DATA)?) else {
+            return Ok(None);
+        };
+
+        if index.len() != num_commits as usize * 4 {
+            return Err("wrong size of Bloom filter index");
+        }
+        if bloom_data.len() < DATA_HEADER_LEN {
+            return Err("truncated Bloom filter data");
+        }
+
+        let header = |n: usize| {
+            u32::from_be_bytes(bloom_data[n * 4..n * 4 + 4].try_into().expect("4 bytes"))
+        };
+
+        let version = header(0);
+        if !(1..=2).contains(&version) {
+            // Git ignores filters it doesn't understand, and so do we
+            return Ok(None);
+        }
+
+        let index = index
+            .chunks_exact(4)
+            .map(|n| u32::from_be_bytes(n.try_into().expect("4 bytes")))
+            .collect::<Vec<_>>();
+
+        if index.windows(2).any(|w| w[0] > w[1])
+            || index.last().map_or(0, |l| *l as usize) > bloom_data.len() - DATA_HEADER_LEN
+        {
+            return Err("invalid Bloom filter index");
+        }
+
+        Ok(Some(Self {
+            version,
+            num_hashes: header(1),
+            index,
+            data: bloom_data[DATA_HEADER_LEN..].to_vec(),
+        }))
+    }
+
+    fn get(&self, pos: usize) -> Option<&[u8]> {
+        let end = *self.index.get(pos)? as usize;
+        let start = match pos {
+            0 => 0,
+            _ => self.index[pos - 1] as usize,
+        };
+
+        self.data.get(start..end)
+    }
+
+    fn contains(&self, filter: &[u8], (hash0, hash1): (u32, u32)) -> bool {
+        let bits = filter.len() as u64 * 8;
+
+        (0..self.num_hashes).all(|i| {
+            let hash = hash0.wrapping_add(i.wrapping_mul(hash1));
+            let bit = (hash as u64 % bits) as usize;
+            filter[bit / 8] & (1 << (bit % 8)) != 0
+        })
+    }
+}
+
+/// The keys to look up to check if `path`, which is separated by `/`, might
+/// have changed. That is the path itself and each of its leading directories.
+pub(crate) fn keys(path: &BStr) -> Vec<Key> {
+    let path = path.strip_suffix(b"/").unwrap_or(path);
+
+    path.iter()
+        .enumerate()
+        .filter(|(_, b)| **b == b'/')
+        .map(|(n, _)| &path[..n])
+        .chain(Some(path))
+        .filter(|p| !p.is_empty())
+        .map(Key::new)
+        .collect()
+}
+
+/// Build a filter for the given changed paths, for writing a commit-graph.
+/// Their leading directories are added as well, like in Git.
+pub(crate) fn filter<'a>(paths: impl IntoIterator<Item = &'a BStr>) -> Vec<u8> {
+    let mut keys = paths.into_iter().flat_map(keys).collect::<Vec<_>>();
+    keys.sort_by_key(|k| k.hashes);
+    keys.dedup();
+
+    if keys.len() > MAX_CHANGED_PATHS {
+        return vec![0xff];
+    }
+
+    // An empty filter means it wasn't computed, so use at least one byte
+    let len = ((keys.len() as u32 * BITS_PER_ENTRY + 7) / 8).max(1) as usize;
+    let bits = len as u64 * 8;
+    let mut filter = vec![0; len];
+
+    for key in keys {
+        let (hash0, hash1) = key.hashes[VERSION as usize - 1];
+        for i in 0..NUM_HASHES {
+            let bit = (hash0.wrapping_add(i.wrapping_mul(hash1)) as u64 % bits) as usize;
+            filter[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    filter
+}
+
+impl Key {
+    fn new(path: &[u8]) -> Self {
+        let hashes = |v1| (murmur3(SEED_0, path, v1), murmur3(SEED_1, path, v1));
+
+        Self {
+            hashes: [hashes(true), hashes(false)],
+        }
+    }
+}
+
+/// 32 bit murmur3. Version 1 of the Bloom filters mistakenly sign extends
+/// bytes above 0x7f, like `murmur3_seeded_v1()` in Git.
+fn murmur3(seed: u32, data: &[u8], v1: bool) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let byte = |b: u8| if v1 { b as i8 as u32 } else { b as u32 };
+    let scramble = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+    let mut hash = seed;
+
+    let mut blocks = data.chunks_exact(4);
+    for block in &mut blocks {
+        let k = byte(block[0]) | byte(block[1]) << 8 | byte(block[2]) << 16 | byte(block[3]) << 24;
+
+        hash ^= scramble(k);
+        hash = hash
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let k = tail
+            .iter()
+            .enumerate()
+            .fold(0, |k, (n, b)| k ^ byte(*b) << (n * 8));
+        hash ^= scramble(k);
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from Git's t0095-bloom.sh
+    #[test]
+    fn murmur3_vectors() {
+        assert_eq!(murmur3(0, b"", false), 0x0000_0000);
+        assert_eq!(murmur3(0, b"Hello world!", false), 0x627b_0c2c);
+        assert_eq!(
+            murmur3(0, b"The quick brown fox jumps over the lazy dog", false),
+            0x2e4f_f723
+        );
+        assert_eq!(
+            murmur3(0, &[0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff], false),
+            0xa183_ccfd
+        );
+    }
+
+    #[test]
+    fn empty_key() {
+        let (hash0, hash1) = Key::new(b"").hashes[1];
+        let hashes = (0..7)
+            .map(|i| hash0.wrapping_add(i * hash1))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            hashes,
+            [
+                0x5615_800c,
+                0x5b96_6560,
+                0x6117_4ab4,
+                0x6698_3008,
+                0x6c19_155c,
+                0x7199_fab0,
+                0x771a_e004
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_include_leading_directories() {
+        assert_eq!(
+            keys("a/b/c".into()),
+            [Key::new(b"a"), Key::new(b"a/b"), Key::new(b"a/b/c")]
+        );
+        assert_eq!(keys("a/".into()), [Key::new(b"a")]);
+    }
+
+    #[test]
+    fn contains_added_keys() {
+        let filters = Filters {
+            version: 2,
+            num_hashes: 7,
+            index: vec![],
+            data: vec![],
+        };
+
+        let mut filter = [0u8; 8];
+        let key = Key::new(b"lorem-ipsum.txt").hashes[1];
+        for i in 0..7 {
+            let bit = key.0.wrapping_add(i * key.1) as usize % 64;
+            filter[bit / 8] |= 1 << (bit % 8);
+        }
+
+        assert!(filters.contains(&filter, key));
+        assert!(filters.contains(&super::filter(Some("lorem-ipsum.txt".into())), key));
+        assert!(!filters.contains(&[0; 8], key));
+        assert!(filters.contains(&[0xff], Key::new(b"anything").hashes[1]));
+    }
+}
@@ -0,0 +1,193 @@
+//! Boundary commits, like `git rev-list --boundary`. These are the commits
+//! that aren't returned by the walk, but are parents of commits that are. They
+//! are returned after the rest of the walk, marked with [`Info::boundary`].
+//!
+//! Like in Git's `get_revision_internal()` they are collected in the order
+//! they are found, and sorted like the walk once it's done.
+
+use std::{cmp::Reverse, collections::VecDeque};
+
+use gix_hash::{oid, ObjectId};
+use gix_revwalk::{graph::IdMap, PriorityQueue};
+
+use super::{Error, Info, Sorting, Visit, Walk};
+
+#[derive(Default)]
+pub(crate) struct Boundary {
+    /// Parents of returned commits, in the order they were found
+    candidates: Vec<ObjectId>,
+    /// Commits that have been returned, or added to `candidates`
+    seen: IdMap<()>,
+    returned: IdMap<()>,
+    /// The boundary commits to return, once the walk is done
+    commits: Option<VecDeque<Info>>,
+}
+
+impl<Find, Predicate, Prune> Walk<Find, Predicate, Prune>
+where
+    Find: gix_object::Find,
+    Prune: FnMut(&oid) -> Visit,
+{
+    /// The next boundary commit, if the walk keeps track of them and is done
+    /// returning the other commits.
+    pub(crate) fn next_boundary(&mut self) -> Option<Result<Info, Error>> {
+        let boundary = self.boundary.as_mut()?;
+
+        if boundary.commits.is_none() {
+            match self.sort_boundary() {
+                Ok(commits) => {
+                    self.boundary.as_mut()?.commits = Some(commits);
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        self.boundary
+            .as_mut()?
+            .commits
+            .as_mut()?
+            .pop_front()
+            .map(Ok)
+    }
+
+    /// Remember a commit that is returned, and its parents as possible
+    /// boundary commits.
+    pub(crate) fn record_for_boundary(&mut self, info: &Info) {
+        let Some(boundary) = &mut self.boundary else {
+            return;
+        };
+
+        boundary.returned.insert(info.id, ());
+        boundary.seen.insert(info.id, ());
+
+        for parent_id in &info.parent_ids {
+            if boundary.seen.insert(*parent_id, ()).is_none() {
+                boundary.candidates.push(*parent_id);
+            }
+        }
+    }
+
+    /// Sort the boundary commits like the rest of the walk, considering only
+    /// the parents among them, like `sort_in_topological_order()` in Git.
+    fn sort_boundary(&mut self) -> Result<VecDeque<Info>, Error> {
+        let boundary = self.boundary.as_mut().expect("only called with a boundary");
+        let candidates = std::mem::take(&mut boundary.candidates);
+        let returned = std::mem::take(&mut boundary.returned);
+
+        let mut infos = IdMap::default();
+        let mut order = vec![];
+
+        for id in &candidates {
+            if returned.contains_key(id) {
+                continue;
+            }
+
+            let parent_ids = self
+                .collect_parents(id)?
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+
+            let (_, time) = self.gen_and_commit_time(id)?;
+            let key = self.topo_queue_key(id, time)?;
+
+            order.push(*id);
+            infos.insert(
+                *id,
+                (
+                    key,
+                    Info {
+                        id: *id,
+                        parent_ids,
+                        side: None,
+                        boundary: true,
+                        details: None,
+                    },
+                ),
+            );
+        }
+
+        let mut indegrees = IdMap::from_iter(order.iter().map(|id| (*id, 1)));
+        for (_, info) in infos.values() {
+            for parent_id in &info.parent_ids {
+                if let Some(i) = indegrees.get_mut(parent_id) {
+                    *i += 1;
+                }
+            }
+        }
+
+        let mut queue = BoundaryQueue::new(self.sorting);
+
+        for id in &order {
+            if indegrees[id] == 1 {
+                queue.push(infos[id].0, *id);
+            }
+        }
+
+        // Git's stack pops in the order the commits were added at first
+        queue.reverse();
+
+        let mut sorted = VecDeque::new();
+
+        while let Some(id) = queue.pop() {
+            let (_, info) = infos
+                .remove(&id)
+                .expect("all queued commits are boundaries");
+
+            for parent_id in &info.parent_ids {
+                let Some(i) = indegrees.get_mut(parent_id) else {
+                    continue;
+                };
+
+                *i -= 1;
+
+                if *i == 1 {
+                    queue.push(infos[parent_id].0, *parent_id);
+                }
+            }
+
+            sorted.push_back(info);
+        }
+
+        Ok(sorted)
+    }
+}
+
+/// Like [`Queue`](super::Queue), but breaks ties in the order commits are
+/// added, like Git's `prio_queue`.
+enum BoundaryQueue {
+    Date(PriorityQueue<(i64, Reverse<usize>), ObjectId>, usize),
+    Topo(Vec<ObjectId>),
+}
+
+impl BoundaryQueue {
+    fn new(sorting: Sorting) -> Self {
+        match sorting {
+            Sorting::DateOrder | Sorting::AuthorDateOrder => Self::Date(PriorityQueue::new(), 0),
+            Sorting::TopoOrder => Self::Topo(vec![]),
+        }
+    }
+
+    fn push(&mut self, key: i64, id: ObjectId) {
+        match self {
+            Self::Date(q, counter) => {
+                q.insert((key, Reverse(*counter)), id);
+                *counter += 1;
+            }
+            Self::Topo(q) => q.push(id),
+        }
+    }
+
+    fn pop(&mut self) -> Option<ObjectId> {
+        match self {
+            Self::Date(q, _) => q.pop().map(|(_, id)| id),
+            Self::Topo(q) => q.pop(),
+        }
+    }
+
+    fn reverse(&mut self) {
+        if let Self::Topo(q) = self {
+            q.reverse();
+        }
+    }
+}
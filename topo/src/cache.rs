@@ -0,0 +1,123 @@
+//! The commit data a walk needs, decoded once per commit, like the commit slab
+//! in Git.
+//!
+//! Without a commit-graph every lookup of a commit's parents, time or tree
+//! means finding and decoding it again, and the walk looks up most commits
+//! several times: as a parent in each of the explore, indegree and topo walks,
+//! and again when computing generation numbers and simplifying history.
+
+use gix_hash::{oid, ObjectId};
+use gix_revwalk::graph::IdMap;
+use smallvec::SmallVec;
+
+use super::{find, Either, Error, GenAndCommitTime};
+
+/// What the walk needs to know about a commit.
+pub(crate) struct CommitData {
+    /// All parents, regardless of [`Parents`](super::Parents)
+    pub(crate) parents: SmallVec<[ObjectId; 2]>,
+    /// The generation number is
+    /// [`GENERATION_NUMBER_INFINITY`](gix_commitgraph::GENERATION_NUMBER_INFINITY)
+    /// if the commit isn't in the commit-graph
+    pub(crate) gen_time: GenAndCommitTime,
+    pub(crate) tree_id: ObjectId,
+}
+
+#[derive(Default)]
+pub(crate) struct Commits(IdMap<CommitData>);
+
+impl Commits {
+    /// The data of the commit, decoding it if it hasn't been already.
+    pub(crate) fn get<Find>(
+        &mut self,
+        cache: Option<&gix_commitgraph::Graph>,
+        f: &Find,
+        id: &oid,
+        buf: &mut Vec<u8>,
+    ) -> Result<&CommitData, Error>
+    where
+        Find: gix_object::Find,
+    {
+        if !self.0.contains_key(id) {
+            let data = decode(cache, f, id, buf)?;
+            self.0.insert(id.to_owned(), data);
+        }
+
+        Ok(&self.0[id])
+    }
+
+    /// The parents of the commit, with their generation numbers and commit
+    /// times, the same as [`collect_parents()`](super::collect_parents).
+    pub(crate) fn parents<Find>(
+        &mut self,
+        cache: Option<&gix_commitgraph::Graph>,
+        f: &Find,
+        id: &oid,
+        first_only: bool,
+        buf: &mut Vec<u8>,
+    ) -> Result<SmallVec<[(ObjectId, GenAndCommitTime); 1]>, Error>
+    where
+        Find: gix_object::Find,
+    {
+        let parents = self.get(cache, f, id, buf)?.parents.clone();
+        let n = if first_only { 1 } else { parents.len() };
+
+        parents
+            .into_iter()
+            .take(n)
+            .map(|parent_id| Ok((parent_id, self.get(cache, f, &parent_id, buf)?.gen_time)))
+            .collect()
+    }
+}
+
+fn decode<Find>(
+    cache: Option<&gix_commitgraph::Graph>,
+    f: &Find,
+    id: &oid,
+    buf: &mut Vec<u8>,
+) -> Result<CommitData, Error>
+where
+    Find: gix_object::Find,
+{
+    match find(cache, f, id, buf)? {
+        Either::CachedCommit(c) => {
+            let graph = cache.expect("cache exists if CachedCommit was returned");
+            let parents = c
+                .iter_parents()
+                .map(|pos| Ok(graph.commit_at(pos?).id().to_owned()))
+                .collect::<Result<_, Error>>()?;
+
+            Ok(CommitData {
+                parents,
+                gen_time: (c.generation(), c.committer_timestamp() as i64),
+                tree_id: c.root_tree_id().to_owned(),
+            })
+        }
+        Either::CommitRefIter(c) => {
+            let mut tree_id = None;
+            let mut parents = SmallVec::new();
+
+            for token in c {
+                use gix_object::commit::ref_iter::Token as T;
+                match token? {
+                    T::Tree { id } => tree_id = Some(id),
+                    T::Parent { id } => parents.push(id),
+                    T::Author { .. } => continue,
+                    T::Committer { signature } => {
+                        return Ok(CommitData {
+                            parents,
+                            gen_time: (
+                                gix_commitgraph::GENERATION_NUMBER_INFINITY,
+                                signature.time.seconds,
+                            ),
+                            tree_id: tree_id.ok_or(Error::MissingTree)?,
+                        });
+                    }
+                    _ => break,
+                }
+            }
+
+            Err(Error::MissingSignature)
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Finding the commits on both sides of a symmetric difference that make the
+//! same change, like `git rev-list --cherry-mark`. Commits are compared by
+//! their patch-ids, hashes of the changes they make that don't depend on
+//! where they're applied. Computing those needs diffs, so they are given by
+//! the caller.
+
+use gix_hash::ObjectId;
+use gix_revwalk::graph::IdMap;
+
+use super::{Info, Side};
+
+/// The commits among `infos` that have an equivalent on the other side of the
+/// symmetric difference, with the same patch-id.
+///
+/// Like in Git, the patch-ids of the side with the fewest commits are
+/// computed first, and those of the other side only if there are any. Merges
+/// and commits that aren't on either side aren't compared, and neither are
+/// commits for which `patch_id` gives `None`.
+pub fn equivalent<E>(
+    infos: &[Info],
+    mut patch_id: impl FnMut(&Info) -> Result<Option<ObjectId>, E>,
+) -> Result<IdMap<()>, E> {
+    let side = |side| {
+        infos
+            .iter()
+            .filter(move |info| info.side == Some(side) && info.parent_ids.len() <= 1)
+            .collect::<Vec<_>>()
+    };
+
+    let (left, right) = (side(Side::Left), side(Side::Right));
+    let (fewest, most) = if left.len() <= right.len() {
+        (left, right)
+    } else {
+        (right, left)
+    };
+
+    let mut equivalent = IdMap::default();
+
+    if fewest.is_empty() {
+        return Ok(equivalent);
+    }
+
+    let mut commits_by_patch_id = IdMap::<Vec<ObjectId>>::default();
+    for info in fewest {
+        if let Some(id) = patch_id(info)? {
+            commits_by_patch_id.entry(id).or_default().push(info.id);
+        }
+    }
+
+    for info in most {
+        let Some(id) = patch_id(info)? else {
+            continue;
+        };
+
+        if let Some(commits) = commits_by_patch_id.get(&id) {
+            equivalent.insert(info.id, ());
+            equivalent.extend(commits.iter().map(|id| (*id, ())));
+        }
+    }
+
+    Ok(equivalent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    fn info(n: u8, side: Option<Side>, parents: usize) -> Info {
+        Info {
+            id: ObjectId::from([n; 20]),
+            parent_ids: (0..parents)
+                .map(|p| ObjectId::from([100 + p as u8; 20]))
+                .collect(),
+            side,
+            boundary: false,
+            details: None,
+        }
+    }
+
+    #[test]
+    fn finds_commits_with_the_same_patch_id() {
+        let infos = [
+            info(1, Some(Side::Left), 1),
+            info(2, Some(Side::Left), 1),
+            info(3, Some(Side::Right), 1),
+            info(4, Some(Side::Right), 1),
+            info(5, Some(Side::Right), 1),
+            // Merges are never equivalent
+            info(6, Some(Side::Left), 2),
+            info(7, Some(Side::Right), 2),
+        ];
+
+        // 1 and 4 make the same change, and so do 6 and 7
+        let patch_ids = [
+            (1, 10),
+            (2, 11),
+            (3, 12),
+            (4, 10),
+            (5, 13),
+            (6, 14),
+            (7, 14),
+        ]
+        .into_iter()
+        .map(|(c, p)| (ObjectId::from([c; 20]), ObjectId::from([p; 20])))
+        .collect::<IdMap<_>>();
+
+        let mut computed = vec![];
+        let equivalent = equivalent(&infos, |info| {
+            computed.push(info.id);
+            Ok::<_, Infallible>(patch_ids.get(&info.id).copied())
+        })
+        .unwrap();
+
+        let mut ids = equivalent.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, [ObjectId::from([1; 20]), ObjectId::from([4; 20])]);
+
+        // The merges are skipped
+        assert_eq!(computed.len(), 5);
+    }
+
+    #[test]
+    fn one_sided_walk_has_no_equivalents() {
+        let infos = [info(1, Some(Side::Right), 1), info(2, None, 1)];
+
+        let equivalent = equivalent(&infos, |_| -> Result<_, Infallible> {
+            panic!("no patch-ids are needed")
+        })
+        .unwrap();
+
+        assert!(equivalent.is_empty());
+    }
+}
@@ -0,0 +1,686 @@
+//! Writing and verifying commit-graph files, like `git commit-graph write
+//! --reachable` and `git commit-graph verify`. See `git help
+//! gitformat-commit-graph` for the format.
+//!
+//! Only a single file is written, including generation data and optionally
+//! changed-path Bloom filters. Split commit-graph chains are read but not
+//! written, and like in Git, writing the single file removes any chain in
+//! `info/commit-graphs` since it would be stale.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use gix_hash::{oid, ObjectId};
+use gix_object::{
+    bstr::{BStr, BString, ByteSlice, ByteVec},
+    tree::EntryMode,
+    FindExt,
+};
+use gix_revwalk::graph::IdMap;
+use smallvec::SmallVec;
+
+use super::{bloom, find, Either};
+
+const OID_FANOUT: &[u8; 4] = b"OIDF";
+const OID_LOOKUP: &[u8; 4] = b"OIDL";
+const COMMIT_DATA: &[u8; 4] = b"CDAT";
+const GENERATION_DATA: &[u8; 4] = b"GDA2";
+const GENERATION_DATA_OVERFLOW: &[u8; 4] = b"GDO2";
+const EXTRA_EDGES: &[u8; 4] = b"EDGE";
+const BLOOM_INDEX: &[u8; 4] = b"BIDX";
+const BLOOM_DATA: &[u8; 4] = b"BDAT";
+
+const PARENT_NONE: u32 = 0x7000_0000;
+const EXTRA_EDGES_NEEDED: u32 = 0x8000_0000;
+const LAST_EXTRA_EDGE: u32 = 0x8000_0000;
+const GENERATION_OVERFLOW: u32 = 0x8000_0000;
+const MAX_GENERATION: u32 = 0x3fff_ffff;
+const MAX_COMMIT_TIME: u64 = 0x3_ffff_ffff;
+
+#[derive(thiserror::Error, Debug)]
+#[allow(missing_docs)]
+pub enum Error {
+    #[error("Could not write {}: {source}", path.display())]
+    Io {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("Error finding object: {0}")]
+    Find(#[from] gix_object::find::existing_iter::Error),
+    #[error("Error decoding object: {0}")]
+    ObjectDecode(#[from] gix_object::decode::Error),
+    #[error("Error opening commit-graph: {0}")]
+    Init(#[from] gix_commitgraph::init::Error),
+    #[error("Commit-graph is invalid: {0}")]
+    Verify(#[from] gix_commitgraph::verify::Error<InvalidCommit>),
+    #[error("Too many commits for a single commit-graph file")]
+    TooManyCommits,
+    #[error("{} exists, another process may be writing the commit-graph", path.display())]
+    Locked { path: PathBuf },
+}
+
+/// A commit whose data in the commit-graph doesn't match the object database.
+#[derive(thiserror::Error, Debug)]
+#[allow(missing_docs)]
+pub enum InvalidCommit {
+    #[error("Commit {id} has root tree {actual} but the commit-graph says {expected}")]
+    Tree {
+        id: ObjectId,
+        expected: ObjectId,
+        actual: ObjectId,
+    },
+    #[error("Commit {id} has different parents than in the commit-graph")]
+    Parents { id: ObjectId },
+    #[error("Commit {id} has commit time {actual} but the commit-graph says {expected}")]
+    CommitTime {
+        id: ObjectId,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Error reading commit: {0}")]
+    Find(#[from] gix_object::find::existing_iter::Error),
+    #[error("Error decoding commit: {0}")]
+    ObjectDecode(#[from] gix_object::decode::Error),
+    #[error("Error reading commit-graph: {0}")]
+    CommitGraphFile(#[from] gix_commitgraph::file::commit::Error),
+}
+
+/// Options for [`write()`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// Compute changed-path Bloom filters, like `git commit-graph write
+    /// --changed-paths`
+    pub changed_paths: bool,
+}
+
+/// What was written by [`write()`]
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    /// The file that was written
+    pub path: PathBuf,
+    /// How many commits it contains
+    pub num_commits: u32,
+}
+
+/// The data of a commit that goes into the commit-graph
+struct Commit {
+    tree: ObjectId,
+    parents: SmallVec<[ObjectId; 1]>,
+    time: u64,
+}
+
+/// Write a commit-graph file with all commits reachable from `tips` to
+/// `info_dir/commit-graph`, where `info_dir` is the `objects/info` directory
+/// of a repository. An existing file is replaced.
+pub fn write<Find>(
+    f: Find,
+    tips: impl IntoIterator<Item = impl Into<ObjectId>>,
+    info_dir: impl AsRef<Path>,
+    options: Options,
+) -> Result<Outcome, Error>
+where
+    Find: gix_object::Find,
+{
+    let mut buf = vec![];
+
+    let mut commits = IdMap::<Commit>::default();
+    let mut stack = tips.into_iter().map(Into::into).collect::<Vec<_>>();
+
+    while let Some(id) = stack.pop() {
+        if commits.contains_key(&id) {
+            continue;
+        }
+
+        let commit = read_commit::<_, Error>(&f, &id, &mut buf)?;
+        stack.extend(commit.parents.iter().copied());
+        commits.insert(id, commit);
+    }
+
+    let mut ids = commits.keys().copied().collect::<Vec<_>>();
+    ids.sort();
+
+    let num_commits = u32::try_from(ids.len())
+        .ok()
+        .filter(|n| *n < PARENT_NONE)
+        .ok_or(Error::TooManyCommits)?;
+
+    let positions = IdMap::from_iter(ids.iter().enumerate().map(|(n, id)| (*id, n as u32)));
+    let (levels, corrected_dates) = generations(&ids, &commits);
+
+    let mut chunks = Vec::<(&[u8; 4], Vec<u8>)>::new();
+
+    let mut fanout = Vec::with_capacity(256 * 4);
+    for byte in 0..=255u8 {
+        let count = ids.partition_point(|id| id.as_bytes()[0] <= byte) as u32;
+        fanout.extend(count.to_be_bytes());
+    }
+    chunks.push((OID_FANOUT, fanout));
+
+    chunks.push((
+        OID_LOOKUP,
+        ids.iter().flat_map(|id| id.as_bytes()).copied().collect(),
+    ));
+
+    let mut commit_data = vec![];
+    let mut extra_edges = Vec::<u32>::new();
+    for id in &ids {
+        let commit = &commits[id];
+        let parents = commit
+            .parents
+            .iter()
+            .map(|p| positions[p])
+            .collect::<Vec<_>>();
+
+        let (parent1, parent2) = match parents.as_slice() {
+            [] => (PARENT_NONE, PARENT_NONE),
+            [p1] => (*p1, PARENT_NONE),
+            [p1, p2] => (*p1, *p2),
+            [p1, rest @ ..] => {
+                let index = EXTRA_EDGES_NEEDED | extra_edges.len() as u32;
+                extra_edges.extend(rest);
+                *extra_edges.last_mut().expect("octopus has parents") |= LAST_EXTRA_EDGE;
+                (*p1, index)
+            }
+        };
+
+        let level = levels[id] as u64;
+        let time = commit.time;
+
+        commit_data.extend(commit.tree.as_bytes());
+        commit_data.extend(parent1.to_be_bytes());
+        commit_data.extend(parent2.to_be_bytes());
+        commit_data.extend(((level << 34) | time).to_be_bytes());
+    }
+    chunks.push((COMMIT_DATA, commit_data));
+
+    let mut generation_data = vec![];
+    let mut overflow = vec![];
+    for id in &ids {
+        let offset = corrected_dates[id] - commits[id].time;
+
+        match u32::try_from(offset) {
+            Ok(offset) if offset & GENERATION_OVERFLOW == 0 => {
+                generation_data.extend(offset.to_be_bytes());
+            }
+            _ => {
+                let index = GENERATION_OVERFLOW | (overflow.len() / 8) as u32;
+                generation_data.extend(index.to_be_bytes());
+                overflow.extend(offset.to_be_bytes());
+            }
+        }
+    }
+    chunks.push((GENERATION_DATA, generation_data));
+    if !overflow.is_empty() {
+        chunks.push((GENERATION_DATA_OVERFLOW, overflow));
+    }
+
+    if !extra_edges.is_empty() {
+        chunks.push((
+            EXTRA_EDGES,
+            extra_edges.iter().flat_map(|e| e.to_be_bytes()).collect(),
+        ));
+    }
+
+    if options.changed_paths {
+        let mut index = vec![];
+        let mut data = vec![];
+        for n in [bloom::VERSION, bloom::NUM_HASHES, bloom::BITS_PER_ENTRY] {
+            data.extend(n.to_be_bytes());
+        }
+
+        let mut paths = vec![];
+        for id in &ids {
+            let commit = &commits[id];
+            let parent_tree = commit.parents.first().map(|p| commits[p].tree);
+
+            paths.clear();
+            changed_paths(
+                &f,
+                parent_tree,
+                Some(commit.tree),
+                BString::default(),
+                &mut paths,
+                &mut buf,
+            )?;
+
+            let filter = if paths.len() > bloom::MAX_CHANGED_PATHS {
+                vec![0xff]
+            } else {
+                bloom::filter(paths.iter().map(|p| p.as_bstr()))
+            };
+
+            data.extend(filter);
+            index.extend(((data.len() - 12) as u32).to_be_bytes());
+        }
+
+        chunks.push((BLOOM_INDEX, index));
+        chunks.push((BLOOM_DATA, data));
+    }
+
+    let mut file = vec![];
+    file.extend(b"CGPH");
+    file.extend([1, 1, chunks.len() as u8, 0]);
+
+    let mut offset = (8 + (chunks.len() + 1) * 12) as u64;
+    for (id, data) in &chunks {
+        file.extend(*id);
+        file.extend(offset.to_be_bytes());
+        offset += data.len() as u64;
+    }
+    file.extend([0; 4]);
+    file.extend(offset.to_be_bytes());
+
+    for (_, data) in chunks {
+        file.extend(data);
+    }
+
+    let mut hasher = gix_features::hash::hasher(gix_hash::Kind::Sha1);
+    hasher.update(&file);
+    file.extend(hasher.digest());
+
+    let info_dir = info_dir.as_ref();
+    let path = info_dir.join("commit-graph");
+    let lock = info_dir.join("commit-graph.lock");
+
+    let io_error = |path: &Path| {
+        let path = path.to_owned();
+        move |source| Error::Io { source, path }
+    };
+
+    std::fs::create_dir_all(info_dir).map_err(io_error(info_dir))?;
+
+    // Like Git, only write if we're the ones who created the lock, and leave
+    // it alone otherwise
+    let mut lock_file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock)
+        .map_err(|source| match source.kind() {
+            std::io::ErrorKind::AlreadyExists => Error::Locked { path: lock.clone() },
+            _ => io_error(&lock)(source),
+        })?;
+
+    let written = lock_file
+        .write_all(&file)
+        .map_err(io_error(&lock))
+        .and_then(|()| std::fs::rename(&lock, &path).map_err(io_error(&path)));
+    if let Err(e) = written {
+        std::fs::remove_file(&lock).ok();
+        return Err(e);
+    }
+
+    remove_split_chain(info_dir)?;
+
+    Ok(Outcome { path, num_commits })
+}
+
+/// Remove the split commit-graph chain in `info_dir`, if any, like Git does
+/// when it writes a single file.
+fn remove_split_chain(info_dir: &Path) -> Result<(), Error> {
+    let dir = info_dir.join("commit-graphs");
+
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(source) => return Err(Error::Io { source, path: dir }),
+    };
+
+    for entry in entries {
+        let path = entry
+            .map_err(|source| Error::Io {
+                source,
+                path: dir.clone(),
+            })?
+            .path();
+
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let in_chain = name == "commit-graph-chain"
+            || (name.starts_with("graph-") && name.ends_with(".graph"));
+
+        if in_chain {
+            std::fs::remove_file(&path).map_err(|source| Error::Io { source, path })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the commit-graph in `info_dir` is valid, and that it agrees
+/// with the object database, like `git commit-graph verify`.
+pub fn verify<Find>(
+    f: Find,
+    info_dir: impl AsRef<Path>,
+) -> Result<gix_commitgraph::verify::Outcome, Error>
+where
+    Find: gix_object::Find,
+{
+    let graph = gix_commitgraph::at(info_dir.as_ref())?;
+    let mut buf = vec![];
+
+    let outcome = graph.verify_integrity(|c| {
+        let id = c.id().to_owned();
+        let commit = read_commit::<_, InvalidCommit>(&f, &id, &mut buf)?;
+
+        if commit.tree.as_ref() != c.root_tree_id() {
+            return Err(InvalidCommit::Tree {
+                id,
+                expected: c.root_tree_id().to_owned(),
+                actual: commit.tree,
+            });
+        }
+
+        let mut parents = SmallVec::<[ObjectId; 1]>::new();
+        for pos in c.iter_parents() {
+            parents.push(graph.id_at(pos?).to_owned());
+        }
+        if parents != commit.parents {
+            return Err(InvalidCommit::Parents { id });
+        }
+
+        if c.committer_timestamp() != commit.time {
+            return Err(InvalidCommit::CommitTime {
+                id,
+                expected: c.committer_timestamp(),
+                actual: commit.time,
+            });
+        }
+
+        Ok(())
+    })?;
+
+    Ok(outcome)
+}
+
+fn read_commit<Find, E>(f: Find, id: &oid, buf: &mut Vec<u8>) -> Result<Commit, E>
+where
+    Find: gix_object::Find,
+    E: From<gix_object::find::existing_iter::Error> + From<gix_object::decode::Error>,
+{
+    let Either::CommitRefIter(iter) = find(None, &f, id, buf)? else {
+        unreachable!("no commit-graph given");
+    };
+
+    let mut commit = Commit {
+        tree: ObjectId::null(id.kind()),
+        parents: SmallVec::new(),
+        time: 0,
+    };
+
+    for token in iter {
+        use gix_object::commit::ref_iter::Token as T;
+        match token? {
+            T::Tree { id } => commit.tree = id,
+            T::Parent { id } => commit.parents.push(id),
+            T::Author { .. } => continue,
+            T::Committer { signature } => {
+                commit.time = (signature.time.seconds.max(0) as u64).min(MAX_COMMIT_TIME);
+                break;
+            }
+            _ => break,
+        }
+    }
+
+    Ok(commit)
+}
+
+/// Topological levels and corrected commit dates of all commits, which are
+/// generation number v1 and v2 respectively.
+fn generations(ids: &[ObjectId], commits: &IdMap<Commit>) -> (IdMap<u32>, IdMap<u64>) {
+    let mut levels = IdMap::<u32>::default();
+    let mut dates = IdMap::<u64>::default();
+
+    for id in ids {
+        let mut stack = vec![*id];
+
+        while let Some(id) = stack.last().copied() {
+            if levels.contains_key(&id) {
+                stack.pop();
+                continue;
+            }
+
+            let commit = &commits[&id];
+            let missing = commit
+                .parents
+                .iter()
+                .filter(|p| !levels.contains_key(*p))
+                .copied()
+                .collect::<Vec<_>>();
+
+            if !missing.is_empty() {
+                stack.extend(missing);
+                continue;
+            }
+
+            let level = commit.parents.iter().map(|p| levels[p]).max().unwrap_or(0) + 1;
+            let date = commit
+                .parents
+                .iter()
+                .map(|p| dates[p] + 1)
+                .max()
+                .unwrap_or(0)
+                .max(commit.time);
+
+            stack.pop();
+            levels.insert(id, level.min(MAX_GENERATION));
+            dates.insert(id, date);
+        }
+    }
+
+    (levels, dates)
+}
+
+/// Collect the paths of the files that differ between two trees, like `git
+/// diff-tree -r`. Stops early if there are more than Bloom filters can hold.
+fn changed_paths<Find>(
+    f: &Find,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+    prefix: BString,
+    paths: &mut Vec<BString>,
+    buf: &mut Vec<u8>,
+) -> Result<(), Error>
+where
+    Find: gix_object::Find,
+{
+    let entries = |tree: Option<ObjectId>, buf: &mut Vec<u8>| -> Result<Vec<_>, Error> {
+        let Some(tree) = tree else {
+            return Ok(vec![]);
+        };
+
+        f.find_tree_iter(&tree, buf)?
+            .map(|e| {
+                e.map(|e| (BString::from(e.filename), e.mode, e.oid.to_owned()))
+                    .map_err(Error::from)
+            })
+            .collect()
+    };
+
+    let old = entries(old, buf)?;
+    let new = entries(new, buf)?;
+
+    let (mut old, mut new) = (old.into_iter().peekable(), new.into_iter().peekable());
+
+    loop {
+        if paths.len() > bloom::MAX_CHANGED_PATHS {
+            return Ok(());
+        }
+
+        let order = match (old.peek(), new.peek()) {
+            (None, None) => return Ok(()),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (Some(o), Some(n)) => tree_order(&o.0, o.1, &n.0, n.1),
+        };
+
+        let (old_entry, new_entry) = match order {
+            std::cmp::Ordering::Less => (old.next(), None),
+            std::cmp::Ordering::Greater => (None, new.next()),
+            std::cmp::Ordering::Equal => (old.next(), new.next()),
+        };
+
+        let name = old_entry
+            .as_ref()
+            .or(new_entry.as_ref())
+            .map(|e| e.0.clone())
+            .expect("at least one entry");
+
+        let mut path = prefix.clone();
+        if !path.is_empty() {
+            path.push(b'/');
+        }
+        path.push_str(&name);
+
+        if let (Some(o), Some(n)) = (&old_entry, &new_entry) {
+            if o.1 == n.1 && o.2 == n.2 {
+                continue;
+            }
+        }
+
+        let tree = |e: &Option<(BString, EntryMode, ObjectId)>| {
+            e.as_ref().filter(|e| e.1.is_tree()).map(|e| e.2)
+        };
+        let leaf =
+            |e: &Option<(BString, EntryMode, ObjectId)>| e.as_ref().is_some_and(|e| !e.1.is_tree());
+
+        if leaf(&old_entry) || leaf(&new_entry) {
+            paths.push(path.clone());
+        }
+
+        let (old_tree, new_tree) = (tree(&old_entry), tree(&new_entry));
+        if old_tree.is_some() || new_tree.is_some() {
+            changed_paths(f, old_tree, new_tree, path, paths, buf)?;
+        }
+    }
+}
+
+/// Git sorts tree entries by name, as if trees had a trailing `/`.
+fn tree_order(a: &BStr, a_mode: EntryMode, b: &BStr, b_mode: EntryMode) -> std::cmp::Ordering {
+    let suffix = |mode: EntryMode| mode.is_tree().then_some(b'/');
+
+    a.iter()
+        .copied()
+        .chain(suffix(a_mode))
+        .cmp(b.iter().copied().chain(suffix(b_mode)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process::Command;
+
+    use crate::scratch::Scratch;
+
+    const TIP: &str = "b282e76b1322e1d26ef002968e1591bd8f22df96";
+
+    #[test]
+    fn git_verifies_written_graph() {
+        let scratch = Scratch::borrowing_objects("verify");
+        let store = gix_odb::at("../.git/objects").expect("find objects");
+        let tip = ObjectId::from_hex(TIP.as_bytes()).unwrap();
+
+        let outcome = write(
+            &store,
+            [tip],
+            scratch.dir.join("objects/info"),
+            Options {
+                changed_paths: true,
+            },
+        )
+        .unwrap();
+
+        let output = scratch.git(&["commit-graph", "verify"]);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let verified = verify(&store, scratch.dir.join("objects/info")).unwrap();
+        assert_eq!(verified.num_commits, outcome.num_commits);
+
+        let count = scratch.git(&["rev-list", "--count", TIP]);
+        assert_eq!(
+            String::from_utf8_lossy(&count.stdout).trim(),
+            outcome.num_commits.to_string()
+        );
+    }
+
+    #[test]
+    fn git_uses_written_bloom_filters() {
+        let scratch = Scratch::borrowing_objects("bloom");
+        let store = gix_odb::at("../.git/objects").expect("find objects");
+        let tip = ObjectId::from_hex(TIP.as_bytes()).unwrap();
+
+        write(
+            &store,
+            [tip],
+            scratch.dir.join("objects/info"),
+            Options {
+                changed_paths: true,
+            },
+        )
+        .unwrap();
+
+        let args = ["log", "--format=%H", TIP, "--", "culpa/src"];
+        let with_filters = scratch.git(&args);
+        let without_graph = Command::new("git")
+            .args(["-c", "core.commitGraph=false"])
+            .args(args)
+            .output()
+            .expect("able to run git log");
+
+        assert_eq!(
+            String::from_utf8_lossy(&with_filters.stdout),
+            String::from_utf8_lossy(&without_graph.stdout)
+        );
+    }
+
+    #[test]
+    fn refuses_to_write_when_locked() {
+        let scratch = Scratch::borrowing_objects("locked");
+        let store = gix_odb::at("../.git/objects").expect("find objects");
+        let tip = ObjectId::from_hex(TIP.as_bytes()).unwrap();
+
+        let info_dir = scratch.dir.join("objects/info");
+        std::fs::write(info_dir.join("commit-graph.lock"), b"").unwrap();
+
+        let result = write(&store, [tip], &info_dir, Options::default());
+        assert!(matches!(result, Err(Error::Locked { .. })));
+        assert!(info_dir.join("commit-graph.lock").exists());
+        assert!(!info_dir.join("commit-graph").exists());
+    }
+
+    #[test]
+    fn replaces_split_chain() {
+        let scratch = Scratch::borrowing_objects("split");
+        let store = gix_odb::at("../.git/objects").expect("find objects");
+        let tip = ObjectId::from_hex(TIP.as_bytes()).unwrap();
+
+        let output = scratch.git(&["update-ref", "refs/heads/main", TIP]);
+        assert!(output.status.success());
+        let output = scratch.git(&["commit-graph", "write", "--reachable", "--split"]);
+        assert!(output.status.success());
+
+        let info_dir = scratch.dir.join("objects/info");
+        let chain_dir = info_dir.join("commit-graphs");
+        assert!(chain_dir.join("commit-graph-chain").exists());
+
+        write(&store, [tip], &info_dir, Options::default()).unwrap();
+
+        let left = std::fs::read_dir(&chain_dir).unwrap().count();
+        assert_eq!(left, 0);
+
+        let output = scratch.git(&["commit-graph", "verify"]);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
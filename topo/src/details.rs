@@ -0,0 +1,94 @@
+//! Details about the commits a walk returns, so callers don't need to look
+//! them up again. These are read from the commit-graph when it's available,
+//! and from the commit itself otherwise.
+
+use gix_commitgraph::GENERATION_NUMBER_INFINITY;
+use gix_hash::{oid, ObjectId};
+
+use super::{Either, Error, Info, Visit, Walk};
+
+/// More about a commit than its parents, see [`Builder::with_details`](super::Builder::with_details).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Details {
+    /// The commit time, in seconds since the epoch
+    pub commit_time: i64,
+    /// The generation number, if the commit is in the commit-graph or the
+    /// walk already computed it. It isn't computed just for the details, that
+    /// can mean following the parents all the way to the root commits.
+    pub generation: Option<u32>,
+    /// The id of the commit's tree
+    pub tree_id: ObjectId,
+}
+
+/// The author and committer of a commit, see [`Info::signatures`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signatures {
+    /// Who wrote the change
+    pub author: gix_actor::Signature,
+    /// Who committed it
+    pub committer: gix_actor::Signature,
+}
+
+impl Info {
+    /// Decode the author and committer of the commit. The commit-graph doesn't
+    /// store these, so unlike the [`Details`] they are read from the commit
+    /// when asked for. Afterwards `buf` holds the commit, to decode anything
+    /// else that's needed from it without looking it up again.
+    pub fn signatures<Find>(&self, find: Find, buf: &mut Vec<u8>) -> Result<Signatures, Error>
+    where
+        Find: gix_object::Find,
+    {
+        let Either::CommitRefIter(commit) = super::find(None, &find, &self.id, buf)? else {
+            unreachable!("no commit-graph given");
+        };
+
+        let mut author = None;
+
+        for token in commit {
+            use gix_object::commit::ref_iter::Token as T;
+            match token? {
+                T::Author { signature } => author = Some(signature.into()),
+                T::Committer { signature } => {
+                    return Ok(Signatures {
+                        author: author.ok_or(Error::MissingSignature)?,
+                        committer: signature.into(),
+                    });
+                }
+                _ => continue,
+            }
+        }
+
+        Err(Error::MissingSignature)
+    }
+}
+
+impl<Find, Predicate, Prune> Walk<Find, Predicate, Prune>
+where
+    Find: gix_object::Find,
+    Prune: FnMut(&oid) -> Visit,
+{
+    /// Fill in the [`Details`] of a commit if the walk should return them.
+    pub(crate) fn add_details(&mut self, mut info: Info) -> Result<Info, Error> {
+        if self.details {
+            info.details = Some(self.details(&info.id)?);
+        }
+
+        Ok(info)
+    }
+
+    fn details(&mut self, id: &oid) -> Result<Details, Error> {
+        let data = self.commit_data(id)?;
+        let ((generation, commit_time), tree_id) = (data.gen_time, data.tree_id);
+
+        let generation = match generation {
+            GENERATION_NUMBER_INFINITY => self.generations.get(id).copied(),
+            generation => Some(generation),
+        };
+
+        Ok(Details {
+            commit_time,
+            generation,
+            tree_id,
+        })
+    }
+}
@@ -0,0 +1,199 @@
+//! Generation numbers for commits that aren't in the commit-graph.
+//!
+//! Without a generation number a commit gets
+//! [`GENERATION_NUMBER_INFINITY`](gix_commitgraph::GENERATION_NUMBER_INFINITY),
+//! and the walk can't tell when it's done with it, so it ends up exploring
+//! everything. Instead the generation number is computed from the parents,
+//! following them until commits that are in the commit-graph, or root
+//! commits. Each commit is only computed once per walk.
+//!
+//! Only the parents the walk follows count, so a simplified commit uses its
+//! simplified parents, and a commit whose parents are pruned is a root. The
+//! history behind it isn't looked at, and may well be missing.
+
+use gix_commitgraph::{GENERATION_NUMBER_INFINITY, GENERATION_NUMBER_MAX};
+use gix_hash::{oid, ObjectId};
+use gix_revwalk::graph::IdMap;
+
+use smallvec::SmallVec;
+
+use super::{Error, GenAndCommitTime, Visit, Walk};
+
+type ParentList = SmallVec<[(ObjectId, GenAndCommitTime); 1]>;
+
+impl<Find, Predicate, Prune> Walk<Find, Predicate, Prune>
+where
+    Find: gix_object::Find,
+    Prune: FnMut(&oid) -> Visit,
+{
+    /// Get the generation number and commit time of a commit, computing the
+    /// generation number if the commit isn't in the commit-graph.
+    pub(crate) fn gen_and_commit_time(&mut self, id: &oid) -> Result<GenAndCommitTime, Error> {
+        let gen_time = self.commit_data(id)?.gen_time;
+
+        self.with_generation(id, gen_time)
+    }
+
+    /// Fill in the generation number if the commit wasn't in the commit-graph.
+    pub(crate) fn with_generation(
+        &mut self,
+        id: &oid,
+        (gen, time): GenAndCommitTime,
+    ) -> Result<GenAndCommitTime, Error> {
+        if gen != GENERATION_NUMBER_INFINITY {
+            return Ok((gen, time));
+        }
+
+        Ok((self.generation(id)?, time))
+    }
+
+    /// See [`generation()`], remembering the numbers for the rest of the walk.
+    fn generation(&mut self, id: &oid) -> Result<u32, Error> {
+        let mut generations = std::mem::take(&mut self.generations);
+        let gen = generation(id, &mut generations, |id| self.generation_parents(id));
+        self.generations = generations;
+
+        gen
+    }
+
+    /// The parents the generation number of a commit depends on: the ones the
+    /// walk follows, like `collect_all_parents()` but without filling in their
+    /// generation numbers.
+    fn generation_parents(&mut self, id: &oid) -> Result<ParentList, Error> {
+        if let Some(parents) = self.path_limit.as_ref().and_then(|p| p.parents(id)) {
+            return Ok(parents.clone());
+        }
+
+        if self.parents_pruned(id) {
+            return Ok(ParentList::new());
+        }
+
+        let cache = self.commit_graph.as_ref();
+        self.commits
+            .parents(cache, &self.find, id, false, &mut self.buf)
+    }
+}
+
+/// The generation number of a commit that isn't in the commit-graph, which is
+/// one more than the highest of its parents. A root commit has generation
+/// number 1. Computed numbers are remembered in `generations`, and the
+/// parents of a commit are given by `parents`, like
+/// [`collect_parents()`](super::collect_parents).
+pub(crate) fn generation(
+    id: &oid,
+    generations: &mut IdMap<u32>,
+    mut parents: impl FnMut(&oid) -> Result<ParentList, Error>,
+) -> Result<u32, Error> {
+    if let Some(gen) = generations.get(id) {
+        return Ok(*gen);
+    }
+
+    // Depth first without recursion, history can be deep
+    let mut stack = vec![id.to_owned()];
+
+    while let Some(id) = stack.last().copied() {
+        if generations.contains_key(&id) {
+            stack.pop();
+            continue;
+        }
+
+        let parents = parents(&id)?;
+
+        let mut max = 0;
+        let mut missing = Vec::<ObjectId>::new();
+
+        for (parent_id, (gen, _)) in parents {
+            match gen {
+                GENERATION_NUMBER_INFINITY => match generations.get(&parent_id) {
+                    Some(gen) => max = max.max(*gen),
+                    None => missing.push(parent_id),
+                },
+                gen => max = max.max(gen),
+            }
+        }
+
+        if missing.is_empty() {
+            stack.pop();
+            generations.insert(id, (max + 1).min(GENERATION_NUMBER_MAX));
+        } else {
+            stack.extend(missing);
+        }
+    }
+
+    Ok(generations[id])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use gix_object::Find;
+    use pretty_assertions::assert_eq;
+    use test_case::test_matrix;
+
+    use crate::memory::{Dag, Store};
+    use crate::{Builder, Parents, Sorting, Visit};
+    use Parents::{All, First};
+    use Sorting::{AuthorDateOrder, DateOrder, TopoOrder};
+
+    /// A store that remembers which objects were looked up.
+    struct Recording<'a> {
+        store: &'a Store,
+        looked_up: RefCell<Vec<ObjectId>>,
+    }
+
+    impl Find for Recording<'_> {
+        fn try_find<'a>(
+            &self,
+            id: &oid,
+            buffer: &'a mut Vec<u8>,
+        ) -> Result<Option<gix_object::Data<'a>>, gix_object::find::Error> {
+            self.looked_up.borrow_mut().push(id.to_owned());
+            self.store.try_find(id, buffer)
+        }
+    }
+
+    #[test_matrix(
+        [ DateOrder, TopoOrder, AuthorDateOrder ],
+        [ All, First ]
+    )]
+    fn stops_at_pruned_parents(sorting: Sorting, parents: Parents) {
+        let mut dag = Dag::new();
+        let root = dag.commit(&[], 1_700_000_000);
+        let behind = dag.commit(&[root], 1_700_000_100);
+        let cut = dag.commit(&[behind], 1_700_000_200);
+        let side = dag.commit(&[cut], 1_700_000_300);
+        let main = dag.commit(&[cut], 1_700_000_400);
+        let tip = dag.commit(&[main, side], 1_700_000_500);
+
+        let find = Recording {
+            store: dag.store(),
+            looked_up: Default::default(),
+        };
+
+        // Without a commit-graph every generation number is computed
+        let ids = Builder::from_iters(&find, [tip], None::<Vec<ObjectId>>)
+            .sorting(sorting)
+            .parents(parents)
+            .with_pruning(move |id| {
+                if id == cut.as_ref() {
+                    Visit::PruneParents
+                } else {
+                    Visit::Keep
+                }
+            })
+            .build()
+            .unwrap()
+            .map(|info| info.map(|info| info.id))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(ids.last(), Some(&cut));
+
+        let looked_up = find.looked_up.into_inner();
+        assert!(looked_up.contains(&cut));
+        assert!(!looked_up.contains(&behind), "{looked_up:?}");
+        assert!(!looked_up.contains(&root), "{looked_up:?}");
+    }
+}
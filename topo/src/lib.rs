@@ -1,12 +1,38 @@
 use gix_hash::{oid, ObjectId};
+use gix_object::bstr::BString;
 #[cfg(feature = "standalone")]
 use gix_object::FindExt;
 use gix_revwalk::{graph::IdMap, PriorityQueue};
+use std::collections::{HashSet, VecDeque};
 
 use flagset::{flags, FlagSet};
 
 use smallvec::SmallVec;
 
+pub mod bloom;
+mod boundary;
+mod cache;
+pub mod cherry;
+pub mod commit_graph;
+mod details;
+mod generation;
+mod limit;
+pub mod memory;
+mod merge_base;
+mod prune;
+pub mod reach;
+pub mod reflog;
+pub mod replace;
+#[cfg(any(test, feature = "test-support"))]
+pub mod scratch;
+pub mod shallow;
+mod simplify;
+mod walk2;
+
+pub use details::{Details, Signatures};
+pub use prune::Visit;
+pub use walk2::Walk2;
+
 #[derive(thiserror::Error, Debug)]
 #[allow(missing_docs)]
 pub enum Error {
@@ -22,6 +48,10 @@ pub enum Error {
     ObjectDecode(#[from] gix_object::decode::Error),
     #[error("Error finding object: {0}")]
     Find(#[from] gix_object::find::existing_iter::Error),
+    #[error("Commit has no tree")]
+    MissingTree,
+    #[error("Commit has no author or committer")]
+    MissingSignature,
 }
 
 #[cfg(feature = "trace")]
@@ -44,9 +74,15 @@ flags! {
         Bottom,
         /// TODO: Figure out purpose of this flag
         Added,
-        /// TODO: Figure out purpose of this flag
+        /// Commit is reachable from the left side of a symmetric difference, like
+        /// foo in `git rev-list foo...bar`
         SymmetricLeft,
-        /// TODO: Figure out purpose of this flag
+        /// Commit doesn't change any of the paths the walk is limited to,
+        /// compared to its (relevant) parents
+        TreeSame,
+        /// Commit is an ancestor of a commit given to
+        /// [`Builder::ancestry_path_through`], like `git rev-list
+        /// --ancestry-path=<commit>`
         AncestryPath,
     }
 }
@@ -62,6 +98,10 @@ pub enum Sorting {
     /// Show no parents before all of its children are shown, and avoid
     /// showing commits on multiple lines of history intermixed.
     TopoOrder,
+
+    /// Show no parents before all of its children are shown, but otherwise show
+    /// commits in the author timestamp order.
+    AuthorDateOrder,
 }
 
 /// Specify how to handle commit parents during traversal.
@@ -74,6 +114,40 @@ pub enum Parents {
     ///Only traverse along the first parent, which commonly ignores all branches.
     First,
 }
+
+/// How to simplify the history of a walk that is limited to paths. See
+/// "History Simplification" in `git help rev-list`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Simplification {
+    /// Hide commits that don't change any of the paths, and only follow one
+    /// parent of a merge that doesn't change them compared to that parent.
+    #[default]
+    Dense,
+
+    /// Like `Dense`, but follow all parents of merges, like `git rev-list
+    /// --full-history`.
+    FullHistory,
+
+    /// Like `FullHistory`, but also hide merges that don't bring anything
+    /// interesting to the remaining history, like `git rev-list
+    /// --simplify-merges`. Whether a merge is kept depends on all of its
+    /// descendants, so the whole range is walked first, see [`Walk`].
+    SimplifyMerges,
+
+    /// Like `Dense`, but show all commits that aren't merges, even if they
+    /// don't change any of the paths, like `git rev-list --sparse`.
+    Sparse,
+}
+
+/// Which side of a symmetric difference a commit is reachable from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// Reachable from the left side, like foo in `foo...bar`
+    Left,
+    /// Reachable from the right side, like bar in `foo...bar`
+    Right,
+}
+
 /// Information about a commit that we obtained naturally as part of the iteration.
 #[derive(Debug, PartialEq)]
 pub struct Info {
@@ -81,6 +155,17 @@ pub struct Info {
     pub id: ObjectId,
     /// All parent ids we have encountered. Note that these will be at most one if [`Parents::First`] is enabled.
     pub parent_ids: SmallVec<[ObjectId; 1]>,
+    /// Which side of a symmetric difference the commit is reachable from, like
+    /// `git rev-list --left-right`. Only set if the walk includes a symmetric
+    /// difference.
+    pub side: Option<Side>,
+    /// Whether this is a boundary commit, a parent of a returned commit that
+    /// is itself excluded, like `git rev-list --boundary`. Boundary commits
+    /// are returned last, and only if [`Builder::boundary`] is set.
+    pub boundary: bool,
+    /// The commit time, generation number and tree of the commit, if the walk
+    /// was built [`with_details`](Builder::with_details).
+    pub details: Option<Details>,
 }
 
 // Git's priority queue works as a LIFO stack if no compare function is set,
@@ -97,14 +182,14 @@ enum Queue {
 impl Queue {
     fn new(s: Sorting) -> Self {
         match s {
-            Sorting::DateOrder => Self::Date(PriorityQueue::new()),
+            Sorting::DateOrder | Sorting::AuthorDateOrder => Self::Date(PriorityQueue::new()),
             Sorting::TopoOrder => Self::Topo(vec![]),
         }
     }
 
-    fn push(&mut self, commit_time: i64, id: Info) {
+    fn push(&mut self, time: i64, id: Info) {
         match self {
-            Self::Date(q) => q.insert(commit_time, id),
+            Self::Date(q) => q.insert(time, id),
             Self::Topo(q) => q.push(id),
         }
     }
@@ -126,7 +211,7 @@ impl Queue {
 type GenAndCommitTime = (u32, i64);
 
 /// Builder for `Walk`
-pub struct Builder<Find, Predicate> {
+pub struct Builder<Find, Predicate, Prune = fn(&oid) -> Visit> {
     commit_graph: Option<gix_commitgraph::Graph>,
     find: Find,
     predicate: Predicate,
@@ -134,9 +219,22 @@ pub struct Builder<Find, Predicate> {
     parents: Parents,
     tips: Vec<ObjectId>,
     ends: Vec<ObjectId>,
+    symmetric: Vec<(ObjectId, ObjectId)>,
+    include_parents: Vec<ObjectId>,
+    exclude_parents: Vec<ObjectId>,
+    ancestry_path: Option<Vec<ObjectId>>,
+    reverse: bool,
+    children: bool,
+    paths: Vec<BString>,
+    simplification: Simplification,
+    changed_paths: Option<bloom::ChangedPaths>,
+    limits: limit::Limits,
+    boundary: bool,
+    details: bool,
+    pruning: Option<Prune>,
 }
 
-impl<Find> Builder<Find, fn(&oid) -> bool>
+impl<Find> Builder<Find, fn(&oid) -> bool, fn(&oid) -> Visit>
 where
     Find: gix_object::Find,
 {
@@ -160,6 +258,19 @@ where
             parents: Default::default(),
             tips,
             ends,
+            symmetric: vec![],
+            include_parents: vec![],
+            exclude_parents: vec![],
+            ancestry_path: None,
+            reverse: false,
+            children: false,
+            paths: vec![],
+            simplification: Default::default(),
+            changed_paths: None,
+            limits: Default::default(),
+            boundary: false,
+            details: false,
+            pruning: None,
             predicate: |_| true,
         }
     }
@@ -169,6 +280,9 @@ where
     pub fn from_specs(find: Find, specs: impl IntoIterator<Item = gix_revision::Spec>) -> Self {
         let mut tips = vec![];
         let mut ends = vec![];
+        let mut symmetric = vec![];
+        let mut include_parents = vec![];
+        let mut exclude_parents = vec![];
 
         for spec in specs {
             use gix_revision::Spec as S;
@@ -179,9 +293,9 @@ where
                     tips.push(to);
                     ends.push(from)
                 }
-                S::Merge { .. } => todo!(),
-                S::IncludeOnlyParents(_) => todo!(),
-                S::ExcludeParents(_) => todo!(),
+                S::Merge { theirs, ours } => symmetric.push((theirs, ours)),
+                S::IncludeOnlyParents(i) => include_parents.push(i),
+                S::ExcludeParents(e) => exclude_parents.push(e),
             }
         }
 
@@ -192,17 +306,67 @@ where
             parents: Default::default(),
             tips,
             ends,
+            symmetric,
+            include_parents,
+            exclude_parents,
+            ancestry_path: None,
+            reverse: false,
+            children: false,
+            paths: vec![],
+            simplification: Default::default(),
+            changed_paths: None,
+            limits: Default::default(),
+            boundary: false,
+            details: false,
+            pruning: None,
             predicate: |_| true,
         }
     }
+}
 
+impl<Find, Prune> Builder<Find, fn(&oid) -> bool, Prune>
+where
+    Find: gix_object::Find,
+{
     /// Set a predicate to filter out revisions from the walk. Can be used to
-    /// implement e.g. filtering on paths or time. This does *not* exclude the
-    /// parent(s) of a revision that is excluded.
-    pub fn with_predicate<Predicate>(self, predicate: Predicate) -> Builder<Find, Predicate>
+    /// implement e.g. filtering on time. This does *not* exclude the parent(s)
+    /// of a revision that is excluded. To limit the walk to paths use
+    /// [`Builder::paths`] instead, and to cut off parts of the history use
+    /// [`Builder::with_pruning`].
+    pub fn with_predicate<Predicate>(self, predicate: Predicate) -> Builder<Find, Predicate, Prune>
     where
         Predicate: FnMut(&oid) -> bool,
     {
+        self.map_hooks(|_, pruning| (predicate, pruning))
+    }
+}
+
+impl<Find, Predicate> Builder<Find, Predicate, fn(&oid) -> Visit>
+where
+    Find: gix_object::Find,
+{
+    /// Decide for each commit whether to return it and whether to walk its
+    /// parents, see [`Visit`]. Unlike the predicate this can stop the walk,
+    /// for example at a release, or at commits imported from elsewhere. The
+    /// hook is called once for every commit the walk comes across, which can
+    /// be more than the commits it returns.
+    pub fn with_pruning<Prune>(self, hook: Prune) -> Builder<Find, Predicate, Prune>
+    where
+        Prune: FnMut(&oid) -> Visit,
+    {
+        self.map_hooks(|predicate, _| (predicate, Some(hook)))
+    }
+}
+
+impl<Find, Predicate, Prune> Builder<Find, Predicate, Prune> {
+    /// Replace the predicate and the pruning hook, which the type of the
+    /// builder depends on.
+    fn map_hooks<P, H>(
+        self,
+        f: impl FnOnce(Predicate, Option<Prune>) -> (P, Option<H>),
+    ) -> Builder<Find, P, H> {
+        let (predicate, pruning) = f(self.predicate, self.pruning);
+
         Builder {
             commit_graph: self.commit_graph,
             find: self.find,
@@ -210,14 +374,29 @@ where
             parents: self.parents,
             tips: self.tips,
             ends: self.ends,
+            symmetric: self.symmetric,
+            include_parents: self.include_parents,
+            exclude_parents: self.exclude_parents,
+            ancestry_path: self.ancestry_path,
+            reverse: self.reverse,
+            children: self.children,
+            paths: self.paths,
+            simplification: self.simplification,
+            changed_paths: self.changed_paths,
+            limits: self.limits,
+            boundary: self.boundary,
+            details: self.details,
+            pruning,
             predicate,
         }
     }
 }
-impl<Find, Predicate> Builder<Find, Predicate>
+
+impl<Find, Predicate, Prune> Builder<Find, Predicate, Prune>
 where
     Find: gix_object::Find,
     Predicate: FnMut(&oid) -> bool,
+    Prune: FnMut(&oid) -> Visit,
 {
     /// Set the [`Sorting`] to use for the topological walk
     pub fn sorting(mut self, sorting: Sorting) -> Self {
@@ -237,23 +416,197 @@ where
         self
     }
 
+    /// Walk the symmetric difference between `left` and `right`, ie. all
+    /// commits reachable from either but not both, like `git rev-list
+    /// left...right`. Which side each commit is reachable from is given by
+    /// [`Info::side`].
+    pub fn symmetric_difference(
+        mut self,
+        left: impl Into<ObjectId>,
+        right: impl Into<ObjectId>,
+    ) -> Self {
+        self.symmetric.push((left.into(), right.into()));
+        self
+    }
+
+    /// Walk all parents of the given commit, but not the commit itself, like
+    /// `git rev-list commit^@`.
+    pub fn parents_of(mut self, id: impl Into<ObjectId>) -> Self {
+        self.include_parents.push(id.into());
+        self
+    }
+
+    /// Walk only the given commit by excluding all of its parents, like `git
+    /// rev-list commit^!`.
+    pub fn only(mut self, id: impl Into<ObjectId>) -> Self {
+        self.exclude_parents.push(id.into());
+        self
+    }
+
+    /// Only walk commits that are descendants of one of the ends, and thus on
+    /// an ancestry path between an end and a tip, like `git rev-list
+    /// --ancestry-path A..B`. Descendants are only known once the ends are
+    /// reached, so the whole range is walked first, see [`Walk`].
+    pub fn ancestry_path(mut self) -> Self {
+        self.ancestry_path.get_or_insert_with(Vec::new);
+        self
+    }
+
+    /// Only walk commits that are ancestors or descendants of the given commit,
+    /// or the commit itself, like `git rev-list --ancestry-path=<commit>`. Can
+    /// be given multiple times, in which case commits on the ancestry path of
+    /// any of them are walked. Like [`Builder::ancestry_path()`], this walks
+    /// the whole range first.
+    pub fn ancestry_path_through(mut self, id: impl Into<ObjectId>) -> Self {
+        self.ancestry_path
+            .get_or_insert_with(Vec::new)
+            .push(id.into());
+        self
+    }
+
+    /// Walk the commits in reverse, oldest first, so that no commit is
+    /// returned before all of its parents, like `git rev-list --reverse`. The
+    /// oldest commit is the last one found, so the whole range is walked
+    /// first, see [`Walk`].
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// Also return the boundary commits once the walk is done, like `git
+    /// rev-list --boundary`. These are marked with [`Info::boundary`].
+    pub fn boundary(mut self, boundary: bool) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    /// Fill in the [`Info::details`] of every commit, from the commit-graph
+    /// when possible. The author and committer can be decoded when needed with
+    /// [`Info::signatures`].
+    pub fn with_details(mut self, details: bool) -> Self {
+        self.details = details;
+        self
+    }
+
+    /// Keep track of the children of each commit within the walked range,
+    /// available from [`Walk::children`].
+    pub fn with_children(mut self, children: bool) -> Self {
+        self.children = children;
+        self
+    }
+
+    /// Limit the walk to commits that change the given paths, relative to the
+    /// root of the repository and separated by `/`, like `git rev-list --
+    /// <paths>`. A directory covers everything beneath it. The history is
+    /// simplified according to the [`Simplification`], and the
+    /// [`Info::parent_ids`] are rewritten to the nearest shown ancestors.
+    pub fn paths(mut self, paths: impl IntoIterator<Item = impl Into<BString>>) -> Self {
+        self.paths = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the [`Simplification`] to use if the walk is limited to paths.
+    pub fn simplification(mut self, simplification: Simplification) -> Self {
+        self.simplification = simplification;
+        self
+    }
+
+    /// Set or unset the changed-path Bloom filters to use if the walk is
+    /// limited to paths. Commits that the filters say didn't change any of
+    /// the paths compared to their first parent are known to be TREESAME to
+    /// it without comparing trees.
+    pub fn with_changed_paths(mut self, changed_paths: Option<bloom::ChangedPaths>) -> Self {
+        self.changed_paths = changed_paths;
+        self
+    }
+
+    /// Stop after returning `n` commits, like `git rev-list --max-count`.
+    pub fn max_count(mut self, n: usize) -> Self {
+        self.limits.max_count = Some(n);
+        self
+    }
+
+    /// Skip the first `n` commits that would otherwise be returned, like `git
+    /// rev-list --skip`.
+    pub fn skip(mut self, n: usize) -> Self {
+        self.limits.skip = n;
+        self
+    }
+
+    /// Only return commits with a commit time, in seconds since the epoch, of
+    /// at least `time`, like `git rev-list --since`. The walk stops at older
+    /// commits, so their ancestors aren't returned either.
+    pub fn since(mut self, time: i64) -> Self {
+        self.limits.since = Some(time);
+        self
+    }
+
+    /// Only return commits with a commit time, in seconds since the epoch, of
+    /// at most `time`, like `git rev-list --until`.
+    pub fn until(mut self, time: i64) -> Self {
+        self.limits.until = Some(time);
+        self
+    }
+
+    /// Only return commits with at least `n` parents, like `git rev-list
+    /// --min-parents`. Use 2 to only return merges.
+    pub fn min_parents(mut self, n: usize) -> Self {
+        self.limits.min_parents = n;
+        self
+    }
+
+    /// Only return commits with at most `n` parents, like `git rev-list
+    /// --max-parents`. Use 1 to hide merges.
+    pub fn max_parents(mut self, n: usize) -> Self {
+        self.limits.max_parents = Some(n);
+        self
+    }
+
+    /// Only return commits whose author, as `Name <email>`, contains
+    /// `pattern`. Commits matching any of the patterns given are returned.
+    pub fn author(mut self, pattern: impl Into<BString>) -> Self {
+        self.limits.authors.push(pattern.into());
+        self
+    }
+
+    /// Only return commits whose committer, as `Name <email>`, contains
+    /// `pattern`. Commits matching any of the patterns given are returned.
+    pub fn committer(mut self, pattern: impl Into<BString>) -> Self {
+        self.limits.committers.push(pattern.into());
+        self
+    }
+
+    /// Only return commits whose message contains `pattern`. Commits matching
+    /// any of the patterns given are returned, unless [`Builder::all_match`]
+    /// is set. Unlike `git rev-list --grep` the patterns are fixed strings.
+    pub fn grep(mut self, pattern: impl Into<BString>) -> Self {
+        self.limits.messages.push(pattern.into());
+        self
+    }
+
+    /// Only return commits whose message contains all the patterns given to
+    /// [`Builder::grep`], like `git rev-list --all-match`.
+    pub fn all_match(mut self, all_match: bool) -> Self {
+        self.limits.all_match = all_match;
+        self
+    }
+
     /// Build a new [`Walk`] instance.
-    pub fn build(self) -> Result<Walk<Find, Predicate>, Error> {
-        Walk::new(
-            self.commit_graph,
-            self.find,
-            self.sorting,
-            self.parents,
-            &self.tips,
-            &self.ends,
-            self.predicate,
-        )
+    pub fn build(self) -> Result<Walk<Find, Predicate, Prune>, Error> {
+        Walk::new(self)
     }
 }
 
 /// A commit walker that walks in topographical order, like `git rev-list
 /// --topo-order` or `--date-order` depending on the chosen [`Sorting`]
-pub struct Walk<Find, Predicate> {
+///
+/// Commits are usually returned as soon as the order allows it, so the first
+/// ones come quickly even in a large history. Walks with
+/// [`Builder::reverse()`], [`Builder::ancestry_path()`] or
+/// [`Simplification::SimplifyMerges`] instead walk the whole range before
+/// returning the first commit, because what they return depends on commits
+/// further down.
+pub struct Walk<Find, Predicate, Prune = fn(&oid) -> Visit> {
     commit_graph: Option<gix_commitgraph::Graph>,
     find: Find,
     predicate: Predicate,
@@ -262,67 +615,168 @@ pub struct Walk<Find, Predicate> {
     explore_queue: PriorityQueue<GenAndCommitTime, ObjectId>,
     indegree_queue: PriorityQueue<GenAndCommitTime, ObjectId>,
     topo_queue: Queue,
+    sorting: Sorting,
     parents: Parents,
     min_gen: u32,
+    left_right: bool,
+    collected: Option<VecDeque<Info>>,
+    /// All commits to return, oldest first, if the walk is reversed
+    reversed: Option<VecDeque<Info>>,
+    limits: limit::Limits,
+    boundary: Option<boundary::Boundary>,
+    details: bool,
+    children: Option<IdMap<SmallVec<[ObjectId; 1]>>>,
+    path_limit: Option<simplify::PathLimit>,
+    /// Generation numbers computed for commits that aren't in the commit-graph
+    generations: IdMap<u32>,
+    commits: cache::Commits,
+    pruning: Option<Prune>,
+    /// What the pruning hook decided for each commit so far
+    visits: IdMap<Visit>,
     buf: Vec<u8>,
 }
 
-impl<Find, Predicate> Walk<Find, Predicate>
+impl<Find, Predicate, Prune> Walk<Find, Predicate, Prune>
 where
     Find: gix_object::Find,
     Predicate: FnMut(&oid) -> bool,
+    Prune: FnMut(&oid) -> Visit,
 {
     /// Create a new Walk that walks the given repository, starting at the
     /// tips and ending at the bottoms. Like `git rev-list --topo-order
     /// ^bottom... tips...`
-    fn new(
-        commit_graph: Option<gix_commitgraph::Graph>,
-        f: Find,
-        sorting: Sorting,
-        parents: Parents,
-        tips: &[ObjectId],
-        ends: &[ObjectId],
-        predicate: Predicate,
-    ) -> Result<Self, Error> {
+    fn new(builder: Builder<Find, Predicate, Prune>) -> Result<Self, Error> {
         let mut s = Self {
-            commit_graph,
-            find: f,
-            predicate,
+            commit_graph: builder.commit_graph,
+            find: builder.find,
+            predicate: builder.predicate,
             indegrees: IdMap::default(),
             states: IdMap::default(),
             explore_queue: PriorityQueue::new(),
             indegree_queue: PriorityQueue::new(),
-            topo_queue: Queue::new(sorting),
-            parents,
+            topo_queue: Queue::new(builder.sorting),
+            sorting: builder.sorting,
+            parents: builder.parents,
             min_gen: gix_commitgraph::GENERATION_NUMBER_INFINITY,
+            left_right: !builder.symmetric.is_empty(),
+            collected: None,
+            reversed: None,
+            limits: builder.limits,
+            boundary: builder.boundary.then(Default::default),
+            details: builder.details,
+            children: builder.children.then(IdMap::default),
+            path_limit: (!builder.paths.is_empty()).then(|| {
+                simplify::PathLimit::new(
+                    builder.paths,
+                    builder.simplification,
+                    builder.changed_paths,
+                )
+            }),
+            generations: IdMap::default(),
+            commits: Default::default(),
+            pruning: builder.pruning,
+            visits: IdMap::default(),
             buf: vec![],
         };
 
-        s.init(tips, ends)?;
+        let mut tips = builder.tips;
+        let mut ends = builder.ends;
+        let mut left_tips = vec![];
+
+        // Parent shorthands always refer to all parents, even with
+        // Parents::First, like in Git.
+        for id in builder.include_parents {
+            tips.extend(s.collect_all_parents(&id)?.into_iter().map(|(id, _)| id));
+        }
+
+        for id in builder.exclude_parents {
+            ends.extend(s.collect_all_parents(&id)?.into_iter().map(|(id, _)| id));
+            tips.push(id);
+        }
+
+        // Like Git we walk a symmetric difference by walking from both sides
+        // and excluding everything reachable from their merge bases.
+        for (left, right) in builder.symmetric {
+            ends.extend(merge_base::merge_base_candidates(
+                s.commit_graph.as_ref(),
+                &s.find,
+                &left,
+                &[right],
+                &mut s.buf,
+            )?);
+            left_tips.push(left);
+            tips.push(right);
+        }
+
+        if let Some(through) = &builder.ancestry_path {
+            for id in through {
+                *s.states.entry(*id).or_default() |= WalkFlags::AncestryPath;
+            }
+        }
+
+        s.init(&tips, &left_tips, &ends)?;
+
+        if let Some(through) = builder.ancestry_path {
+            let bottoms = if through.is_empty() { ends } else { through };
+            s.limit_to_ancestry(bottoms)?;
+        }
+
+        if builder.simplification == Simplification::SimplifyMerges && s.path_limit.is_some() {
+            let commits = match s.collected.take() {
+                Some(commits) => commits,
+                None => s.collect_all()?,
+            };
+            s.collected = Some(s.simplify_merges(commits)?);
+        }
+
+        // Like Git, the limits apply before reversing
+        if builder.reverse {
+            let mut commits = VecDeque::new();
+            while let Some(info) = s.next_commit() {
+                commits.push_front(s.add_details(info?)?);
+            }
+            s.reversed = Some(commits);
+        }
 
         Ok(s)
     }
+
+    /// The children of the given commit among the commits walked so far.
+    /// Since children are walked before their parents, this is complete for
+    /// every commit that has been returned, unless the walk is reversed in
+    /// which case it's complete from the start. Returns `None` if the walk
+    /// wasn't built [`with_children`](Builder::with_children).
+    pub fn children(&self, id: &oid) -> Option<&[ObjectId]> {
+        let children = self.children.as_ref()?;
+        Some(children.get(id).map_or(&[], |c| c.as_slice()))
+    }
 }
 
 #[cfg_attr(feature = "trace", trace(prefix_enter = "", prefix_exit = ""))]
-impl<Find, Predicate> Walk<Find, Predicate>
+impl<Find, Predicate, Prune> Walk<Find, Predicate, Prune>
 where
     Find: gix_object::Find,
+    Prune: FnMut(&oid) -> Visit,
 {
-    fn init(&mut self, tips: &[ObjectId], ends: &[ObjectId]) -> Result<(), Error> {
+    fn init(
+        &mut self,
+        tips: &[ObjectId],
+        left_tips: &[ObjectId],
+        ends: &[ObjectId],
+    ) -> Result<(), Error> {
         let tip_flags: FlagSet<WalkFlags> = WalkFlags::Seen.into();
+        let left_flags = tip_flags | WalkFlags::SymmetricLeft;
         let end_flags = tip_flags | WalkFlags::Uninteresting | WalkFlags::Bottom;
 
         for (id, flags) in tips
             .iter()
             .map(|id| (id, tip_flags))
+            .chain(left_tips.iter().map(|id| (id, left_flags)))
             .chain(ends.iter().map(|id| (id, end_flags)))
         {
             *self.indegrees.entry(*id).or_default() = 1;
 
-            let commit = find(self.commit_graph.as_ref(), &self.find, id, &mut self.buf)?;
-
-            let (gen, time) = get_gen_and_commit_time(commit)?;
+            let (gen, time) = self.gen_and_commit_time(id)?;
 
             if gen < self.min_gen {
                 self.min_gen = gen;
@@ -330,7 +784,7 @@ where
 
             let state = flags | WalkFlags::Explored | WalkFlags::InDegree;
 
-            self.states.insert(*id, state);
+            *self.states.entry(*id).or_default() |= state;
             self.explore_queue.insert((gen, time), *id);
             self.indegree_queue.insert((gen, time), *id);
         }
@@ -349,15 +803,19 @@ where
 
         self.compute_indegrees_to_depth(self.min_gen)?;
 
-        for id in tips.iter() {
+        for id in tips.iter().chain(left_tips) {
+            let state = self.states.get(id).ok_or(Error::MissingState)?;
+            if state.contains(WalkFlags::Uninteresting) {
+                continue;
+            }
+
             let i = *self.indegrees.get(id).ok_or(Error::MissingIndegree)?;
 
             // NOTE: in Git the ends are also added to the topo_queue, but then
             // in simplify_commit() Git is told to ignore it. For now the tests pass.
             if i == 1 {
-                let commit = find(self.commit_graph.as_ref(), &self.find, id, &mut self.buf)?;
-
-                let (_, time) = get_gen_and_commit_time(commit)?;
+                let (_, time) = self.gen_and_commit_time(id)?;
+                let key = self.topo_queue_key(id, time)?;
 
                 let parent_ids = self
                     .collect_all_parents(id)?
@@ -366,10 +824,13 @@ where
                     .collect();
 
                 self.topo_queue.push(
-                    time,
+                    key,
                     Info {
                         id: *id,
                         parent_ids,
+                        side: None,
+                        boundary: false,
+                        details: None,
                     },
                 );
             }
@@ -380,6 +841,44 @@ where
         Ok(())
     }
 
+    /// Walk the whole range up front.
+    fn collect_all(&mut self) -> Result<VecDeque<Info>, Error> {
+        let mut commits = VecDeque::new();
+        while let Some(info) = self.pop_commit() {
+            commits.push_back(info?);
+        }
+
+        Ok(commits)
+    }
+
+    /// Walk the whole range and keep only the commits that are descendants of
+    /// one of `bottoms`, or ancestors of a commit marked with
+    /// [`WalkFlags::AncestryPath`]. Like `limit_to_ancestry()` in Git.
+    fn limit_to_ancestry(&mut self, bottoms: Vec<ObjectId>) -> Result<(), Error> {
+        let commits = self.collect_all()?;
+
+        // Parents come after their children, so one pass from the oldest
+        // commit finds all descendants.
+        let mut descendants = bottoms.into_iter().collect::<HashSet<_>>();
+        for info in commits.iter().rev() {
+            if info.parent_ids.iter().any(|id| descendants.contains(id)) {
+                descendants.insert(info.id);
+            }
+        }
+
+        let mut limited = VecDeque::with_capacity(commits.len());
+        for info in commits {
+            let state = self.states.get(&info.id).ok_or(Error::MissingState)?;
+            if descendants.contains(&info.id) || state.contains(WalkFlags::AncestryPath) {
+                limited.push_back(info);
+            }
+        }
+
+        self.collected = Some(limited);
+
+        Ok(())
+    }
+
     fn compute_indegrees_to_depth(&mut self, gen_cutoff: u32) -> Result<(), Error> {
         while let Some(((gen, _), _)) = self.indegree_queue.peek() {
             if *gen >= gen_cutoff {
@@ -431,8 +930,7 @@ where
     fn explore_walk_step(&mut self) -> Result<(), Error> {
         if let Some((_, id)) = self.explore_queue.pop() {
             let parents = self.collect_parents(&id)?;
-
-            self.process_parents(&id, &parents)?;
+            let parents = self.process_parents(&id, parents)?;
 
             for (id, gen_time) in parents {
                 let state = self.states.get_mut(&id).ok_or(Error::MissingState)?;
@@ -449,8 +947,7 @@ where
 
     fn expand_topo_walk(&mut self, id: &oid) -> Result<(), Error> {
         let parents = self.collect_parents(id)?;
-
-        self.process_parents(id, &parents)?;
+        let parents = self.process_parents(id, parents)?;
 
         for (pid, (parent_gen, parent_commit_time)) in parents {
             let parent_state = self.states.get(&pid).ok_or(Error::MissingState)?;
@@ -469,6 +966,8 @@ where
             *i -= 1;
 
             if *i == 1 {
+                let key = self.topo_queue_key(&pid, parent_commit_time)?;
+
                 let parent_ids = self
                     .collect_all_parents(&pid)?
                     .into_iter()
@@ -476,10 +975,13 @@ where
                     .collect();
 
                 self.topo_queue.push(
-                    parent_commit_time,
+                    key,
                     Info {
                         id: pid,
                         parent_ids,
+                        side: None,
+                        boundary: false,
+                        details: None,
                     },
                 );
             }
@@ -488,25 +990,49 @@ where
         Ok(())
     }
 
+    /// The time to order the topo queue by, given the commit time
+    fn topo_queue_key(&mut self, id: &oid, commit_time: i64) -> Result<i64, Error> {
+        match self.sorting {
+            Sorting::AuthorDateOrder => get_author_time(&self.find, id, &mut self.buf),
+            Sorting::DateOrder | Sorting::TopoOrder => Ok(commit_time),
+        }
+    }
+
+    /// Pass the flags of a commit on to its parents. If the walk is limited to
+    /// paths this also simplifies the commit, and returns the parents that
+    /// are left to follow.
     fn process_parents(
         &mut self,
         id: &oid,
-        parents: &[(ObjectId, GenAndCommitTime)],
-    ) -> Result<(), Error> {
+        parents: SmallVec<[(ObjectId, GenAndCommitTime); 1]>,
+    ) -> Result<SmallVec<[(ObjectId, GenAndCommitTime); 1]>, Error> {
         let state = self.states.get_mut(id).ok_or(Error::MissingState)?;
 
         if state.contains(WalkFlags::Added) {
-            return Ok(());
+            return Ok(parents);
         }
 
         *state |= WalkFlags::Added;
 
+        self.mark_too_old(id)?;
+        let state = *self.states.get(id).ok_or(Error::MissingState)?;
+
+        let parents = if self.path_limit.is_some() && !state.contains(WalkFlags::Uninteresting) {
+            let mut parents = self.try_to_simplify_commit(id)?;
+            if matches!(self.parents, Parents::First) {
+                parents.truncate(1);
+            }
+            parents
+        } else {
+            parents
+        };
+
         // If the current commit is uninteresting we pass that on to ALL parents,
         // otherwise we pass SymmetricLeft and AncestryPath + Seen
         let (pass, insert) = if state.contains(WalkFlags::Uninteresting) {
             let flags = WalkFlags::Uninteresting.into();
 
-            for (id, _) in parents {
+            for (id, _) in &parents {
                 let grand_parents = self.collect_all_parents(id)?;
 
                 for (id, _) in &grand_parents {
@@ -519,31 +1045,50 @@ where
 
             (flags, flags)
         } else {
-            let flags = *state & (WalkFlags::SymmetricLeft | WalkFlags::AncestryPath);
+            let flags = state & (WalkFlags::SymmetricLeft | WalkFlags::AncestryPath);
             (flags, flags | WalkFlags::Seen)
         };
 
-        for (id, _) in parents {
+        for (id, _) in &parents {
             self.states
                 .entry(*id)
                 .and_modify(|s| *s |= pass)
                 .or_insert(insert);
         }
 
-        Ok(())
+        Ok(parents)
     }
 
     fn collect_parents(
         &mut self,
         id: &oid,
     ) -> Result<SmallVec<[(ObjectId, GenAndCommitTime); 1]>, Error> {
-        collect_parents(
+        // Follow the simplified parents if the commit has been simplified
+        if let Some(parents) = self.path_limit.as_ref().and_then(|p| p.parents(id)) {
+            let mut parents = parents.clone();
+            if matches!(self.parents, Parents::First) {
+                parents.truncate(1);
+            }
+            return Ok(parents);
+        }
+
+        if self.parents_pruned(id) {
+            return Ok(SmallVec::new());
+        }
+
+        let mut parents = self.commits.parents(
             self.commit_graph.as_ref(),
             &self.find,
             id,
             matches!(self.parents, Parents::First),
             &mut self.buf,
-        )
+        )?;
+
+        for (id, gen_time) in &mut parents {
+            *gen_time = self.with_generation(id, *gen_time)?;
+        }
+
+        Ok(parents)
     }
 
     // Same as collect_parents but disregards the first_parent flag
@@ -551,17 +1096,34 @@ where
         &mut self,
         id: &oid,
     ) -> Result<SmallVec<[(ObjectId, GenAndCommitTime); 1]>, Error> {
-        collect_parents(
+        if self.parents_pruned(id) {
+            return Ok(SmallVec::new());
+        }
+
+        let mut parents = self.commits.parents(
             self.commit_graph.as_ref(),
             &self.find,
             id,
             false,
             &mut self.buf,
-        )
+        )?;
+
+        for (id, gen_time) in &mut parents {
+            *gen_time = self.with_generation(id, *gen_time)?;
+        }
+
+        Ok(parents)
+    }
+
+    /// The parents, commit time and tree of a commit, decoding it only the
+    /// first time.
+    fn commit_data(&mut self, id: &oid) -> Result<&cache::CommitData, Error> {
+        self.commits
+            .get(self.commit_graph.as_ref(), &self.find, id, &mut self.buf)
     }
 
     fn pop_commit(&mut self) -> Option<Result<Info, Error>> {
-        let id = self.topo_queue.pop()?;
+        let mut id = self.topo_queue.pop()?;
 
         let i = match self.indegrees.get_mut(&id.id) {
             Some(i) => i,
@@ -579,28 +1141,81 @@ where
             }
         };
 
+        if self.left_right {
+            id.side = match self.states.get(&id.id) {
+                Some(s) if s.contains(WalkFlags::SymmetricLeft) => Some(Side::Left),
+                Some(_) => Some(Side::Right),
+                None => return Some(Err(Error::MissingState)),
+            };
+        }
+
+        if let Some(children) = &mut self.children {
+            for parent_id in &id.parent_ids {
+                children.entry(*parent_id).or_default().push(id.id);
+            }
+        }
+
         Some(Ok(id))
     }
 }
 
 #[cfg_attr(feature = "trace", trace(prefix_enter = "", prefix_exit = ""))]
-impl<Find, Predicate> Iterator for Walk<Find, Predicate>
+impl<Find, Predicate, Prune> Iterator for Walk<Find, Predicate, Prune>
 where
     Find: gix_object::Find,
     Predicate: FnMut(&oid) -> bool,
+    Prune: FnMut(&oid) -> Visit,
 {
     type Item = Result<Info, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.pop_commit()? {
-            Ok(id) => {
-                if (self.predicate)(&id.id) {
-                    Some(Ok(id))
-                } else {
-                    self.next()
+        match &mut self.reversed {
+            Some(reversed) => reversed.pop_front().map(Ok),
+            None => self
+                .next_commit()
+                .map(|info| info.and_then(|info| self.add_details(info))),
+        }
+    }
+}
+
+impl<Find, Predicate, Prune> Walk<Find, Predicate, Prune>
+where
+    Find: gix_object::Find,
+    Predicate: FnMut(&oid) -> bool,
+    Prune: FnMut(&oid) -> Visit,
+{
+    /// The next commit to return, in walking order
+    fn next_commit(&mut self) -> Option<Result<Info, Error>> {
+        loop {
+            if self.limit_reached() {
+                return self.next_boundary();
+            }
+
+            let next = match &mut self.collected {
+                Some(collected) => match collected.pop_front() {
+                    Some(info) => Ok(info),
+                    None => return self.next_boundary(),
+                },
+                None => match self.pop_commit() {
+                    Some(next) => next,
+                    None => return self.next_boundary(),
+                },
+            };
+
+            let info = match next.and_then(|info| self.simplify_commit(info)) {
+                Ok(Some(info)) if (self.predicate)(&info.id) && !self.hidden(&info.id) => info,
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            };
+
+            match self.limit_commit(&info) {
+                Ok(true) => {
+                    self.record_for_boundary(&info);
+                    return Some(Ok(info));
                 }
+                Ok(false) => continue,
+                Err(e) => return Some(Err(e)),
             }
-            Err(e) => Some(Err(e)),
         }
     }
 }
@@ -712,18 +1327,42 @@ fn get_gen_and_commit_time(c: Either<'_, '_>) -> Result<GenAndCommitTime, Error>
     }
 }
 
+// The commit-graph doesn't store author times, so they are always read from
+// the commit itself.
+fn get_author_time<Find>(f: Find, id: &oid, buf: &mut Vec<u8>) -> Result<i64, Error>
+where
+    Find: gix_object::Find,
+{
+    match find(None, &f, id, buf)? {
+        Either::CommitRefIter(c) => {
+            for token in c {
+                use gix_object::commit::ref_iter::Token as T;
+                match token {
+                    Ok(T::Tree { .. }) => continue,
+                    Ok(T::Parent { .. }) => continue,
+                    Ok(T::Author { signature }) => return Ok(signature.time.seconds),
+                    Ok(_unused_token) => break,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            Ok(0)
+        }
+        Either::CachedCommit(_) => unreachable!("no commit-graph was given"),
+    }
+}
+
 #[cfg(test)]
 #[cfg(feature = "standalone")]
 mod tests {
     use std::str::FromStr;
-    use test_case::test_matrix;
+    use test_case::{test_case, test_matrix};
 
     use super::*;
     use pretty_assertions::assert_eq;
 
     // Just to make the generated test case names a bit shorter
     use Parents::{All, First};
-    use Sorting::{DateOrder, TopoOrder};
+    use Sorting::{AuthorDateOrder, DateOrder, TopoOrder};
 
     enum GraphSetting {
         UseGraph,
@@ -733,11 +1372,22 @@ mod tests {
 
     // To avoid not depending on the gix crate itself
     fn simple_parse(r: &str) -> gix_revision::Spec {
-        if let Some((from, to)) = r.split_once("..") {
+        if let Some((theirs, ours)) = r.split_once("...") {
+            gix_revision::Spec::Merge {
+                theirs: ObjectId::from_str(theirs).expect("Valid SHA1 in tests"),
+                ours: ObjectId::from_str(ours).expect("Valid SHA1 in tests"),
+            }
+        } else if let Some((from, to)) = r.split_once("..") {
             gix_revision::Spec::Range {
                 from: ObjectId::from_str(from).expect("Valid SHA1 in tests"),
                 to: ObjectId::from_str(to).expect("Valid SHA1 in tests"),
             }
+        } else if let Some(i) = r.strip_suffix("^@") {
+            gix_revision::Spec::IncludeOnlyParents(
+                ObjectId::from_str(i).expect("Valid SHA1 in tests"),
+            )
+        } else if let Some(e) = r.strip_suffix("^!") {
+            gix_revision::Spec::ExcludeParents(ObjectId::from_str(e).expect("Valid SHA1 in tests"))
         } else if let Some(e) = r.strip_prefix("^") {
             gix_revision::Spec::Exclude(ObjectId::from_str(e).expect("Valid SHA1 in tests"))
         } else {
@@ -749,6 +1399,7 @@ mod tests {
         graph_setting: GraphSetting,
         sorting: Sorting,
         parents: Parents,
+        extra_args: &[&str],
         specs: &[&str],
     ) -> Vec<Info> {
         let git_flags = match graph_setting {
@@ -761,13 +1412,19 @@ mod tests {
             (All, TopoOrder) => &["--topo-order"],
             (First, DateOrder) => &["--first-parent", "--date-order"],
             (First, TopoOrder) => &["--first-parent", "--topo-order"],
+            (All, AuthorDateOrder) => &["--author-date-order"],
+            (First, AuthorDateOrder) => &["--first-parent", "--author-date-order"],
         };
 
+        let left_right = specs.iter().any(|s| s.contains("..."));
+
         let output = std::process::Command::new("git")
             .args(git_flags)
             .args(&["rev-list", "--parents"])
             .args(rev_list_flags)
+            .args(left_right.then_some("--left-right"))
             .args(specs)
+            .args(extra_args)
             .output()
             .expect("able to run git rev-list")
             .stdout;
@@ -776,6 +1433,15 @@ mod tests {
             .expect("sensible output from git rev-list")
             .split_terminator('\n')
             .map(|l| {
+                // Boundary commits are marked with '-', instead of the side
+                let (boundary, side, l) = match l.strip_prefix('-') {
+                    Some(l) => (true, None, l),
+                    None => match l.split_at(left_right as usize) {
+                        ("<", l) => (false, Some(Side::Left), l),
+                        (">", l) => (false, Some(Side::Right), l),
+                        (_, l) => (false, None, l),
+                    },
+                };
                 let mut l = l.split_ascii_whitespace();
                 Info {
                     id: ObjectId::from_str(l.next().expect("at least one object id"))
@@ -784,6 +1450,9 @@ mod tests {
                         .map(ObjectId::from_str)
                         .collect::<Result<SmallVec<_>, _>>()
                         .expect("rev-list returns valid object ids"),
+                    side,
+                    boundary,
+                    details: None,
                 }
             })
             .collect::<Vec<_>>()
@@ -794,6 +1463,19 @@ mod tests {
         sorting: Sorting,
         parents: Parents,
         raw_specs: &[&str],
+    ) {
+        test_body_with_options(graph_setting, sorting, parents, |b| b, &[], raw_specs);
+    }
+
+    fn test_body_with_options(
+        graph_setting: GraphSetting,
+        sorting: Sorting,
+        parents: Parents,
+        options: impl FnOnce(
+            Builder<&gix_odb::Handle, fn(&oid) -> bool>,
+        ) -> Builder<&gix_odb::Handle, fn(&oid) -> bool>,
+        git_args: &[&str],
+        raw_specs: &[&str],
     ) {
         let store = gix_odb::at("../.git/objects").expect("find objects");
         let specs = raw_specs
@@ -811,7 +1493,7 @@ mod tests {
             NoGraph => None,
         };
 
-        let walk = Builder::from_specs(&store, specs)
+        let walk = options(Builder::from_specs(&store, specs))
             .with_commit_graph(commit_graph)
             .sorting(sorting)
             .parents(parents)
@@ -819,7 +1501,7 @@ mod tests {
             .unwrap();
 
         let ids = walk.collect::<Result<Vec<_>, _>>().unwrap();
-        let git_ids = git_rev_list(graph_setting, sorting, parents, raw_specs);
+        let git_ids = git_rev_list(graph_setting, sorting, parents, git_args, raw_specs);
 
         assert_eq!(
             ids, git_ids,
@@ -859,7 +1541,7 @@ mod tests {
             .unwrap();
 
         let ids = walk.collect::<Result<Vec<_>, _>>().unwrap();
-        let mut git_ids = git_rev_list(graph_setting, sorting, parents, raw_specs);
+        let mut git_ids = git_rev_list(graph_setting, sorting, parents, &[], raw_specs);
         git_ids.retain(|e| (&mut pred)(&e.id));
 
         assert_eq!(
@@ -872,7 +1554,7 @@ mod tests {
         ($test_name:ident, $($spec:literal),+) => {
             #[test_matrix(
                 [ UseGraph, NoGraph ],
-                [ DateOrder, TopoOrder ],
+                [ DateOrder, TopoOrder, AuthorDateOrder ],
                 [ All, First ]
             )]
             fn $test_name(graph_setting: GraphSetting, sorting: Sorting, parents: Parents) {
@@ -885,7 +1567,7 @@ mod tests {
         ($test_name:ident, $pred:expr, $($spec:literal),+) => {
             #[test_matrix(
                 [ UseGraph, NoGraph ],
-                [ DateOrder, TopoOrder ],
+                [ DateOrder, TopoOrder, AuthorDateOrder ],
                 [ All, First ]
             )]
             fn $test_name(graph_setting: GraphSetting, sorting: Sorting, parents: Parents) {
@@ -894,6 +1576,26 @@ mod tests {
         };
     }
 
+    macro_rules! topo_test_with_options {
+        ($test_name:ident, $options:expr, [$($arg:literal),*], $($spec:literal),+) => {
+            #[test_matrix(
+                [ UseGraph, NoGraph ],
+                [ DateOrder, TopoOrder, AuthorDateOrder ],
+                [ All, First ]
+            )]
+            fn $test_name(graph_setting: GraphSetting, sorting: Sorting, parents: Parents) {
+                test_body_with_options(
+                    graph_setting,
+                    sorting,
+                    parents,
+                    $options,
+                    &[$($arg),*],
+                    &[$($spec),+],
+                );
+            }
+        };
+    }
+
     #[cfg(feature = "alltests")]
     include!("generated_tests.rs");
 
@@ -928,4 +1630,417 @@ mod tests {
         "bb8601cfa2f3bb33f9a8a9bdc4d66e3b598cddff",
         "^616867d9e3b817f505d4044ee3d81cfd348d579f"
     );
+
+    topo_test!(
+        symmetric,
+        "bb482759d46e81f0f51d7845d86d2dae93b8b3da...616867d9e3b817f505d4044ee3d81cfd348d579f"
+    );
+    topo_test!(
+        symmetric_one_side_empty,
+        "bb482759d46e81f0f51d7845d86d2dae93b8b3da...d87231e63272c03850847902b86f0358e161210c"
+    );
+    topo_test!(only_merge, "bb8601cfa2f3bb33f9a8a9bdc4d66e3b598cddff^!");
+    topo_test!(
+        parents_of_merge,
+        "bb8601cfa2f3bb33f9a8a9bdc4d66e3b598cddff^@"
+    );
+    topo_test!(only_root, "753d1dba0c677cdb2f32be664faaff55856ede66^!");
+
+    topo_test_with_options!(
+        reverse,
+        |b| b.reverse(true),
+        ["--reverse"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        reverse_range,
+        |b| b.reverse(true),
+        ["--reverse"],
+        "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        reverse_ancestry_path,
+        |b| b.reverse(true).ancestry_path(),
+        ["--reverse", "--ancestry-path"],
+        "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+
+    #[test_matrix(
+        [ UseGraph, NoGraph ],
+        [ DateOrder, TopoOrder ],
+        [ false, true ]
+    )]
+    fn children(graph_setting: GraphSetting, sorting: Sorting, reverse: bool) {
+        let store = gix_odb::at("../.git/objects").expect("find objects");
+        let spec =
+            "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96";
+
+        let commit_graph = match graph_setting {
+            UseGraph => Some(
+                gix_commitgraph::at(store.store_ref().path().join("info"))
+                    .expect("commit graph available"),
+            ),
+            NoGraph => None,
+        };
+
+        let mut walk = Builder::from_specs(&store, [simple_parse(spec)])
+            .with_commit_graph(commit_graph)
+            .sorting(sorting)
+            .reverse(reverse)
+            .with_children(true)
+            .build()
+            .unwrap();
+
+        let mut children = vec![];
+        while let Some(info) = walk.next() {
+            let info = info.unwrap();
+            let mut c = walk.children(&info.id).unwrap().to_vec();
+            c.sort();
+            children.push((info.id, c));
+        }
+        children.sort();
+
+        let output = std::process::Command::new("git")
+            .args(["rev-list", "--children", spec])
+            .output()
+            .expect("able to run git rev-list")
+            .stdout;
+
+        let mut git_children = std::str::from_utf8(&output)
+            .expect("sensible output from git rev-list")
+            .split_terminator('\n')
+            .map(|l| {
+                let mut ids = l
+                    .split_ascii_whitespace()
+                    .map(|id| ObjectId::from_str(id).expect("rev-list returns valid object ids"));
+                let id = ids.next().expect("at least one object id");
+                let mut c = ids.collect::<Vec<_>>();
+                c.sort();
+                (id, c)
+            })
+            .collect::<Vec<_>>();
+        git_children.sort();
+
+        assert_eq!(children, git_children);
+    }
+
+    #[test_case("b282e76b1322e1d26ef002968e1591bd8f22df96")]
+    #[test_case(
+        "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96"
+    )]
+    fn walk2(spec: &str) {
+        let store = gix_odb::at("../.git/objects").expect("find objects");
+
+        let (tips, ends) = match simple_parse(spec) {
+            gix_revision::Spec::Include(id) => (vec![id], vec![]),
+            gix_revision::Spec::Range { from, to } => (vec![to], vec![from]),
+            _ => unreachable!("only plain revisions and ranges are tested"),
+        };
+
+        let walk = Walk2::new(&store, tips, ends).unwrap();
+        let ids = walk.collect::<Result<Vec<_>, _>>().unwrap();
+        let git_ids = git_rev_list(NoGraph, DateOrder, All, &[], &[spec]);
+
+        assert_eq!(ids, git_ids);
+    }
+
+    #[test]
+    fn details() {
+        let store = gix_odb::at("../.git/objects").expect("find objects");
+        let spec =
+            "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96";
+
+        let walk = |commit_graph| {
+            Builder::from_specs(&store, [simple_parse(spec)])
+                .with_commit_graph(commit_graph)
+                .with_details(true)
+                .build()
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+
+        let with_graph = walk(Some(
+            gix_commitgraph::at(store.store_ref().path().join("info"))
+                .expect("commit graph available"),
+        ));
+        let without_graph = walk(None);
+
+        // Without the commit-graph only the generation numbers the walk needed
+        // are known, and they should agree with it
+        for (with, without) in with_graph.iter().zip(&without_graph) {
+            let with = with.details.as_ref().expect("details requested");
+            let without = without.details.as_ref().expect("details requested");
+            assert!(with.generation.is_some());
+            assert!(without.generation.is_none() || without.generation == with.generation);
+        }
+
+        let without_generation = |infos: &[Info]| {
+            infos
+                .iter()
+                .map(|info| {
+                    let details = info.details.as_ref().map(|d| (d.commit_time, d.tree_id));
+                    (info.id, info.parent_ids.clone(), info.side, details)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            without_generation(&with_graph),
+            without_generation(&without_graph)
+        );
+
+        let output = std::process::Command::new("git")
+            .args(["log", "--topo-order", "--format=%H %T %ct %an <%ae>", spec])
+            .output()
+            .expect("able to run git log")
+            .stdout;
+
+        let git_details = std::str::from_utf8(&output)
+            .expect("sensible output from git log")
+            .split_terminator('\n')
+            .map(|l| l.splitn(4, ' ').map(str::to_owned).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let mut buf = vec![];
+        let details = without_graph
+            .iter()
+            .map(|info| {
+                let d = info.details.as_ref().expect("details requested");
+                let author = info.signatures(&store, &mut buf).unwrap().author;
+                vec![
+                    info.id.to_string(),
+                    d.tree_id.to_string(),
+                    d.commit_time.to_string(),
+                    format!("{} <{}>", author.name, author.email),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(details, git_details);
+    }
+
+    #[test_matrix(
+        [ DateOrder, TopoOrder, AuthorDateOrder ],
+        [ All, First ]
+    )]
+    fn pruning(sorting: Sorting, parents: Parents) {
+        let store = gix_odb::at("../.git/objects").expect("find objects");
+        let tip = ObjectId::from_str("b282e76b1322e1d26ef002968e1591bd8f22df96").unwrap();
+        let cut = ObjectId::from_str("3be8265bc3f7d982170bd475be3b82cb140643b9").unwrap();
+
+        let walk = |find, visit: Visit| {
+            Builder::from_iters(find, [tip], None::<Vec<ObjectId>>)
+                .sorting(sorting)
+                .parents(parents)
+                .with_pruning(move |id| {
+                    if id == cut.as_ref() {
+                        visit
+                    } else {
+                        Visit::Keep
+                    }
+                })
+                .build()
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+
+        // Pruning the parents of a commit is the same as a shallow clone
+        // that ends there
+        let unpruned = shallow::Shallow::new(&store, None::<ObjectId>);
+        let mut expected = walk(&shallow::Shallow::new(&store, [cut]), Visit::Keep);
+        assert_eq!(walk(&unpruned, Visit::PruneParents), expected);
+
+        expected.retain(|info| info.id != cut);
+        assert_eq!(walk(&unpruned, Visit::HideAndPruneParents), expected);
+
+        // Hiding a commit is the same as filtering it out with a predicate
+        let mut expected = git_rev_list(NoGraph, sorting, parents, &[], &[&tip.to_string()]);
+        expected.retain(|info| info.id != cut);
+        assert_eq!(walk(&unpruned, Visit::Hide), expected);
+    }
+
+    topo_test_with_options!(
+        ancestry_path,
+        |b| b.ancestry_path(),
+        ["--ancestry-path"],
+        "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        ancestry_path_two_ends,
+        |b| b.ancestry_path(),
+        ["--ancestry-path"],
+        "d87231e63272c03850847902b86f0358e161210c",
+        "^3be8265bc3f7d982170bd475be3b82cb140643b9",
+        "^bb482759d46e81f0f51d7845d86d2dae93b8b3da"
+    );
+    topo_test_with_options!(
+        ancestry_path_through,
+        |b| b.ancestry_path_through(
+            ObjectId::from_str("bb8601cfa2f3bb33f9a8a9bdc4d66e3b598cddff").unwrap()
+        ),
+        ["--ancestry-path=bb8601cfa2f3bb33f9a8a9bdc4d66e3b598cddff"],
+        "753d1dba0c677cdb2f32be664faaff55856ede66..b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+
+    topo_test_with_options!(
+        max_count,
+        |b| b.max_count(5),
+        ["--max-count=5"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        skip_and_max_count,
+        |b| b.skip(3).max_count(5),
+        ["--skip=3", "--max-count=5"],
+        "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        since,
+        |b| b.since(1_697_000_000),
+        ["--since=1697000000"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        until,
+        |b| b.until(1_697_000_000),
+        ["--until=1697000000"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        merges,
+        |b| b.min_parents(2),
+        ["--merges"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        no_merges,
+        |b| b.max_parents(1),
+        ["--no-merges"],
+        "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        author_and_grep,
+        |b| b.author("Walle").grep("test").grep("Test"),
+        [
+            "--fixed-strings",
+            "--author=Walle",
+            "--grep=test",
+            "--grep=Test"
+        ],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        reverse_max_count,
+        |b| b.reverse(true).max_count(4),
+        ["--reverse", "--max-count=4"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+
+    topo_test_with_options!(
+        boundary,
+        |b| b.boundary(true),
+        ["--boundary"],
+        "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        boundary_max_count,
+        |b| b.boundary(true).max_count(5),
+        ["--boundary", "--max-count=5"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        boundary_symmetric,
+        |b| b.boundary(true),
+        ["--boundary"],
+        "3be8265bc3f7d982170bd475be3b82cb140643b9...b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+
+    topo_test_with_options!(
+        path_limited,
+        |b| b.paths(["lorem-ipsum.txt"]),
+        ["--", "lorem-ipsum.txt"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    #[test_matrix(
+        [ DateOrder, TopoOrder, AuthorDateOrder ],
+        [ All, First ]
+    )]
+    fn path_limited_bloom(sorting: Sorting, parents: Parents) {
+        let tip = "b282e76b1322e1d26ef002968e1591bd8f22df96";
+
+        // Let Git write the filters, so the walk is checked against filters
+        // it didn't write itself
+        let scratch = crate::scratch::Scratch::borrowing_objects("path-limited-bloom");
+        assert!(scratch
+            .git(&["update-ref", "refs/heads/main", tip])
+            .status
+            .success());
+        let output = scratch.git(&["commit-graph", "write", "--reachable", "--changed-paths"]);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let store = scratch.store();
+        let info_dir = scratch.dir.join("objects/info");
+        let changed_paths = bloom::ChangedPaths::at(&info_dir).expect("changed-path filters");
+        assert!(!changed_paths.is_empty());
+
+        let walk = Builder::from_specs(&store, [simple_parse(tip)])
+            .with_commit_graph(Some(
+                gix_commitgraph::at(&info_dir).expect("commit graph available"),
+            ))
+            .with_changed_paths(Some(changed_paths))
+            .paths(["lorem-ipsum.txt"])
+            .sorting(sorting)
+            .parents(parents)
+            .build()
+            .unwrap();
+
+        let ids = walk.collect::<Result<Vec<_>, _>>().unwrap();
+        let git_ids = git_rev_list(
+            NoGraph,
+            sorting,
+            parents,
+            &["--", "lorem-ipsum.txt"],
+            &[tip],
+        );
+
+        assert_eq!(
+            ids, git_ids,
+            "left = ids, right = git_ids, flags = {parents:?} {sorting:?}"
+        );
+    }
+    topo_test_with_options!(
+        path_limited_range,
+        |b| b.paths(["lorem-ipsum.txt"]),
+        ["--", "lorem-ipsum.txt"],
+        "3be8265bc3f7d982170bd475be3b82cb140643b9..b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        path_limited_full_history,
+        |b| b
+            .paths(["lorem-ipsum.txt"])
+            .simplification(Simplification::FullHistory),
+        ["--full-history", "--", "lorem-ipsum.txt"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        path_limited_simplify_merges,
+        |b| b
+            .paths(["lorem-ipsum.txt"])
+            .simplification(Simplification::SimplifyMerges),
+        ["--simplify-merges", "--", "lorem-ipsum.txt"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
+    topo_test_with_options!(
+        path_limited_sparse,
+        |b| b
+            .paths(["lorem-ipsum.txt"])
+            .simplification(Simplification::Sparse),
+        ["--sparse", "--", "lorem-ipsum.txt"],
+        "b282e76b1322e1d26ef002968e1591bd8f22df96"
+    );
 }
@@ -0,0 +1,150 @@
+//! Limiting which commits a walk returns, like `git rev-list --max-count`,
+//! `--since`, `--merges`, `--author` and friends.
+//!
+//! Most limits only hide commits, but `--since` also stops the walk at commits
+//! that are too old by marking them as uninteresting, like Git does. A walk
+//! with a max count ends as soon as enough commits have been returned.
+
+use gix_hash::oid;
+use gix_object::bstr::{BString, ByteSlice};
+
+use super::{find, Either, Error, Info, Parents, Visit, Walk, WalkFlags};
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Limits {
+    pub(crate) max_count: Option<usize>,
+    pub(crate) skip: usize,
+    pub(crate) since: Option<i64>,
+    pub(crate) until: Option<i64>,
+    pub(crate) min_parents: usize,
+    pub(crate) max_parents: Option<usize>,
+    pub(crate) authors: Vec<BString>,
+    pub(crate) committers: Vec<BString>,
+    pub(crate) messages: Vec<BString>,
+    pub(crate) all_match: bool,
+    /// How many commits have been skipped and returned so far
+    skipped: usize,
+    returned: usize,
+}
+
+impl Limits {
+    fn needs_commit(&self) -> bool {
+        !(self.authors.is_empty() && self.committers.is_empty() && self.messages.is_empty())
+    }
+}
+
+/// Check a `Name <email>` line or message against fixed string patterns.
+fn matches_any(patterns: &[BString], haystack: &[u8]) -> bool {
+    patterns.is_empty() || patterns.iter().any(|p| haystack.contains_str(p))
+}
+
+impl<Find, Predicate, Prune> Walk<Find, Predicate, Prune>
+where
+    Find: gix_object::Find,
+    Prune: FnMut(&oid) -> Visit,
+{
+    /// Whether the walk has returned as many commits as it should.
+    pub(crate) fn limit_reached(&self) -> bool {
+        self.limits
+            .max_count
+            .is_some_and(|max| self.limits.returned >= max)
+    }
+
+    /// Mark the commit as uninteresting if it's older than the `since`
+    /// limit, so none of its ancestors are walked either.
+    pub(crate) fn mark_too_old(&mut self, id: &oid) -> Result<(), Error> {
+        let Some(since) = self.limits.since else {
+            return Ok(());
+        };
+
+        let (_, time) = self.gen_and_commit_time(id)?;
+        if time < since {
+            *self.states.get_mut(id).ok_or(Error::MissingState)? |= WalkFlags::Uninteresting;
+        }
+
+        Ok(())
+    }
+
+    /// Decide whether to return a commit that is otherwise shown, and count
+    /// it towards the skip and max count if so.
+    pub(crate) fn limit_commit(&mut self, info: &Info) -> Result<bool, Error> {
+        if !self.passes_limits(info)? {
+            return Ok(false);
+        }
+
+        if self.limits.skipped < self.limits.skip {
+            self.limits.skipped += 1;
+            return Ok(false);
+        }
+
+        self.limits.returned += 1;
+
+        Ok(true)
+    }
+
+    fn passes_limits(&mut self, info: &Info) -> Result<bool, Error> {
+        if self.limits.since.is_some() || self.limits.until.is_some() {
+            let (_, time) = self.gen_and_commit_time(&info.id)?;
+
+            if self.limits.since.is_some_and(|since| time < since)
+                || self.limits.until.is_some_and(|until| time > until)
+            {
+                return Ok(false);
+            }
+        }
+
+        // Parents are rewritten when limiting to paths, otherwise count them
+        // all even if only the first is followed, like Git
+        let num_parents = if matches!(self.parents, Parents::First) && self.path_limit.is_none() {
+            self.collect_all_parents(&info.id)?.len()
+        } else {
+            info.parent_ids.len()
+        };
+
+        if num_parents < self.limits.min_parents
+            || self.limits.max_parents.is_some_and(|max| num_parents > max)
+        {
+            return Ok(false);
+        }
+
+        if !self.limits.needs_commit() {
+            return Ok(true);
+        }
+
+        let Either::CommitRefIter(commit) = find(None, &self.find, &info.id, &mut self.buf)? else {
+            unreachable!("no commit-graph given");
+        };
+
+        let mut author = BString::default();
+        let mut committer = BString::default();
+        let mut message = BString::default();
+
+        for token in commit {
+            use gix_object::commit::ref_iter::Token as T;
+            match token? {
+                T::Author { signature } => {
+                    author = format!("{} <{}>", signature.name, signature.email).into();
+                }
+                T::Committer { signature } => {
+                    committer = format!("{} <{}>", signature.name, signature.email).into();
+                }
+                T::Message(m) => {
+                    message = m.to_owned();
+                    break;
+                }
+                _ => continue,
+            }
+        }
+
+        let limits = &self.limits;
+        let message_matches = if limits.all_match {
+            limits.messages.iter().all(|p| message.contains_str(p))
+        } else {
+            matches_any(&limits.messages, &message)
+        };
+
+        Ok(matches_any(&limits.authors, &author)
+            && matches_any(&limits.committers, &committer)
+            && message_matches)
+    }
+}
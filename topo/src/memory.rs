@@ -0,0 +1,337 @@
+//! Objects kept in memory instead of in a repository, to walk synthetic
+//! histories. [`Dag`] builds the commits of a graph, with whatever parents and
+//! times a test or an embedding application needs, including octopus merges,
+//! clock skew and equal timestamps that are hard to come by in a real
+//! repository.
+
+use gix_hash::{oid, ObjectId};
+use gix_object::Kind;
+use gix_revwalk::graph::IdMap;
+
+/// An object database in memory.
+#[derive(Clone, Debug, Default)]
+pub struct Store {
+    objects: IdMap<(Kind, Vec<u8>)>,
+}
+
+impl Store {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an encoded object, and return its id.
+    pub fn insert(&mut self, kind: Kind, data: impl Into<Vec<u8>>) -> ObjectId {
+        let data = data.into();
+        let id = gix_object::compute_hash(gix_hash::Kind::Sha1, kind, &data);
+        self.objects.insert(id, (kind, data));
+        id
+    }
+
+    /// Whether the object is in the store.
+    pub fn contains(&self, id: &oid) -> bool {
+        self.objects.contains_key(id)
+    }
+
+    /// The number of objects in the store.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Whether the store has no objects.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl gix_object::Find for Store {
+    fn try_find<'a>(
+        &self,
+        id: &oid,
+        buffer: &'a mut Vec<u8>,
+    ) -> Result<Option<gix_object::Data<'a>>, gix_object::find::Error> {
+        let Some((kind, data)) = self.objects.get(id) else {
+            return Ok(None);
+        };
+
+        buffer.clear();
+        buffer.extend_from_slice(data);
+
+        Ok(Some(gix_object::Data {
+            kind: *kind,
+            data: buffer.as_slice(),
+        }))
+    }
+}
+
+/// Builds a commit graph in a [`Store`]. All commits have the same empty
+/// tree, and each gets a message with a counter so that commits with the same
+/// parents and times still have different ids.
+#[derive(Clone, Debug)]
+pub struct Dag {
+    store: Store,
+    tree: ObjectId,
+    count: usize,
+}
+
+impl Default for Dag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dag {
+    /// Create a graph without commits.
+    pub fn new() -> Self {
+        let mut store = Store::new();
+        let tree = store.insert(Kind::Tree, vec![]);
+
+        Self {
+            store,
+            tree,
+            count: 0,
+        }
+    }
+
+    /// Add a commit with the given parents, in order, that was authored and
+    /// committed at `time`, in seconds since the epoch. Returns its id.
+    pub fn commit(&mut self, parents: &[ObjectId], time: i64) -> ObjectId {
+        self.commit_with_times(parents, time, time)
+    }
+
+    /// Add a commit like [`Dag::commit`], with different author and commit
+    /// times, for walks in [`AuthorDateOrder`](crate::Sorting::AuthorDateOrder).
+    pub fn commit_with_times(
+        &mut self,
+        parents: &[ObjectId],
+        author_time: i64,
+        commit_time: i64,
+    ) -> ObjectId {
+        use std::fmt::Write;
+
+        let mut commit = format!("tree {}\n", self.tree);
+        for parent in parents {
+            assert!(self.store.contains(parent), "parent {parent} is missing");
+            writeln!(commit, "parent {parent}").expect("writing to a String");
+        }
+        writeln!(
+            commit,
+            "author A U Thor <author@example.com> {author_time} +0000"
+        )
+        .expect("writing to a String");
+        writeln!(
+            commit,
+            "committer C O Mitter <committer@example.com> {commit_time} +0000"
+        )
+        .expect("writing to a String");
+        writeln!(commit, "\ncommit {}", self.count).expect("writing to a String");
+
+        self.count += 1;
+        self.store.insert(Kind::Commit, commit)
+    }
+
+    /// The store with the commits added so far.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Take the store with all commits.
+    pub fn into_store(self) -> Store {
+        self.store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use test_case::test_matrix;
+
+    use crate::{Builder, Info, Parents, Sorting, Walk2};
+    use Parents::{All, First};
+    use Sorting::{AuthorDateOrder, DateOrder, TopoOrder};
+
+    /// A xorshift generator, to get the same graphs on every run without
+    /// depending on a crate for it.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    /// A random graph with commits, tips and ends. If `distinct_times` isn't
+    /// set, many commits share their commit time. Either way commits can be
+    /// older than their parents.
+    fn random_dag(seed: u64, distinct_times: bool) -> (Dag, Vec<ObjectId>, Vec<ObjectId>) {
+        let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1);
+        let mut dag = Dag::new();
+        let mut commits = Vec::<ObjectId>::new();
+
+        let n = 1 + rng.below(40);
+        for i in 0..n {
+            let mut parents = vec![];
+            if !commits.is_empty() {
+                // Mostly linear history with some merges, octopus merges
+                // and additional roots
+                let n_parents = [1, 1, 1, 1, 2, 2, 3, 4, 0][rng.below(9)];
+                for _ in 0..n_parents {
+                    let parent = commits[rng.below(commits.len())];
+                    if !parents.contains(&parent) {
+                        parents.push(parent);
+                    }
+                }
+            }
+
+            let time = if distinct_times {
+                1_700_000_000 + rng.below(1000) as i64 * 100 + i as i64
+            } else {
+                1_700_000_000 + rng.below(4) as i64
+            };
+            let author_time = 1_700_000_000 + rng.below(1000) as i64;
+
+            commits.push(dag.commit_with_times(&parents, author_time, time));
+        }
+
+        let tips = (0..1 + rng.below(3))
+            .map(|_| commits[rng.below(n)])
+            .collect();
+        let ends = (0..rng.below(3)).map(|_| commits[rng.below(n)]).collect();
+
+        (dag, tips, ends)
+    }
+
+    fn walk(
+        dag: &Dag,
+        tips: &[ObjectId],
+        ends: &[ObjectId],
+        sorting: Sorting,
+        parents: Parents,
+    ) -> Vec<Info> {
+        Builder::from_iters(dag.store(), tips.to_vec(), Some(ends.to_vec()))
+            .sorting(sorting)
+            .parents(parents)
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    /// Check that no commit comes after one of its parents.
+    fn assert_topological(infos: &[Info]) {
+        let positions = infos
+            .iter()
+            .enumerate()
+            .map(|(i, info)| (info.id, i))
+            .collect::<IdMap<_>>();
+
+        for (i, info) in infos.iter().enumerate() {
+            for parent_id in &info.parent_ids {
+                if let Some(&j) = positions.get(parent_id) {
+                    assert!(
+                        i < j,
+                        "{} is returned after its parent {parent_id}",
+                        info.id
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn finds_inserted_objects() {
+        let mut dag = Dag::new();
+        let root = dag.commit(&[], 1_700_000_000);
+        let same_time = dag.commit(&[], 1_700_000_000);
+        assert_ne!(root, same_time);
+
+        let store = dag.into_store();
+        let mut buf = vec![];
+        let data = gix_object::Find::try_find(&store, &root, &mut buf)
+            .unwrap()
+            .expect("commit is stored");
+        assert_eq!(data.kind, Kind::Commit);
+
+        let missing = ObjectId::null(gix_hash::Kind::Sha1);
+        assert!(gix_object::Find::try_find(&store, &missing, &mut buf)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn octopus_merge_and_clock_skew() {
+        let mut dag = Dag::new();
+        let root = dag.commit(&[], 100);
+        // The branches are all committed before the root
+        let a = dag.commit(&[root], 50);
+        let b = dag.commit(&[root], 60);
+        let c = dag.commit(&[root], 60);
+        let merge = dag.commit(&[a, b, c], 70);
+
+        for sorting in [DateOrder, TopoOrder, AuthorDateOrder] {
+            let infos = walk(&dag, &[merge], &[], sorting, All);
+            assert_topological(&infos);
+            assert_eq!(infos[0].id, merge);
+            assert_eq!(infos[0].parent_ids.as_slice(), &[a, b, c]);
+            assert_eq!(infos.last().map(|info| info.id), Some(root));
+        }
+
+        let first_parents = walk(&dag, &[merge], &[], TopoOrder, First)
+            .into_iter()
+            .map(|info| info.id)
+            .collect::<Vec<_>>();
+        assert_eq!(first_parents, vec![merge, a, root]);
+    }
+
+    #[test]
+    fn walk_agrees_with_walk2() {
+        for seed in 0..200 {
+            let (dag, tips, ends) = random_dag(seed, true);
+
+            let ids = walk(&dag, &tips, &ends, DateOrder, All);
+            let ids2 = Walk2::new(dag.store(), tips.clone(), ends.clone())
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+
+            assert_eq!(ids, ids2, "seed {seed}, tips {tips:?}, ends {ends:?}");
+        }
+    }
+
+    // With equal commit times the order between unrelated commits depends on
+    // how ties are broken, so only the commits and the topological order are
+    // compared here
+    #[test_matrix(
+        [ DateOrder, TopoOrder, AuthorDateOrder ],
+        [ All, First ]
+    )]
+    fn walk_is_topological(sorting: Sorting, parents: Parents) {
+        for seed in 0..200 {
+            let (dag, tips, ends) = random_dag(seed, false);
+
+            let infos = walk(&dag, &tips, &ends, sorting, parents);
+            assert_topological(&infos);
+
+            if matches!(parents, All) {
+                let mut ids = infos.iter().map(|info| info.id).collect::<Vec<_>>();
+                let mut ids2 = Walk2::new(dag.store(), tips.clone(), ends.clone())
+                    .unwrap()
+                    .map(|info| info.unwrap().id)
+                    .collect::<Vec<_>>();
+                ids.sort();
+                ids2.sort();
+
+                assert_eq!(ids, ids2, "seed {seed}, tips {tips:?}, ends {ends:?}");
+            }
+        }
+    }
+}
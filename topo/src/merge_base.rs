@@ -0,0 +1,88 @@
+//! Finding merge bases, like `git merge-base --all`, using the same generation
+//! numbers and commit times as [`Walk`](crate::Walk).
+
+use gix_hash::{oid, ObjectId};
+use gix_revwalk::{graph::IdMap, PriorityQueue};
+
+use flagset::{flags, FlagSet};
+
+use super::{collect_parents, find, get_gen_and_commit_time, Error, GenAndCommitTime};
+
+flags! {
+    /// Flags used to paint commits while looking for merge bases
+    enum Paint: u8 {
+        /// Commit is reachable from `one`
+        Parent1,
+        /// Commit is reachable from one of `others`
+        Parent2,
+        /// Commit is an ancestor of a merge base and can't be a better one
+        Stale,
+        /// Commit has been added to the result
+        Result,
+    }
+}
+
+/// Find all commits reachable from both `one` and one of `others` that are not
+/// ancestors of another such commit. This is the same as `paint_down_to_common()`
+/// in Git, and might return some redundant merge bases.
+pub(crate) fn merge_base_candidates<Find>(
+    cache: Option<&gix_commitgraph::Graph>,
+    f: &Find,
+    one: &oid,
+    others: &[ObjectId],
+    buf: &mut Vec<u8>,
+) -> Result<Vec<ObjectId>, Error>
+where
+    Find: gix_object::Find,
+{
+    let mut states = IdMap::<FlagSet<Paint>>::default();
+    let mut queue = PriorityQueue::<GenAndCommitTime, ObjectId>::new();
+    let mut result = vec![];
+
+    let gen_time = get_gen_and_commit_time(find(cache, f, one, buf)?)?;
+    *states.entry(one.to_owned()).or_default() |= Paint::Parent1;
+    queue.insert(gen_time, one.to_owned());
+
+    for id in others {
+        let gen_time = get_gen_and_commit_time(find(cache, f, id, buf)?)?;
+        *states.entry(*id).or_default() |= Paint::Parent2;
+        queue.insert(gen_time, *id);
+    }
+
+    let both = Paint::Parent1 | Paint::Parent2;
+
+    while queue
+        .iter_unordered()
+        .any(|id| !states[id].contains(Paint::Stale))
+    {
+        let Some((_, id)) = queue.pop() else {
+            break;
+        };
+
+        let state = states.get_mut(&id).ok_or(Error::MissingState)?;
+        let mut flags = *state & (both | Paint::Stale);
+
+        if flags == both {
+            if !state.contains(Paint::Result) {
+                *state |= Paint::Result;
+                result.push(id);
+            }
+            flags |= Paint::Stale;
+        }
+
+        for (parent_id, gen_time) in collect_parents(cache, f, &id, false, buf)? {
+            let parent_state = states.entry(parent_id).or_default();
+
+            if parent_state.contains(flags) {
+                continue;
+            }
+
+            *parent_state |= flags;
+            queue.insert(gen_time, parent_id);
+        }
+    }
+
+    result.retain(|id| !states[id].contains(Paint::Stale));
+
+    Ok(result)
+}
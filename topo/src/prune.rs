@@ -0,0 +1,76 @@
+//! Pruning the walk with a hook that decides, per commit, whether to return it
+//! and whether to walk its parents. See [`Builder::with_pruning`].
+//!
+//! A commit whose parents are pruned is treated as a root commit everywhere
+//! in the walk. That includes the indegree counting, so the topological order
+//! of the commits that are returned still holds, and computing generation
+//! numbers, so the history behind it is never looked at.
+//!
+//! [`Builder::with_pruning`]: super::Builder::with_pruning
+
+use gix_hash::oid;
+
+use super::{Walk, WalkFlags};
+
+/// What to do with a commit, as decided by the hook given to
+/// [`Builder::with_pruning`](super::Builder::with_pruning).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Visit {
+    /// Return the commit and walk its parents.
+    #[default]
+    Keep,
+
+    /// Don't return the commit, but walk its parents like a commit that is
+    /// filtered out by a predicate.
+    Hide,
+
+    /// Return the commit, but don't walk any further. It's returned without
+    /// parents.
+    PruneParents,
+
+    /// Neither return the commit nor walk any further, which cuts off
+    /// everything that is only reachable through it.
+    HideAndPruneParents,
+}
+
+impl Visit {
+    fn hides(self) -> bool {
+        matches!(self, Self::Hide | Self::HideAndPruneParents)
+    }
+
+    fn prunes_parents(self) -> bool {
+        matches!(self, Self::PruneParents | Self::HideAndPruneParents)
+    }
+}
+
+impl<Find, Predicate, Prune> Walk<Find, Predicate, Prune>
+where
+    Find: gix_object::Find,
+    Prune: FnMut(&oid) -> Visit,
+{
+    /// Ask the hook what to do with a commit, once per commit.
+    fn visit(&mut self, id: &oid) -> Visit {
+        let Some(hook) = &mut self.pruning else {
+            return Visit::Keep;
+        };
+
+        *self.visits.entry(id.to_owned()).or_insert_with(|| hook(id))
+    }
+
+    /// Whether the commit is hidden by the hook.
+    pub(crate) fn hidden(&mut self, id: &oid) -> bool {
+        self.visit(id).hides()
+    }
+
+    /// Whether the parents of the commit are pruned by the hook. The parents
+    /// of uninteresting commits are always walked, or commits that are only
+    /// excluded through them would be returned.
+    pub(crate) fn parents_pruned(&mut self, id: &oid) -> bool {
+        let uninteresting = self
+            .states
+            .get(id)
+            .is_some_and(|s| s.contains(WalkFlags::Uninteresting));
+
+        !uninteresting && self.visit(id).prunes_parents()
+    }
+}
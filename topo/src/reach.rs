@@ -0,0 +1,372 @@
+//! Reachability queries, like `git merge-base`, `git merge-base --is-ancestor`
+//! and `git rev-list --count --left-right`.
+//!
+//! These take the same object database and optional commit-graph as a
+//! [`Walk`](crate::Walk), and use generation numbers to stop as early as
+//! possible. Generation numbers are computed for commits missing from the
+//! commit-graph, like in the walk.
+
+use gix_hash::{oid, ObjectId};
+use gix_revwalk::{graph::IdMap, PriorityQueue};
+use smallvec::SmallVec;
+
+use flagset::{flags, FlagSet};
+
+use super::{
+    collect_parents, find, generation::generation, get_gen_and_commit_time,
+    merge_base::merge_base_candidates, Error, GenAndCommitTime,
+};
+
+flags! {
+    /// Which tips a commit is reachable from when counting ahead and behind
+    enum Sides: u8 {
+        /// Commit is reachable from `left`
+        Left,
+        /// Commit is reachable from `right`
+        Right,
+    }
+}
+
+/// All the best common ancestors of `one` and any of `others`, newest first,
+/// like `git merge-base --all one others...`.
+pub fn merge_bases<Find>(
+    find: Find,
+    commit_graph: Option<&gix_commitgraph::Graph>,
+    one: impl Into<ObjectId>,
+    others: impl IntoIterator<Item = impl Into<ObjectId>>,
+) -> Result<Vec<ObjectId>, Error>
+where
+    Find: gix_object::Find,
+{
+    let one = one.into();
+    let others = others.into_iter().map(Into::into).collect::<Vec<_>>();
+
+    let mut reach = Reach::new(&find, commit_graph);
+    let candidates = merge_base_candidates(commit_graph, &find, &one, &others, &mut reach.buf)?;
+
+    reach.remove_redundant(candidates)
+}
+
+/// The best common ancestor of `one` and any of `others`, like `git
+/// merge-base one others...`. If there are several, the newest is returned.
+pub fn merge_base<Find>(
+    find: Find,
+    commit_graph: Option<&gix_commitgraph::Graph>,
+    one: impl Into<ObjectId>,
+    others: impl IntoIterator<Item = impl Into<ObjectId>>,
+) -> Result<Option<ObjectId>, Error>
+where
+    Find: gix_object::Find,
+{
+    Ok(merge_bases(find, commit_graph, one, others)?
+        .into_iter()
+        .next())
+}
+
+/// Whether `ancestor` is reachable from `descendant`, like `git merge-base
+/// --is-ancestor ancestor descendant`. A commit is its own ancestor.
+pub fn is_ancestor<Find>(
+    find: Find,
+    commit_graph: Option<&gix_commitgraph::Graph>,
+    ancestor: impl Into<ObjectId>,
+    descendant: impl Into<ObjectId>,
+) -> Result<bool, Error>
+where
+    Find: gix_object::Find,
+{
+    Reach::new(&find, commit_graph).is_ancestor(&ancestor.into(), &descendant.into())
+}
+
+/// How many commits are reachable from `left` but not `right`, and the other
+/// way around, like `git rev-list --count --left-right left...right`.
+pub fn ahead_behind<Find>(
+    find: Find,
+    commit_graph: Option<&gix_commitgraph::Graph>,
+    left: impl Into<ObjectId>,
+    right: impl Into<ObjectId>,
+) -> Result<(usize, usize), Error>
+where
+    Find: gix_object::Find,
+{
+    let mut reach = Reach::new(&find, commit_graph);
+
+    let mut states = IdMap::<FlagSet<Sides>>::default();
+    let mut queue = PriorityQueue::<GenAndCommitTime, ObjectId>::new();
+
+    for (id, side) in [(left.into(), Sides::Left), (right.into(), Sides::Right)] {
+        let state = states.entry(id).or_default();
+        if state.is_empty() {
+            queue.insert(reach.gen_and_commit_time(&id)?, id);
+        }
+        *state |= side;
+    }
+
+    let both = Sides::Left | Sides::Right;
+    let (mut ahead, mut behind) = (0, 0);
+
+    // How many commits in the queue aren't reachable from both sides yet,
+    // like Git's queue_has_nonstale() but without going through the queue
+    let mut nonstale = queue
+        .iter_unordered()
+        .filter(|id| states[*id] != both)
+        .count();
+
+    // Children have higher generation numbers than their parents, so a commit
+    // is reachable from all the sides it's going to be when it's popped. Like
+    // Git, stop when all that's left is reachable from both.
+    while nonstale > 0 {
+        let Some((_, id)) = queue.pop() else {
+            break;
+        };
+
+        let flags = states[&id];
+        if flags == FlagSet::from(Sides::Left) {
+            ahead += 1;
+        } else if flags == FlagSet::from(Sides::Right) {
+            behind += 1;
+        }
+        if flags != both {
+            nonstale -= 1;
+        }
+
+        for (parent_id, gen_time) in reach.parents(&id)? {
+            let state = states.entry(parent_id).or_default();
+            let before = *state;
+            *state |= flags;
+
+            if before.is_empty() {
+                queue.insert(gen_time, parent_id);
+                if *state != both {
+                    nonstale += 1;
+                }
+            } else if before != both && *state == both {
+                // Still in the queue, it can only have been popped once all
+                // its children were
+                nonstale -= 1;
+            }
+        }
+    }
+
+    Ok((ahead, behind))
+}
+
+/// What's needed to answer a query, including the generation numbers that have
+/// been computed so far.
+struct Reach<'a, Find> {
+    cache: Option<&'a gix_commitgraph::Graph>,
+    find: &'a Find,
+    generations: IdMap<u32>,
+    buf: Vec<u8>,
+}
+
+impl<'a, Find> Reach<'a, Find>
+where
+    Find: gix_object::Find,
+{
+    fn new(find: &'a Find, cache: Option<&'a gix_commitgraph::Graph>) -> Self {
+        Self {
+            cache,
+            find,
+            generations: IdMap::default(),
+            buf: vec![],
+        }
+    }
+
+    fn gen_and_commit_time(&mut self, id: &oid) -> Result<GenAndCommitTime, Error> {
+        let gen_time = get_gen_and_commit_time(find(self.cache, self.find, id, &mut self.buf)?)?;
+        self.with_generation(id, gen_time)
+    }
+
+    fn with_generation(
+        &mut self,
+        id: &oid,
+        (gen, time): GenAndCommitTime,
+    ) -> Result<GenAndCommitTime, Error> {
+        if gen != gix_commitgraph::GENERATION_NUMBER_INFINITY {
+            return Ok((gen, time));
+        }
+
+        let (cache, f, buf) = (self.cache, self.find, &mut self.buf);
+        let gen = generation(id, &mut self.generations, |id| {
+            collect_parents(cache, f, id, false, buf)
+        })?;
+
+        Ok((gen, time))
+    }
+
+    fn parents(&mut self, id: &oid) -> Result<SmallVec<[(ObjectId, GenAndCommitTime); 1]>, Error> {
+        let mut parents = collect_parents(self.cache, self.find, id, false, &mut self.buf)?;
+
+        for (id, gen_time) in &mut parents {
+            *gen_time = self.with_generation(id, *gen_time)?;
+        }
+
+        Ok(parents)
+    }
+
+    fn is_ancestor(&mut self, ancestor: &oid, descendant: &oid) -> Result<bool, Error> {
+        if ancestor == descendant {
+            return Ok(true);
+        }
+
+        // Only commits with a higher generation number than the ancestor can
+        // reach it
+        let (min_gen, _) = self.gen_and_commit_time(ancestor)?;
+        let (gen, _) = self.gen_and_commit_time(descendant)?;
+        if gen <= min_gen {
+            return Ok(false);
+        }
+
+        let mut seen = IdMap::<()>::default();
+        let mut stack = vec![descendant.to_owned()];
+
+        while let Some(id) = stack.pop() {
+            for (parent_id, (gen, _)) in self.parents(&id)? {
+                if parent_id == ancestor {
+                    return Ok(true);
+                }
+
+                if gen > min_gen && seen.insert(parent_id, ()).is_none() {
+                    stack.push(parent_id);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Drop the merge base candidates that are ancestors of others, like
+    /// `remove_redundant()` in Git, and sort the rest newest first.
+    fn remove_redundant(&mut self, candidates: Vec<ObjectId>) -> Result<Vec<ObjectId>, Error> {
+        let mut result = vec![];
+
+        for id in &candidates {
+            let mut redundant = false;
+            for other in &candidates {
+                if other != id && self.is_ancestor(id, other)? {
+                    redundant = true;
+                    break;
+                }
+            }
+
+            if !redundant {
+                let (_, time) = self.gen_and_commit_time(id)?;
+                result.push((time, *id));
+            }
+        }
+
+        result.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+
+        Ok(result.into_iter().map(|(_, id)| id).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{process::Command, str::FromStr};
+    use test_case::test_matrix;
+
+    fn store() -> gix_odb::Handle {
+        gix_odb::at("../.git/objects").expect("find objects")
+    }
+
+    fn commit_graph(use_graph: bool) -> Option<gix_commitgraph::Graph> {
+        use_graph.then(|| {
+            gix_commitgraph::at(store().store_ref().path().join("info"))
+                .expect("commit graph available")
+        })
+    }
+
+    fn id(hex: &str) -> ObjectId {
+        ObjectId::from_str(hex).expect("Valid SHA1 in tests")
+    }
+
+    fn git(args: &[&str]) -> std::process::Output {
+        Command::new("git")
+            .args(args)
+            .output()
+            .expect("able to run git")
+    }
+
+    const A: &str = "3be8265bc3f7d982170bd475be3b82cb140643b9";
+    const B: &str = "b282e76b1322e1d26ef002968e1591bd8f22df96";
+    const C: &str = "d87231e63272c03850847902b86f0358e161210c";
+    const D: &str = "bb482759d46e81f0f51d7845d86d2dae93b8b3da";
+
+    #[test_matrix([true, false], [(A, B), (B, A), (C, D), (A, A)])]
+    fn merge_bases_like_git(use_graph: bool, (one, other): (&str, &str)) {
+        let store = store();
+        let bases = merge_bases(
+            &store,
+            commit_graph(use_graph).as_ref(),
+            id(one),
+            [id(other)],
+        )
+        .unwrap();
+
+        let output = git(&["merge-base", "--all", one, other]).stdout;
+        let mut git_bases = std::str::from_utf8(&output)
+            .expect("sensible output from git merge-base")
+            .split_terminator('\n')
+            .map(id)
+            .collect::<Vec<_>>();
+
+        // Git doesn't promise an order for --all
+        let mut sorted = bases.clone();
+        sorted.sort();
+        git_bases.sort();
+        assert_eq!(sorted, git_bases);
+
+        let output = git(&["merge-base", one, other]).stdout;
+        let git_base = std::str::from_utf8(&output)
+            .expect("sensible output from git merge-base")
+            .split_terminator('\n')
+            .map(id)
+            .next();
+        assert_eq!(bases.first().copied(), git_base);
+    }
+
+    #[test_matrix([true, false], [(A, B), (B, A), (C, D), (D, C), (A, A)])]
+    fn is_ancestor_like_git(use_graph: bool, (ancestor, descendant): (&str, &str)) {
+        let store = store();
+        let result = is_ancestor(
+            &store,
+            commit_graph(use_graph).as_ref(),
+            id(ancestor),
+            id(descendant),
+        )
+        .unwrap();
+
+        let status = git(&["merge-base", "--is-ancestor", ancestor, descendant]).status;
+        assert_eq!(result, status.success());
+    }
+
+    #[test_matrix([true, false], [(A, B), (B, A), (C, D), (A, A)])]
+    fn ahead_behind_like_git(use_graph: bool, (left, right): (&str, &str)) {
+        let store = store();
+        let counts = ahead_behind(
+            &store,
+            commit_graph(use_graph).as_ref(),
+            id(left),
+            id(right),
+        )
+        .unwrap();
+
+        let output = git(&[
+            "rev-list",
+            "--count",
+            "--left-right",
+            &format!("{left}...{right}"),
+        ])
+        .stdout;
+        let git_counts = std::str::from_utf8(&output)
+            .expect("sensible output from git rev-list")
+            .split_ascii_whitespace()
+            .map(|n| n.parse::<usize>().expect("counts are numbers"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![counts.0, counts.1], git_counts);
+    }
+}
@@ -0,0 +1,257 @@
+//! Walking the reflog of a ref, like `git log -g`. Instead of following the
+//! parents of commits, this returns the commits a ref pointed to, newest
+//! first, including ones that aren't reachable from any ref anymore.
+
+use std::path::Path;
+
+use gix_hash::ObjectId;
+use gix_object::bstr::{BString, ByteSlice};
+
+use super::{collect_parents, Error, Info};
+
+/// A change to a ref, and the commit it pointed to after the change.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    /// The commit the ref pointed to after the change. The parents are the
+    /// commit's own, like in `git log -g`.
+    pub info: Info,
+    /// The commit the ref pointed to before the change, which is null if the
+    /// ref was created
+    pub previous_id: ObjectId,
+    /// How to refer to the entry, like `HEAD@{2}`
+    pub selector: String,
+    /// Who changed the ref, and when
+    pub signature: gix_actor::Signature,
+    /// What changed the ref, like `rebase (finish): returning to
+    /// refs/heads/main`
+    pub message: BString,
+}
+
+/// The entries of a reflog, newest first.
+pub struct Reflog<Find> {
+    find: Find,
+    /// The entries without their commit, oldest first to pop from the end
+    entries: Vec<Entry>,
+    buf: Vec<u8>,
+}
+
+impl<Find> Reflog<Find>
+where
+    Find: gix_object::Find,
+{
+    /// Read the reflog of the ref with the full `name`, like `HEAD` or
+    /// `refs/heads/main`, from `logs/` in `dir`. That's the git dir for `HEAD`
+    /// and the common dir for other refs. A ref without a reflog has no
+    /// entries.
+    pub fn at(find: Find, dir: impl AsRef<Path>, name: &str) -> std::io::Result<Self> {
+        let contents = match std::fs::read(dir.as_ref().join("logs").join(name)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        let lines = contents
+            .lines()
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>();
+        let short_name = shorten(name);
+
+        let entries = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let selector = format!("{short_name}@{{{}}}", lines.len() - 1 - i);
+                parse_line(line, selector).ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("malformed reflog of {name}"),
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            find,
+            entries,
+            buf: vec![],
+        })
+    }
+
+    /// The number of entries left.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no entries left.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Shorten the name of a ref like Git does for reflog selectors, so
+/// `refs/heads/main` becomes `main`.
+fn shorten(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+/// Parse a line like `<old> <new> <name> <<email>> <time> <tz>\t<message>`.
+fn parse_line(line: &[u8], selector: String) -> Option<Entry> {
+    let (header, message) = line.split_once_str("\t").unwrap_or((line, &b""[..]));
+    let (previous_id, rest) = header.split_once_str(" ")?;
+    let (id, signature) = rest.split_once_str(" ")?;
+
+    let signature = gix_actor::SignatureRef::from_bytes::<()>(signature).ok()?;
+
+    Some(Entry {
+        info: Info {
+            id: ObjectId::from_hex(id).ok()?,
+            parent_ids: Default::default(),
+            side: None,
+            boundary: false,
+            details: None,
+        },
+        previous_id: ObjectId::from_hex(previous_id).ok()?,
+        selector,
+        signature: signature.into(),
+        message: message.into(),
+    })
+}
+
+impl<Find> Iterator for Reflog<Find>
+where
+    Find: gix_object::Find,
+{
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut entry = self.entries.pop()?;
+
+            // The ref was deleted, there is no commit to show
+            if entry.info.id.is_null() {
+                continue;
+            }
+
+            return Some(
+                collect_parents(None, &self.find, &entry.info.id, false, &mut self.buf).map(
+                    |parents| {
+                        entry.info.parent_ids = parents.into_iter().map(|(id, _)| id).collect();
+                        entry
+                    },
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::scratch::Scratch;
+
+    #[test]
+    fn parses_lines() {
+        let line = b"3be8265bc3f7d982170bd475be3b82cb140643b9 \
+            b282e76b1322e1d26ef002968e1591bd8f22df96 \
+            A U Thor <author@example.com> 1697000000 +0200\t\
+            rebase (finish): returning to refs/heads/main";
+
+        let entry = parse_line(line, "main@{0}".into()).unwrap();
+        assert_eq!(
+            entry.info.id.to_string(),
+            "b282e76b1322e1d26ef002968e1591bd8f22df96"
+        );
+        assert_eq!(
+            entry.previous_id.to_string(),
+            "3be8265bc3f7d982170bd475be3b82cb140643b9"
+        );
+        assert_eq!(entry.signature.email, "author@example.com");
+        assert_eq!(entry.signature.time.seconds, 1697000000);
+        assert_eq!(
+            entry.message,
+            "rebase (finish): returning to refs/heads/main"
+        );
+
+        assert!(parse_line(b"not a reflog line", "HEAD@{0}".into()).is_none());
+    }
+
+    #[test]
+    fn shortens_names() {
+        assert_eq!(shorten("HEAD"), "HEAD");
+        assert_eq!(shorten("refs/heads/main"), "main");
+        assert_eq!(shorten("refs/remotes/origin/main"), "origin/main");
+        assert_eq!(shorten("refs/stash"), "stash");
+    }
+
+    #[test]
+    fn walks_like_git() {
+        let scratch = Scratch::clone("reflog", &[]);
+
+        // Move a branch back and forth, like a rebase that is undone
+        for (target, message) in [
+            (
+                "3be8265bc3f7d982170bd475be3b82cb140643b9",
+                "reset: moving back",
+            ),
+            (
+                "b282e76b1322e1d26ef002968e1591bd8f22df96",
+                "reset: moving forward",
+            ),
+            (
+                "d87231e63272c03850847902b86f0358e161210c",
+                "reset: moving on",
+            ),
+        ] {
+            let args = [
+                "update-ref",
+                "--create-reflog",
+                "-m",
+                message,
+                "refs/heads/reflog-test",
+                target,
+            ];
+            assert!(scratch.git(&args).status.success());
+        }
+
+        let output = scratch
+            .git(&[
+                "log",
+                "-g",
+                "--format=%H%x09%P%x09%gd%x09%gs",
+                "reflog-test",
+            ])
+            .stdout;
+        let git_entries = std::str::from_utf8(&output)
+            .expect("sensible output from git log")
+            .split_terminator('\n')
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+
+        let store = scratch.store();
+        let entries = Reflog::at(&store, &scratch.dir, "refs/heads/reflog-test")
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let parents = entry
+                    .info
+                    .parent_ids
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>();
+                format!(
+                    "{}\t{}\t{}\t{}",
+                    entry.info.id,
+                    parents.join(" "),
+                    entry.selector,
+                    entry.message
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries, git_entries);
+    }
+}
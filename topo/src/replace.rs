@@ -0,0 +1,173 @@
+//! Replace refs, like `git replace`. An object with a ref under
+//! `refs/replace/` named after its id is replaced by the object the ref points
+//! to whenever it's looked up, for example to graft histories together with
+//! `git replace --graft`.
+//!
+//! Like in Git, replacements are ignored if `GIT_NO_REPLACE_OBJECTS` is set.
+//! Git also doesn't use the commit-graph when there are replacements, and
+//! neither should a walk with a [`Replace`], since the commit-graph has the
+//! original parents.
+
+use std::path::Path;
+
+use gix_hash::{oid, ObjectId};
+use gix_object::bstr::ByteSlice;
+use gix_revwalk::graph::IdMap;
+
+/// How many replacements of replacements to follow, like in Git
+const MAX_REPLACE_DEPTH: usize = 5;
+
+/// Object lookup that finds the replacement of an object instead of the
+/// object itself.
+#[derive(Clone)]
+pub struct Replace<Find> {
+    find: Find,
+    replacements: IdMap<ObjectId>,
+}
+
+impl<Find> Replace<Find>
+where
+    Find: gix_object::Find,
+{
+    /// Replace the first object of each pair with the second when looking
+    /// them up in `find`.
+    pub fn new(
+        find: Find,
+        replacements: impl IntoIterator<Item = (impl Into<ObjectId>, impl Into<ObjectId>)>,
+    ) -> Self {
+        Self {
+            find,
+            replacements: replacements
+                .into_iter()
+                .map(|(original, replacement)| (original.into(), replacement.into()))
+                .collect(),
+        }
+    }
+
+    /// Read the replace refs of the repository whose refs are in
+    /// `common_dir`, both loose and packed. There are none if
+    /// `GIT_NO_REPLACE_OBJECTS` is set.
+    pub fn at(find: Find, common_dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        if std::env::var_os("GIT_NO_REPLACE_OBJECTS").is_some() {
+            return Ok(Self::new(find, Vec::<(ObjectId, ObjectId)>::new()));
+        }
+
+        let common_dir = common_dir.as_ref();
+        let mut replacements = vec![];
+
+        let packed = match std::fs::read(common_dir.join("packed-refs")) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        for line in packed.lines() {
+            let Some((id, name)) = line.split_once_str(" ") else {
+                continue;
+            };
+            let Some(original) = name.strip_prefix(b"refs/replace/") else {
+                continue;
+            };
+            replacements.push((parse_id(original)?, parse_id(id)?));
+        }
+
+        // Loose refs take precedence over packed ones, so they come last
+        let entries = match std::fs::read_dir(common_dir.join("refs/replace")) {
+            Ok(entries) => entries.collect::<Result<Vec<_>, _>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        for entry in entries {
+            let name = entry.file_name();
+            let Some(original) = name.to_str().and_then(|n| parse_id(n.as_bytes()).ok()) else {
+                continue;
+            };
+            let contents = std::fs::read(entry.path())?;
+            replacements.push((original, parse_id(contents.trim())?));
+        }
+
+        Ok(Self::new(find, replacements))
+    }
+
+    /// Whether any objects are replaced.
+    pub fn is_empty(&self) -> bool {
+        self.replacements.is_empty()
+    }
+
+    /// The id of the object to look up instead of the given one, which is the
+    /// same id if it isn't replaced.
+    pub fn replacement(&self, id: &oid) -> ObjectId {
+        let mut id = id.to_owned();
+
+        for _ in 0..MAX_REPLACE_DEPTH {
+            match self.replacements.get(&id) {
+                Some(replacement) => id = *replacement,
+                None => break,
+            }
+        }
+
+        id
+    }
+}
+
+fn parse_id(hex: &[u8]) -> std::io::Result<ObjectId> {
+    ObjectId::from_hex(hex).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+impl<Find> gix_object::Find for Replace<Find>
+where
+    Find: gix_object::Find,
+{
+    fn try_find<'a>(
+        &self,
+        id: &oid,
+        buffer: &'a mut Vec<u8>,
+    ) -> Result<Option<gix_object::Data<'a>>, gix_object::find::Error> {
+        self.find.try_find(&self.replacement(id), buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::scratch::{parse_parents, Scratch};
+    use crate::Builder;
+
+    const TIP: &str = "b282e76b1322e1d26ef002968e1591bd8f22df96";
+    const GRAFT: &str = "3be8265bc3f7d982170bd475be3b82cb140643b9";
+
+    #[test]
+    fn walks_grafted_history() {
+        let scratch = Scratch::clone("replace", &[]);
+
+        // Cut the history off at an older commit with a packed ref, and
+        // stitch the tip directly onto it with a loose one
+        assert!(scratch.git(&["replace", "--graft", GRAFT]).status.success());
+        assert!(scratch.git(&["pack-refs", "--all"]).status.success());
+        assert!(scratch
+            .git(&["replace", "--graft", TIP, GRAFT])
+            .status
+            .success());
+
+        let store = scratch.store();
+        let replace = Replace::at(&store, &scratch.dir).unwrap();
+        assert!(!replace.is_empty());
+
+        let output = scratch.git(&["rev-list", "--parents", "--date-order", TIP]);
+        let git_ids = parse_parents(&output.stdout);
+
+        let tip = ObjectId::from_hex(TIP.as_bytes()).unwrap();
+        let ids = Builder::from_iters(replace, [tip], None::<Vec<ObjectId>>)
+            .build()
+            .unwrap()
+            .map(|info| {
+                let info = info.unwrap();
+                std::iter::once(info.id).chain(info.parent_ids).collect()
+            })
+            .collect::<Vec<Vec<_>>>();
+
+        assert_eq!(ids, git_ids);
+    }
+}
@@ -0,0 +1,100 @@
+//! Scratch repositories for tests that need to change refs or write files
+//! without touching this repository. Available to the tests of other crates
+//! with the `test-support` feature.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use gix_hash::ObjectId;
+
+/// A bare repository in the temporary directory, removed again when dropped.
+pub struct Scratch {
+    /// Where the repository is.
+    pub dir: PathBuf,
+}
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("topo-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        Self { dir }
+    }
+
+    /// Clone this repository, with extra arguments to `git clone` like
+    /// `--depth=3`.
+    pub fn clone(name: &str, args: &[&str]) -> Self {
+        let scratch = Self::new(name);
+
+        let source = std::fs::canonicalize("../.git").expect("find repository");
+        let status = Command::new("git")
+            .args(["clone", "-q", "--bare"])
+            .args(args)
+            .arg(format!("file://{}", source.display()))
+            .arg(&scratch.dir)
+            .status()
+            .expect("able to run git clone");
+        assert!(status.success());
+
+        scratch
+    }
+
+    /// An empty repository that borrows the objects of this one.
+    pub fn borrowing_objects(name: &str) -> Self {
+        let scratch = Self::new(name);
+
+        let status = Command::new("git")
+            .args(["init", "-q", "--bare"])
+            .arg(&scratch.dir)
+            .status()
+            .expect("able to run git init");
+        assert!(status.success());
+
+        let objects = std::fs::canonicalize("../.git/objects").expect("find objects");
+        std::fs::write(
+            scratch.dir.join("objects/info/alternates"),
+            format!("{}\n", objects.display()),
+        )
+        .expect("write alternates");
+
+        scratch
+    }
+
+    /// Run git in the repository, with an identity for commits and reflogs.
+    pub fn git(&self, args: &[&str]) -> Output {
+        Command::new("git")
+            .arg("--git-dir")
+            .arg(&self.dir)
+            .args(["-c", "user.name=A U Thor"])
+            .args(["-c", "user.email=author@example.com"])
+            .args(args)
+            .output()
+            .expect("able to run git")
+    }
+
+    /// The repository's object database.
+    #[cfg(test)]
+    pub(crate) fn store(&self) -> gix_odb::Handle {
+        gix_odb::at(self.dir.join("objects")).expect("find objects")
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// Parse the output of `git rev-list --parents` into lists of commits
+/// followed by their parents.
+pub fn parse_parents(output: &[u8]) -> Vec<Vec<ObjectId>> {
+    std::str::from_utf8(output)
+        .expect("sensible output from git rev-list")
+        .split_terminator('\n')
+        .map(|l| {
+            l.split_ascii_whitespace()
+                .map(|id| ObjectId::from_hex(id.as_bytes()).expect("valid object ids"))
+                .collect()
+        })
+        .collect()
+}
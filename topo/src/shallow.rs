@@ -0,0 +1,167 @@
+//! Walking the history of shallow clones, where the history is cut off at the
+//! commits listed in `.git/shallow`. Their parents are missing, so like Git
+//! they are treated as root commits.
+//!
+//! Git doesn't use the commit-graph in shallow repositories, and neither
+//! should a walk with a [`Shallow`], since the commit-graph would still give
+//! the parents.
+
+use std::path::Path;
+
+use gix_hash::{oid, ObjectId};
+use gix_object::bstr::ByteSlice;
+use gix_revwalk::graph::IdMap;
+
+/// Object lookup that hides the parents of the shallow commits, so that they
+/// look like root commits to anything walking the history.
+pub struct Shallow<Find> {
+    find: Find,
+    commits: IdMap<()>,
+}
+
+impl<Find> Shallow<Find>
+where
+    Find: gix_object::Find,
+{
+    /// Hide the parents of the given commits when looking them up in `find`.
+    pub fn new(find: Find, commits: impl IntoIterator<Item = impl Into<ObjectId>>) -> Self {
+        Self {
+            find,
+            commits: commits.into_iter().map(|id| (id.into(), ())).collect(),
+        }
+    }
+
+    /// Read the shallow commits of the repository in `git_dir`, which there
+    /// are none of if it isn't a shallow clone.
+    pub fn at(find: Find, git_dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let contents = match std::fs::read(git_dir.as_ref().join("shallow")) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        let commits = contents
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| {
+                ObjectId::from_hex(l)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::new(find, commits))
+    }
+
+    /// Whether the repository is a shallow clone.
+    pub fn is_shallow(&self) -> bool {
+        !self.commits.is_empty()
+    }
+
+    /// Whether the parents of the commit are hidden.
+    pub fn contains(&self, id: &oid) -> bool {
+        self.commits.contains_key(id)
+    }
+}
+
+impl<Find> gix_object::Find for Shallow<Find>
+where
+    Find: gix_object::Find,
+{
+    fn try_find<'a>(
+        &self,
+        id: &oid,
+        buffer: &'a mut Vec<u8>,
+    ) -> Result<Option<gix_object::Data<'a>>, gix_object::find::Error> {
+        if !self.contains(id) {
+            return self.find.try_find(id, buffer);
+        }
+
+        let stripped = match self.find.try_find(id, buffer)? {
+            Some(data) if data.kind == gix_object::Kind::Commit => Some(without_parents(data.data)),
+            Some(_) => None,
+            None => return Ok(None),
+        };
+
+        // Only commits are expected to be shallow
+        let Some(stripped) = stripped else {
+            return self.find.try_find(id, buffer);
+        };
+
+        *buffer = stripped;
+
+        Ok(Some(gix_object::Data {
+            kind: gix_object::Kind::Commit,
+            data: buffer.as_slice(),
+        }))
+    }
+}
+
+/// Remove the parent lines from the header of an encoded commit.
+fn without_parents(commit: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(commit.len());
+    let mut in_header = true;
+
+    for line in commit.split_inclusive(|b| *b == b'\n') {
+        if in_header && line == b"\n" {
+            in_header = false;
+        }
+
+        if !(in_header && line.starts_with(b"parent ")) {
+            out.extend_from_slice(line);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::scratch::{parse_parents, Scratch};
+    use crate::Builder;
+
+    #[test]
+    fn strips_only_header_parents() {
+        let commit = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            parent 3be8265bc3f7d982170bd475be3b82cb140643b9\n\
+            parent b282e76b1322e1d26ef002968e1591bd8f22df96\n\
+            author A <a@example.com> 1697000000 +0200\n\
+            committer A <a@example.com> 1697000000 +0200\n\
+            \n\
+            parent in the message\n";
+
+        assert_eq!(
+            without_parents(commit).as_bstr(),
+            b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author A <a@example.com> 1697000000 +0200\n\
+            committer A <a@example.com> 1697000000 +0200\n\
+            \n\
+            parent in the message\n"
+                .as_bstr()
+        );
+    }
+
+    #[test]
+    fn walks_shallow_clone() {
+        let scratch = Scratch::clone("shallow", &["--depth=3"]);
+
+        let store = scratch.store();
+        let shallow = Shallow::at(&store, &scratch.dir).unwrap();
+        assert!(shallow.is_shallow());
+
+        let output = scratch.git(&["rev-list", "--parents", "--date-order", "HEAD"]);
+        let git_ids = parse_parents(&output.stdout);
+
+        let ids = Builder::from_iters(shallow, [git_ids[0][0]], None::<Vec<ObjectId>>)
+            .build()
+            .unwrap()
+            .map(|info| {
+                let info = info.unwrap();
+                std::iter::once(info.id).chain(info.parent_ids).collect()
+            })
+            .collect::<Vec<Vec<_>>>();
+
+        assert_eq!(ids, git_ids);
+    }
+}
@@ -0,0 +1,635 @@
+//! History simplification for walks that are limited to paths, like `git
+//! rev-list -- <paths>`.
+//!
+//! A commit is TREESAME to a parent if none of the paths differ between the
+//! two. Which parents are followed, which commits are shown and what their
+//! parents are rewritten to is decided like in Git's revision.c, see
+//! `try_to_simplify_commit()`, `rewrite_parents()` and `simplify_merges()`.
+
+use std::collections::{HashSet, VecDeque};
+
+use gix_hash::{oid, ObjectId};
+use gix_object::{
+    bstr::{BStr, BString},
+    tree::EntryMode,
+    FindExt,
+};
+use gix_revwalk::graph::IdMap;
+use smallvec::{smallvec, SmallVec};
+
+use super::{
+    bloom, merge_base, Error, GenAndCommitTime, Info, Parents, Simplification, Visit, Walk,
+    WalkFlags,
+};
+
+/// The entries at each of the paths in a commit's tree
+type Entries = SmallVec<[Option<(EntryMode, ObjectId)>; 1]>;
+
+type ParentList = SmallVec<[(ObjectId, GenAndCommitTime); 1]>;
+
+/// State of a walk that is limited to paths.
+pub(crate) struct PathLimit {
+    paths: Vec<BString>,
+    simplification: Simplification,
+    /// Bloom filters to check before comparing trees, and the keys of the
+    /// paths to look up in them
+    changed_paths: Option<(bloom::ChangedPaths, Vec<Vec<bloom::Key>>)>,
+    /// Cache of the entries at the paths, by commit
+    entries: IdMap<Entries>,
+    /// The parents left after simplifying each commit
+    parents: IdMap<ParentList>,
+    /// Whether a merge is TREESAME to each of its parents. Only kept when
+    /// following all parents of merges.
+    treesame: IdMap<SmallVec<[bool; 2]>>,
+}
+
+impl PathLimit {
+    pub(crate) fn new(
+        paths: Vec<BString>,
+        simplification: Simplification,
+        changed_paths: Option<bloom::ChangedPaths>,
+    ) -> Self {
+        let changed_paths = changed_paths.filter(|c| !c.is_empty()).map(|c| {
+            let keys = paths.iter().map(|p| bloom::keys(p.as_ref())).collect();
+            (c, keys)
+        });
+
+        Self {
+            paths,
+            simplification,
+            changed_paths,
+            entries: IdMap::default(),
+            parents: IdMap::default(),
+            treesame: IdMap::default(),
+        }
+    }
+
+    /// The simplified parents of a commit, if it has been simplified.
+    pub(crate) fn parents(&self, id: &oid) -> Option<&ParentList> {
+        self.parents.get(id)
+    }
+
+    fn simplify_history(&self) -> bool {
+        matches!(
+            self.simplification,
+            Simplification::Dense | Simplification::Sparse
+        )
+    }
+
+    fn dense(&self) -> bool {
+        self.simplification != Simplification::Sparse
+    }
+
+    /// Whether the Bloom filters say that the commit definitely didn't change
+    /// any of the paths compared to its first parent.
+    fn unchanged_from_first_parent(&self, id: &oid) -> bool {
+        self.changed_paths
+            .as_ref()
+            .and_then(|(changed_paths, keys)| changed_paths.maybe_changed(id, keys))
+            == Some(false)
+    }
+}
+
+impl<Find, Predicate, Prune> Walk<Find, Predicate, Prune>
+where
+    Find: gix_object::Find,
+    Prune: FnMut(&oid) -> Visit,
+{
+    fn limit(&self) -> &PathLimit {
+        self.path_limit
+            .as_ref()
+            .expect("only used in walks limited to paths")
+    }
+
+    fn limit_mut(&mut self) -> &mut PathLimit {
+        self.path_limit
+            .as_mut()
+            .expect("only used in walks limited to paths")
+    }
+
+    /// Compare the commit to its parents and mark it as TREESAME if
+    /// appropriate. Returns the parents to follow, which is only the first
+    /// parent it's TREESAME to, unless following all parents of merges.
+    pub(crate) fn try_to_simplify_commit(&mut self, id: &oid) -> Result<ParentList, Error> {
+        let all = self.collect_all_parents(id)?;
+
+        if all.is_empty() {
+            if self.path_entries(id)?.iter().all(Option::is_none) {
+                self.set_treesame(id, true)?;
+            }
+            return Ok(self.store_parents(id, all));
+        }
+
+        // Without dense history, a commit that isn't a merge is always shown
+        if !self.limit().dense() && all.len() == 1 {
+            return Ok(self.store_parents(id, all));
+        }
+
+        let simplify_history = self.limit().simplify_history();
+        let first_parent_only = matches!(self.parents, Parents::First);
+
+        let mut relevant_parents = 0;
+        let mut relevant_change = false;
+        let mut irrelevant_change = false;
+        let mut treesame = SmallVec::<[bool; 2]>::new();
+
+        for (n, (parent_id, _)) in all.iter().enumerate() {
+            let relevant = self.is_relevant(parent_id);
+            if relevant {
+                relevant_parents += 1;
+            }
+
+            // Don't let a side branch that brought everything derail us
+            if n == 1 && first_parent_only {
+                break;
+            }
+
+            if self.treesame_to(id, n, parent_id)? {
+                if !simplify_history || !relevant {
+                    treesame.push(true);
+                    continue;
+                }
+
+                self.set_treesame(id, true)?;
+                return Ok(self.store_parents(id, smallvec![all[n]]));
+            }
+
+            if relevant {
+                relevant_change = true;
+            } else {
+                irrelevant_change = true;
+            }
+            treesame.push(false);
+        }
+
+        // Irrelevant parents can't make a merge !TREESAME if it has relevant
+        // ones
+        let changed = if relevant_parents > 0 {
+            relevant_change
+        } else {
+            irrelevant_change
+        };
+        self.set_treesame(id, !changed)?;
+
+        if all.len() > 1 && !simplify_history && !first_parent_only {
+            self.limit_mut().treesame.insert(id.to_owned(), treesame);
+        }
+
+        Ok(self.store_parents(id, all))
+    }
+
+    /// Decide whether to show a commit, and rewrite its parents to the nearest
+    /// shown ancestors.
+    pub(crate) fn simplify_commit(&mut self, mut info: Info) -> Result<Option<Info>, Error> {
+        if self.path_limit.is_none() {
+            return Ok(Some(info));
+        }
+
+        let state = *self.states.get(&info.id).ok_or(Error::MissingState)?;
+        let parents = self
+            .simplified_parent_ids(&info.id)
+            .unwrap_or_else(|| info.parent_ids.clone());
+
+        // Keep merges between relevant commits to tie the history together
+        if state.contains(WalkFlags::TreeSame)
+            && parents.iter().filter(|id| self.is_relevant(id)).count() < 2
+        {
+            return Ok(None);
+        }
+
+        info.parent_ids = self.rewrite_parents(&parents)?;
+
+        Ok(Some(info))
+    }
+
+    fn rewrite_parents(&mut self, parents: &[ObjectId]) -> Result<SmallVec<[ObjectId; 1]>, Error> {
+        let mut rewritten = SmallVec::new();
+
+        for id in parents {
+            if let Some(id) = self.rewrite_one(*id)? {
+                if !rewritten.contains(&id) {
+                    rewritten.push(id);
+                }
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Follow TREESAME commits until one that is shown. Returns `None` if
+    /// that leads to a root commit that isn't shown.
+    fn rewrite_one(&mut self, mut id: ObjectId) -> Result<Option<ObjectId>, Error> {
+        loop {
+            let state = *self.states.get(&id).ok_or(Error::MissingState)?;
+
+            if !state.contains(WalkFlags::Added) {
+                let parents = self.collect_parents(&id)?;
+                self.process_parents(&id, parents)?;
+            }
+
+            let state = *self.states.get(&id).ok_or(Error::MissingState)?;
+
+            if state.contains(WalkFlags::Uninteresting) || !state.contains(WalkFlags::TreeSame) {
+                return Ok(Some(id));
+            }
+
+            let parents = self.simplified_parent_ids(&id).unwrap_or_default();
+
+            if parents.is_empty() {
+                return Ok(None);
+            }
+
+            match self.one_relevant_parent(&parents) {
+                Some(parent_id) => id = parent_id,
+                None => return Ok(Some(id)),
+            }
+        }
+    }
+
+    /// Simplify away merges that don't bring anything interesting to the
+    /// rest of the history, given the whole walk.
+    pub(crate) fn simplify_merges(
+        &mut self,
+        commits: VecDeque<Info>,
+    ) -> Result<VecDeque<Info>, Error> {
+        let mut simplified = IdMap::<ObjectId>::default();
+
+        // Oldest first, so most parents are ready before their children
+        let mut yet_to_do = commits.iter().rev().map(|i| i.id).collect::<Vec<_>>();
+
+        while !yet_to_do.is_empty() {
+            for id in std::mem::take(&mut yet_to_do) {
+                self.simplify_one(id, &mut simplified, &mut yet_to_do)?;
+            }
+        }
+
+        Ok(commits
+            .into_iter()
+            .filter(|info| simplified.get(&info.id) == Some(&info.id))
+            .collect())
+    }
+
+    fn simplify_one(
+        &mut self,
+        id: ObjectId,
+        simplified: &mut IdMap<ObjectId>,
+        yet_to_do: &mut Vec<ObjectId>,
+    ) -> Result<(), Error> {
+        if simplified.contains_key(&id) {
+            return Ok(());
+        }
+
+        let state = self.states.get(&id).copied().unwrap_or_default();
+        let mut parents = self.simplified_parent_ids(&id).unwrap_or_default();
+
+        // Uninteresting and root commits simplify to themselves
+        if state.contains(WalkFlags::Uninteresting) || parents.is_empty() {
+            simplified.insert(id, id);
+            return Ok(());
+        }
+
+        let first_parent_only = matches!(self.parents, Parents::First);
+        let considered = if first_parent_only { 1 } else { parents.len() };
+
+        // We need to know what our parents simplify to first
+        let mut not_ready = 0;
+        for parent_id in &parents[..considered] {
+            if !simplified.contains_key(parent_id) {
+                yet_to_do.push(*parent_id);
+                not_ready += 1;
+            }
+        }
+
+        if not_ready > 0 {
+            yet_to_do.push(id);
+            return Ok(());
+        }
+
+        for parent_id in &mut parents[..considered] {
+            *parent_id = simplified[&*parent_id];
+        }
+        self.set_parent_ids(&id, &parents)?;
+
+        let mut count = if first_parent_only {
+            1
+        } else {
+            let mut seen = HashSet::new();
+            let keep = parents.iter().map(|p| seen.insert(*p)).collect::<Vec<_>>();
+            self.remove_parents(&id, &keep)?
+        };
+
+        // A side branch that doesn't touch the paths is rewritten to the merge
+        // base, or to a root commit that doesn't have them
+        if count > 1 {
+            let mut marked = self.redundant_parents(&id)?;
+            marked.extend(self.treesame_root_parents(&id));
+
+            if let Some(parent_id) = self.only_marked_treesame_parent(&id, &marked) {
+                marked.remove(&parent_id);
+            }
+
+            if !marked.is_empty() {
+                let parents = self.simplified_parent_ids(&id).unwrap_or_default();
+                let keep = parents
+                    .iter()
+                    .map(|p| !marked.contains(p))
+                    .collect::<Vec<_>>();
+                count = self.remove_parents(&id, &keep)?;
+
+                // Removing parents can only make a commit TREESAME
+                if !self.states[&id].contains(WalkFlags::TreeSame) {
+                    self.update_treesame(&id)?;
+                }
+            }
+        }
+
+        let state = self.states.get(&id).copied().unwrap_or_default();
+        let parents = self.simplified_parent_ids(&id).unwrap_or_default();
+
+        let target = if count == 0
+            || state.contains(WalkFlags::Uninteresting)
+            || !state.contains(WalkFlags::TreeSame)
+        {
+            id
+        } else {
+            match self.one_relevant_parent(&parents) {
+                Some(parent_id) => simplified.get(&parent_id).copied().unwrap_or(parent_id),
+                None => id,
+            }
+        };
+
+        simplified.insert(id, target);
+
+        Ok(())
+    }
+
+    /// Parents that are ancestors of other parents
+    fn redundant_parents(&mut self, id: &oid) -> Result<HashSet<ObjectId>, Error> {
+        let parents = self.simplified_parent_ids(id).unwrap_or_default();
+        let mut redundant = HashSet::new();
+
+        for one in &parents {
+            for other in parents.iter().filter(|other| *other != one) {
+                let bases = merge_base::merge_base_candidates(
+                    self.commit_graph.as_ref(),
+                    &self.find,
+                    one,
+                    &[*other],
+                    &mut self.buf,
+                )?;
+
+                if bases.contains(one) {
+                    redundant.insert(*one);
+                    break;
+                }
+            }
+        }
+
+        Ok(redundant)
+    }
+
+    /// Parents that are root commits without any of the paths
+    fn treesame_root_parents(&self, id: &oid) -> Vec<ObjectId> {
+        self.simplified_parent_ids(id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|parent_id| {
+                self.limit()
+                    .parents(parent_id)
+                    .is_some_and(|p| p.is_empty())
+                    && self
+                        .states
+                        .get(parent_id)
+                        .is_some_and(|s| s.contains(WalkFlags::TreeSame))
+            })
+            .collect()
+    }
+
+    /// If the commit is only TREESAME to parents that are about to be
+    /// removed, return the first of them so it can be kept. That is the one
+    /// the default simplification would have followed.
+    fn only_marked_treesame_parent(
+        &self,
+        id: &oid,
+        marked: &HashSet<ObjectId>,
+    ) -> Option<ObjectId> {
+        let treesame = self.limit().treesame.get(id)?;
+        let parents = self.simplified_parent_ids(id)?;
+        let mut first_marked = None;
+
+        for (parent_id, same) in parents.iter().zip(treesame) {
+            if !same {
+                continue;
+            }
+
+            if !marked.contains(parent_id) {
+                return None;
+            }
+
+            first_marked.get_or_insert(*parent_id);
+        }
+
+        first_marked
+    }
+
+    /// Remove the parents not marked to keep, along with whether the commit is
+    /// TREESAME to them. Returns the number of parents left.
+    fn remove_parents(&mut self, id: &oid, keep: &[bool]) -> Result<usize, Error> {
+        let parents = self.simplified_parent_ids(id).unwrap_or_default();
+        let kept = parents
+            .iter()
+            .zip(keep)
+            .filter(|(_, keep)| **keep)
+            .map(|(id, _)| *id)
+            .collect::<SmallVec<[ObjectId; 1]>>();
+
+        self.set_parent_ids(id, &kept)?;
+
+        let dense = self.limit().dense();
+        if let Some(mut treesame) = self.limit_mut().treesame.remove(id) {
+            let mut n = 0;
+            treesame.retain(|_| {
+                n += 1;
+                keep[n - 1]
+            });
+
+            // A commit that is no longer a merge is simply TREESAME or not
+            match treesame.len() {
+                0 => (),
+                1 => self.set_treesame(id, treesame[0] && dense)?,
+                _ => {
+                    self.limit_mut().treesame.insert(id.to_owned(), treesame);
+                }
+            }
+        }
+
+        Ok(kept.len())
+    }
+
+    fn update_treesame(&mut self, id: &oid) -> Result<(), Error> {
+        let parents = self.simplified_parent_ids(id).unwrap_or_default();
+
+        let Some(treesame) = self.limit().treesame.get(id).cloned() else {
+            return Ok(());
+        };
+
+        if parents.len() < 2 {
+            return Ok(());
+        }
+
+        let mut relevant_parents = 0;
+        let mut relevant_change = false;
+        let mut irrelevant_change = false;
+
+        for (parent_id, same) in parents.iter().zip(treesame) {
+            if self.is_relevant(parent_id) {
+                relevant_change |= !same;
+                relevant_parents += 1;
+            } else {
+                irrelevant_change |= !same;
+            }
+        }
+
+        let changed = if relevant_parents > 0 {
+            relevant_change
+        } else {
+            irrelevant_change
+        };
+
+        self.set_treesame(id, !changed)
+    }
+
+    /// The only parent that is relevant, or the first parent if there is only
+    /// one or we only follow the first parent.
+    fn one_relevant_parent(&self, parents: &[ObjectId]) -> Option<ObjectId> {
+        if parents.len() == 1 || matches!(self.parents, Parents::First) {
+            return parents.first().copied();
+        }
+
+        let mut relevant = parents.iter().filter(|id| self.is_relevant(id));
+
+        match (relevant.next(), relevant.next()) {
+            (Some(id), None) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Uninteresting commits are irrelevant, except the ends themselves
+    fn is_relevant(&self, id: &oid) -> bool {
+        match self.states.get(id) {
+            Some(s) => !s.contains(WalkFlags::Uninteresting) || s.contains(WalkFlags::Bottom),
+            None => true,
+        }
+    }
+
+    fn set_treesame(&mut self, id: &oid, treesame: bool) -> Result<(), Error> {
+        let state = self.states.get_mut(id).ok_or(Error::MissingState)?;
+
+        if treesame {
+            *state |= WalkFlags::TreeSame;
+        } else {
+            *state -= WalkFlags::TreeSame;
+        }
+
+        Ok(())
+    }
+
+    fn store_parents(&mut self, id: &oid, parents: ParentList) -> ParentList {
+        self.limit_mut()
+            .parents
+            .insert(id.to_owned(), parents.clone());
+        parents
+    }
+
+    fn simplified_parent_ids(&self, id: &oid) -> Option<SmallVec<[ObjectId; 1]>> {
+        self.limit()
+            .parents(id)
+            .map(|parents| parents.iter().map(|(id, _)| *id).collect())
+    }
+
+    fn set_parent_ids(&mut self, id: &oid, parent_ids: &[ObjectId]) -> Result<(), Error> {
+        let mut parents = ParentList::new();
+
+        for parent_id in parent_ids {
+            parents.push((*parent_id, self.gen_and_commit_time(parent_id)?));
+        }
+
+        self.store_parents(id, parents);
+
+        Ok(())
+    }
+
+    /// Whether the commit is TREESAME to its nth parent. The Bloom filters
+    /// only cover the first parent, the others need the trees compared.
+    fn treesame_to(&mut self, id: &oid, n: usize, parent_id: &oid) -> Result<bool, Error> {
+        if n == 0 && self.limit().unchanged_from_first_parent(id) {
+            return Ok(true);
+        }
+
+        Ok(self.path_entries(parent_id)? == self.path_entries(id)?)
+    }
+
+    fn path_entries(&mut self, id: &oid) -> Result<Entries, Error> {
+        if let Some(entries) = self.limit().entries.get(id) {
+            return Ok(entries.clone());
+        }
+
+        let tree_id = self.commit_data(id)?.tree_id;
+
+        let limit = self
+            .path_limit
+            .as_ref()
+            .expect("only used in walks limited to paths");
+
+        let mut entries = Entries::new();
+        for path in &limit.paths {
+            entries.push(lookup_path(
+                &self.find,
+                tree_id,
+                path.as_ref(),
+                &mut self.buf,
+            )?);
+        }
+
+        self.limit_mut()
+            .entries
+            .insert(id.to_owned(), entries.clone());
+
+        Ok(entries)
+    }
+}
+
+/// Find the entry at `path` in the tree with the given id.
+fn lookup_path<Find>(
+    f: &Find,
+    tree_id: ObjectId,
+    path: &BStr,
+    buf: &mut Vec<u8>,
+) -> Result<Option<(EntryMode, ObjectId)>, Error>
+where
+    Find: gix_object::Find,
+{
+    let mut entry = (EntryMode::Tree, tree_id);
+
+    for component in path.split(|b| *b == b'/').filter(|c| !c.is_empty()) {
+        if !entry.0.is_tree() {
+            return Ok(None);
+        }
+
+        let mut found = None;
+        for e in f.find_tree_iter(&entry.1, buf)? {
+            let e = e?;
+            if e.filename == component {
+                found = Some((e.mode, e.oid.to_owned()));
+                break;
+            }
+        }
+
+        match found {
+            Some(e) => entry = e,
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(entry))
+}
@@ -0,0 +1,138 @@
+//! A simple topological walk that collects the whole range up front, like
+//! `sort_in_topological_order()` in Git. It doesn't need generation numbers,
+//! but always visits every commit in the range, which makes it useful as a
+//! reference for [`Walk`](crate::Walk).
+
+use std::cmp::Reverse;
+
+use gix_hash::ObjectId;
+use gix_revwalk::{graph::IdMap, PriorityQueue};
+use smallvec::SmallVec;
+
+use super::{collect_parents, find, get_gen_and_commit_time, Error, Info};
+
+/// A walk in `git rev-list --date-order` order, that collects all commits
+/// reachable from the tips but not from the ends before returning the first.
+pub struct Walk2 {
+    /// In reverse order, to pop from the end
+    list: Vec<Info>,
+}
+
+impl Walk2 {
+    /// Create a new Walk2 that walks the given repository, starting at the
+    /// tips and ending at the ends. Like `git rev-list --date-order ^ends...
+    /// tips...`
+    pub fn new<Find>(
+        f: Find,
+        tips: impl IntoIterator<Item = impl Into<ObjectId>>,
+        ends: impl IntoIterator<Item = impl Into<ObjectId>>,
+    ) -> Result<Self, Error>
+    where
+        Find: gix_object::Find,
+    {
+        let mut buf = vec![];
+
+        let mut uninteresting = IdMap::<()>::default();
+        let mut stack = ends.into_iter().map(Into::into).collect::<Vec<_>>();
+        while let Some(id) = stack.pop() {
+            if uninteresting.insert(id, ()).is_none() {
+                let parents = collect_parents(None, &f, &id, false, &mut buf)?;
+                stack.extend(parents.into_iter().map(|(id, _)| id));
+            }
+        }
+
+        // Commit time and parents of every commit in the range, in the order
+        // they're found
+        let mut all = Vec::<(i64, Info)>::new();
+        let mut seen = IdMap::<()>::default();
+        let mut stack = tips.into_iter().map(Into::into).collect::<Vec<_>>();
+        stack.reverse();
+
+        while let Some(id) = stack.pop() {
+            if uninteresting.contains_key(&id) || seen.insert(id, ()).is_some() {
+                continue;
+            }
+
+            let (_, time) = get_gen_and_commit_time(find(None, &f, &id, &mut buf)?)?;
+            let parent_ids = collect_parents(None, &f, &id, false, &mut buf)?
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<SmallVec<_>>();
+
+            stack.extend(parent_ids.iter().rev().copied());
+            all.push((
+                time,
+                Info {
+                    id,
+                    parent_ids,
+                    side: None,
+                    boundary: false,
+                    details: None,
+                },
+            ));
+        }
+
+        // Newest first, then in the order they were found
+        all.sort_by_key(|(time, _)| Reverse(*time));
+
+        // Like in Git, the indegree of a commit in the range starts at 1, and
+        // it's ready to be returned when it's back at 1
+        let mut indegrees = IdMap::from_iter(all.iter().map(|(_, info)| (info.id, 1)));
+        for (_, info) in &all {
+            for parent_id in &info.parent_ids {
+                if let Some(i) = indegrees.get_mut(parent_id) {
+                    *i += 1;
+                }
+            }
+        }
+
+        let mut infos = IdMap::default();
+        let mut queue = PriorityQueue::<(i64, Reverse<usize>), ObjectId>::new();
+        let mut counter = 0;
+
+        for (time, info) in all {
+            if indegrees[&info.id] == 1 {
+                queue.insert((time, Reverse(counter)), info.id);
+                counter += 1;
+            }
+            infos.insert(info.id, (time, info));
+        }
+
+        let mut list = vec![];
+
+        while let Some((_, id)) = queue.pop() {
+            let (_, info) = infos
+                .remove(&id)
+                .expect("all queued commits are in the range");
+
+            for parent_id in &info.parent_ids {
+                // Parents outside of the range don't have an indegree
+                let Some(i) = indegrees.get_mut(parent_id) else {
+                    continue;
+                };
+
+                *i -= 1;
+
+                if *i == 1 {
+                    let time = infos[parent_id].0;
+                    queue.insert((time, Reverse(counter)), *parent_id);
+                    counter += 1;
+                }
+            }
+
+            list.push(info);
+        }
+
+        list.reverse();
+
+        Ok(Self { list })
+    }
+}
+
+impl Iterator for Walk2 {
+    type Item = Result<Info, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.list.pop().map(Ok)
+    }
+}